@@ -1,19 +1,217 @@
 import * as plug from "jsr:@denosaurs/plug@1.0.6";
 import metadata from "../deno.json" with { type: "json" };
 
+// Define the C struct for Deno FFI
+export const CAPTURED_IMAGE_STRUCT_DEF = {
+  struct: [
+    "pointer", // data: *mut u8
+    "usize", // len: size_t
+    "u32", // width: c_uint
+    "u32", // height: c_uint
+    "usize", // stride: size_t
+  ],
+} as const;
+
+export const CAPTURE_OPTIONS_STRUCT_DEF = {
+  struct: [
+    "usize", // struct_size: size_t
+    "usize", // row_alignment: size_t
+    "u32", // output_scale: c_uint
+  ],
+} as const;
+
+export const ENCODE_OPTIONS_STRUCT_DEF = {
+  struct: [
+    "usize", // struct_size: size_t
+    "i32", // format: ImageFormat
+    "i32", // quality: c_int
+    "i32", // atomic_write: c_int
+    "i32", // fsync: c_int
+    "i32", // collision: CollisionPolicy
+  ],
+} as const;
+
+export const GALLERY_ENTRY_STRUCT_DEF = {
+  struct: [
+    "u64", // id: CaptureHandle
+    "u64", // timestamp_ms: u64
+    "i32", // target.kind: TargetKind
+    "u32", // target.id: c_uint
+    "u32", // width: c_uint
+    "u32", // height: c_uint
+  ],
+} as const;
+
+export const GALLERY_QUERY_STRUCT_DEF = {
+  struct: [
+    "usize", // struct_size: size_t
+    "u64", // since_ms: u64
+    "u64", // until_ms: u64
+    "i32", // match_target: c_int
+    "i32", // target.kind: TargetKind
+    "u32", // target.id: c_uint
+  ],
+} as const;
+
+export const CAPTURE_TARGET_STRUCT_DEF = {
+  struct: [
+    "i32", // kind: TargetKind
+    "u32", // id: c_uint
+  ],
+} as const;
+
+export const CAPTURE_RECT_STRUCT_DEF = {
+  struct: [
+    "i32", // x: c_int
+    "i32", // y: c_int
+    "u32", // width: c_uint
+    "u32", // height: c_uint
+  ],
+} as const;
+
+export const COMPOSITE_LAYER_STRUCT_DEF = {
+  struct: [
+    "usize", // struct_size: size_t
+    CAPTURE_TARGET_STRUCT_DEF, // target: CaptureTarget
+    CAPTURE_RECT_STRUCT_DEF, // source: CaptureRect
+    "i32", // x: c_int
+    "i32", // y: c_int
+    "u32", // scale: c_uint (percent)
+    "i32", // z: c_int
+    "u32", // opacity: c_uint (percent)
+    "u32", // corner_radius: c_uint
+    "u32", // border_width: c_uint
+    "u32", // border_color: 0xRRGGBBAA
+  ],
+} as const;
+
+export const COMPOSITE_OPTIONS_STRUCT_DEF = {
+  struct: [
+    "usize", // struct_size: size_t
+    "u32", // width: c_uint
+    "u32", // height: c_uint
+    "u32", // max_fps: c_uint
+    "u32", // background: 0xRRGGBBAA
+  ],
+} as const;
+
+export const IMAGE_VIEW_STRUCT_DEF = {
+  struct: [
+    "pointer", // data: *const u8
+    "usize", // stride: size_t
+    "u32", // width: c_uint
+    "u32", // height: c_uint
+    "u64", // handle: CaptureHandle
+  ],
+} as const;
+
+export const LATENCY_STATS_STRUCT_DEF = {
+  struct: [
+    "u32", // samples: c_uint
+    "u32", // timeouts: c_uint
+    "f64", // min_ms
+    "f64", // max_ms
+    "f64", // mean_ms
+    "f64", // median_ms
+    "f64", // p95_ms
+    "f64", // stddev_ms
+  ],
+} as const;
+
+export const PATCH_SAMPLE_STRUCT_DEF = {
+  struct: [
+    "u32", // samples: c_uint
+    "u32", // pixels: c_uint
+    "f64", // r
+    "f64", // g
+    "f64", // b
+    "f64", // luminance
+    "f64", // luminance_stddev
+    "bool", // profile_applied
+  ],
+} as const;
+
+export const ARCHIVE_OPTIONS_STRUCT_DEF = {
+  struct: [
+    "usize", // struct_size: size_t
+    "i32", // level: c_int
+    "u32", // keyframe_interval: c_uint
+    "i32", // collision: CollisionPolicy
+    "i32", // fsync: c_int
+  ],
+} as const;
+
+export const ARCHIVE_STATS_STRUCT_DEF = {
+  struct: [
+    "u64", // frames
+    "u64", // keyframes
+    "u64", // raw_bytes
+    "u64", // written_bytes
+    "u64", // duration_ms
+    "u64", // bytes_per_hour
+  ],
+} as const;
+
+export const ARCHIVE_ESTIMATE_STRUCT_DEF = {
+  struct: [
+    "u64", // raw_bytes_per_frame
+    "u64", // keyframe_bytes
+    "u64", // delta_bytes
+    "u64", // bytes_per_hour
+  ],
+} as const;
+
+export const HEATMAP_INFO_STRUCT_DEF = {
+  struct: [
+    "u32", // width: c_uint
+    "u32", // height: c_uint
+    "f64", // cell_size
+    "u64", // samples
+    "u32", // interval_ms: c_uint
+  ],
+} as const;
+
+export const STREAM_INFO_STRUCT_DEF = {
+  struct: [
+    "u32", // width: c_uint
+    "u32", // height: c_uint
+    "i32", // pixel_format: PixelFormat
+    "u32", // max_fps: c_uint
+    "i32", // cursor: CursorMode
+    "i32", // color_space: ColorSpace
+  ],
+} as const;
+
+export const STREAM_RESOURCE_STATS_STRUCT_DEF = {
+  struct: [
+    "u64", // held_bytes
+    "u64", // held_frames
+    "u64", // frames_delivered
+    "u64", // conversion_cpu_us
+    "u64", // encode_cpu_us
+  ],
+} as const;
+
+export const FRAME_CALLBACK_DEF = {
+  parameters: [CAPTURED_IMAGE_STRUCT_DEF, "pointer"], // frame, user_data
+  result: "void",
+} as const;
+
+export const EVENT_CALLBACK_DEF = {
+  parameters: ["pointer", "pointer"], // *const CaptureEvent, user_data
+  result: "void",
+} as const;
+
+export const WRITE_CALLBACK_DEF = {
+  parameters: ["pointer", "usize", "pointer"], // data, len, user_data
+  result: "i32", // 0 to continue
+} as const;
+
 export const library = await instantiate();
 
 async function instantiate() {
-  // Define the C struct for Deno FFI
-  const CAPTURED_IMAGE_STRUCT_DEF = {
-    struct: [
-      "pointer", // data: *mut u8
-      "usize", // len: size_t
-      "u32", // width: c_uint
-      "u32", // height: c_uint
-    ],
-  } as const;
-
+  // Deno receives structs by value, so the `*_out` variants of struct-returning
+  // functions (src/out.rs), meant for runtimes that can't, aren't bound here.
   const symbols = {
     capture_monitor_count: {
       parameters: [],
@@ -27,6 +225,28 @@ async function instantiate() {
       parameters: ["usize"],
       result: "u32", // c_uint
     },
+    capture_window_count: {
+      parameters: [],
+      result: "usize",
+    },
+    capture_window_id: {
+      parameters: ["usize"],
+      result: "u32", // c_uint
+    },
+    capture_window_title: {
+      parameters: ["usize"],
+      result: "pointer", // *mut c_char
+    },
+    capture_highlight_target: {
+      parameters: [CAPTURE_TARGET_STRUCT_DEF, "u32", "u32"], // target, 0xRRGGBBAA, duration_ms
+      result: "i32", // ErrorCode
+    },
+    capture_delayed: {
+      // target, delay_secs, show_countdown, cancel token
+      parameters: [CAPTURE_TARGET_STRUCT_DEF, "u32", "bool", "u64"],
+      result: CAPTURED_IMAGE_STRUCT_DEF,
+      nonblocking: true, // Blocks for the whole delay
+    },
     capture_monitor_width: {
       parameters: ["usize"],
       result: "u32", // c_uint
@@ -40,6 +260,203 @@ async function instantiate() {
       result: CAPTURED_IMAGE_STRUCT_DEF, // Our struct definition
       nonblocking: true, // Capture can take time
     },
+    capture_window_image_ex: {
+      // window_id, *const CaptureOptions, *const CaptureRect frame (NULL to leave in place)
+      parameters: ["u32", "buffer", "buffer"],
+      result: CAPTURED_IMAGE_STRUCT_DEF,
+      nonblocking: true, // Waits for the window manager when placing the window
+    },
+    capture_monitor_image_ex: {
+      parameters: ["usize", "buffer"], // index, *const CaptureOptions
+      result: CAPTURED_IMAGE_STRUCT_DEF,
+      nonblocking: true, // Capture can take time
+    },
+    capture_options_default: {
+      parameters: [],
+      result: CAPTURE_OPTIONS_STRUCT_DEF,
+    },
+    capture_encode_options_default: {
+      parameters: [],
+      result: ENCODE_OPTIONS_STRUCT_DEF,
+    },
+    capture_monitor_save: {
+      parameters: ["usize", "buffer", "buffer"], // index, path, *const EncodeOptions
+      result: "i32", // ErrorCode
+      nonblocking: true, // Capture and encode can take time
+    },
+    capture_monitor_save_as: {
+      parameters: ["usize", "buffer", "buffer"], // index, path template, *const EncodeOptions
+      result: "pointer", // *mut c_char, the path written
+      nonblocking: true, // Capture and encode can take time
+    },
+    capture_snap: {
+      parameters: ["buffer"], // NUL-terminated JSON spec
+      result: "pointer", // *mut c_char, JSON result
+      nonblocking: true, // Captures and encodes
+    },
+    capture_monitor_save_fd: {
+      // index, CaptureFd (a HANDLE on Windows), *const EncodeOptions
+      parameters: [
+        "usize",
+        Deno.build.os === "windows" ? "pointer" : "i32",
+        "buffer",
+      ],
+      result: "i32", // ErrorCode
+      nonblocking: true, // Capture and encode can take time
+    },
+    capture_window_save_fd: {
+      // window ID, CaptureFd (a HANDLE on Windows), *const EncodeOptions
+      parameters: [
+        "u32",
+        Deno.build.os === "windows" ? "pointer" : "i32",
+        "buffer",
+      ],
+      result: "i32", // ErrorCode
+      nonblocking: true, // Capture and encode can take time
+    },
+    capture_monitor_encode: {
+      // index, *const EncodeOptions, write callback, user_data
+      parameters: ["usize", "buffer", "function", "pointer"],
+      result: "i32", // ErrorCode
+      nonblocking: true, // Capture and encode can take time
+    },
+    capture_sample_patch: {
+      // index, CaptureRect, samples, interval_ms, cancel token
+      parameters: ["usize", CAPTURE_RECT_STRUCT_DEF, "u32", "u32", "u64"],
+      result: PATCH_SAMPLE_STRUCT_DEF,
+      nonblocking: true, // Blocks for samples * interval_ms
+    },
+    capture_measure_latency: {
+      parameters: ["usize", "u32"],
+      result: LATENCY_STATS_STRUCT_DEF,
+      nonblocking: true, // Takes several frames per sample
+    },
+    capture_measure_latency_ex: {
+      parameters: ["usize", "u32", "u64"], // index, samples, cancel token
+      result: LATENCY_STATS_STRUCT_DEF,
+      nonblocking: true, // Takes several frames per sample
+    },
+    capture_stream_start: {
+      parameters: ["usize", "u32", "function", "pointer"],
+      result: "u64", // CaptureHandle
+    },
+    capture_stream_start_ex: {
+      // index, max_fps, on_frame, user_data, cancel token
+      parameters: ["usize", "u32", "function", "pointer", "u64"],
+      result: "u64", // CaptureHandle
+    },
+    capture_region_watch: {
+      // rect, interval_ms, threshold, on_change, user_data, cancel token
+      parameters: [
+        CAPTURE_RECT_STRUCT_DEF,
+        "u32",
+        "f64",
+        "function",
+        "pointer",
+        "u64",
+      ],
+      result: "u64", // CaptureHandle
+    },
+    capture_region_watch_stop: {
+      parameters: ["u64"],
+      result: "i32", // ErrorCode
+      nonblocking: true, // Joins the watch thread
+    },
+    capture_heatmap_start: {
+      parameters: ["usize", "u32", "u32", "u64"], // index, interval_ms, max_side, cancel token
+      result: "u64", // CaptureHandle, 0 on error
+      nonblocking: true, // Takes the first capture
+    },
+    capture_heatmap_get: {
+      parameters: ["u64"],
+      result: CAPTURED_IMAGE_STRUCT_DEF,
+    },
+    capture_heatmap_info: {
+      parameters: ["u64"],
+      result: HEATMAP_INFO_STRUCT_DEF,
+    },
+    capture_heatmap_stop: {
+      parameters: ["u64"],
+      result: "i32", // ErrorCode
+      nonblocking: true, // Joins the sampling thread
+    },
+    capture_composite_layer_default: {
+      parameters: [],
+      result: COMPOSITE_LAYER_STRUCT_DEF,
+    },
+    capture_composite_options_default: {
+      parameters: [],
+      result: COMPOSITE_OPTIONS_STRUCT_DEF,
+    },
+    capture_composite_start: {
+      // layers, count, options, on_frame, user_data, cancel token
+      parameters: ["buffer", "usize", "buffer", "function", "pointer", "u64"],
+      result: "u64", // CaptureHandle
+      nonblocking: true, // Captures the first frame of every layer
+    },
+    capture_composite_stop: {
+      parameters: ["u64"],
+      result: "i32", // ErrorCode
+      nonblocking: true, // Joins the recording thread
+    },
+    capture_cancel_token_new: {
+      parameters: [],
+      result: "u64", // CaptureHandle
+    },
+    capture_cancel_token_cancel: {
+      parameters: ["u64"],
+      result: "i32", // ErrorCode
+    },
+    capture_cancel_token_is_cancelled: {
+      parameters: ["u64"],
+      result: "bool",
+    },
+    capture_cancel_token_free: {
+      parameters: ["u64"],
+      result: "i32", // ErrorCode
+    },
+    capture_stream_info: {
+      parameters: ["u64"],
+      result: STREAM_INFO_STRUCT_DEF,
+    },
+    capture_stream_resource_stats: {
+      parameters: ["u64"],
+      result: STREAM_RESOURCE_STATS_STRUCT_DEF,
+    },
+    capture_stream_stop: {
+      parameters: ["u64"],
+      result: "i32", // ErrorCode
+      // Waits for the stream thread, which may be waiting on a JS frame callback
+      nonblocking: true,
+    },
+    capture_stream_poll_frame: {
+      parameters: ["u64", "u32"], // handle, timeout_ms
+      result: CAPTURED_IMAGE_STRUCT_DEF,
+      nonblocking: true, // Waits up to the timeout
+    },
+    capture_set_event_callback: {
+      parameters: ["function", "pointer"],
+      result: "void",
+    },
+    capture_callback_register: {
+      parameters: ["i32", "pointer", "pointer"], // CallbackKind, function, user_data
+      result: "u32", // callback ID, 0 on error
+    },
+    capture_callback_unregister: {
+      parameters: ["u32"],
+      result: "i32", // ErrorCode
+      // Waits for calls in progress, which need the JS thread to finish.
+      nonblocking: true,
+    },
+    capture_callback_trampoline: {
+      parameters: ["i32"], // CallbackKind
+      result: "pointer",
+    },
+    capture_events_poll: {
+      parameters: ["buffer", "usize", "u32"], // *mut CaptureEvent, max, timeout_ms
+      result: "usize",
+      nonblocking: true, // Waits up to the timeout
+    },
     capture_free_string: {
       parameters: ["pointer"], // *mut c_char
       result: "void",
@@ -48,10 +465,197 @@ async function instantiate() {
       parameters: [CAPTURED_IMAGE_STRUCT_DEF], // Pass the struct by value
       result: "void",
     },
+    capture_image_clone_ref: {
+      parameters: [CAPTURED_IMAGE_STRUCT_DEF],
+      result: CAPTURED_IMAGE_STRUCT_DEF, // Same buffer, one more reference
+    },
+    capture_image_from_rgba: {
+      parameters: ["buffer", "u32", "u32", "usize"], // data, width, height, stride
+      result: CAPTURED_IMAGE_STRUCT_DEF, // A library-owned copy
+    },
+    capture_image_save: {
+      // image, path template, *const EncodeOptions
+      parameters: [CAPTURED_IMAGE_STRUCT_DEF, "buffer", "buffer"],
+      result: "i32", // ErrorCode
+      nonblocking: true, // Encoding can take time
+    },
+    capture_image_save_fd: {
+      // image, CaptureFd (a HANDLE on Windows), *const EncodeOptions
+      parameters: [
+        CAPTURED_IMAGE_STRUCT_DEF,
+        Deno.build.os === "windows" ? "pointer" : "i32",
+        "buffer",
+      ],
+      result: "i32", // ErrorCode
+      nonblocking: true, // Encoding can take time
+    },
+    capture_image_encode: {
+      // image, *const EncodeOptions, write callback, user_data
+      parameters: [CAPTURED_IMAGE_STRUCT_DEF, "buffer", "function", "pointer"],
+      result: "i32", // ErrorCode
+      nonblocking: true, // Encoding can take time
+    },
+    capture_delta_encoder_new: {
+      // tile_size, keyframe_interval, *const EncodeOptions
+      parameters: ["u32", "u32", "buffer"],
+      result: "u64", // CaptureHandle, 0 on error
+    },
+    capture_delta_encode: {
+      // encoder, image, write callback, user_data
+      parameters: ["u64", CAPTURED_IMAGE_STRUCT_DEF, "function", "pointer"],
+      result: "i32", // ErrorCode
+      nonblocking: true, // Encoding can take time
+    },
+    capture_delta_encoder_request_keyframe: {
+      parameters: ["u64"],
+      result: "i32", // ErrorCode
+    },
+    capture_delta_encoder_free: {
+      parameters: ["u64"],
+      result: "i32", // ErrorCode
+    },
+    capture_delta_decoder_new: {
+      parameters: [],
+      result: "u64", // CaptureHandle
+    },
+    capture_delta_decode: {
+      parameters: ["u64", "buffer", "usize"], // decoder, packet data, len
+      result: CAPTURED_IMAGE_STRUCT_DEF,
+      nonblocking: true, // Decoding can take time
+    },
+    capture_delta_decoder_free: {
+      parameters: ["u64"],
+      result: "i32", // ErrorCode
+    },
+    capture_archive_options_default: {
+      parameters: [],
+      result: ARCHIVE_OPTIONS_STRUCT_DEF,
+    },
+    capture_archive_open: {
+      parameters: ["buffer", "buffer"], // path template, *const ArchiveOptions
+      result: "u64", // CaptureHandle, 0 on error
+    },
+    capture_archive_open_fd: {
+      // CaptureFd (a HANDLE on Windows), *const ArchiveOptions
+      parameters: [Deno.build.os === "windows" ? "pointer" : "i32", "buffer"],
+      result: "u64", // CaptureHandle, 0 on error
+    },
+    capture_archive_write: {
+      parameters: ["u64", CAPTURED_IMAGE_STRUCT_DEF], // archive, image
+      result: "i32", // ErrorCode
+      nonblocking: true, // Compression can take time
+    },
+    capture_archive_stats: {
+      parameters: ["u64"],
+      result: ARCHIVE_STATS_STRUCT_DEF,
+    },
+    capture_archive_close: {
+      parameters: ["u64"],
+      result: "i32", // ErrorCode
+      nonblocking: true, // May sync to disk
+    },
+    capture_archive_estimate: {
+      // first, second, *const ArchiveOptions, fps
+      parameters: [
+        CAPTURED_IMAGE_STRUCT_DEF,
+        CAPTURED_IMAGE_STRUCT_DEF,
+        "buffer",
+        "u32",
+      ],
+      result: ARCHIVE_ESTIMATE_STRUCT_DEF,
+      nonblocking: true, // Compresses both frames
+    },
+    capture_archive_reader_open: {
+      parameters: ["buffer"], // path
+      result: "u64", // CaptureHandle, 0 on error
+    },
+    capture_archive_reader_next: {
+      parameters: ["u64", "buffer"], // reader, *mut u64 timestamp_ms
+      result: CAPTURED_IMAGE_STRUCT_DEF,
+      nonblocking: true, // Reads and decompresses a frame
+    },
+    capture_archive_reader_free: {
+      parameters: ["u64"],
+      result: "i32", // ErrorCode
+    },
+    capture_gallery_set_capacity: {
+      parameters: ["u32"],
+      result: "void",
+    },
+    capture_gallery_query_default: {
+      parameters: [],
+      result: GALLERY_QUERY_STRUCT_DEF,
+    },
+    capture_gallery_list: {
+      parameters: ["buffer", "buffer", "usize"], // *const GalleryQuery, *mut GalleryEntry, max
+      result: "usize", // Total number of matches
+    },
+    capture_gallery_get: {
+      parameters: ["u64"], // Gallery entry handle
+      result: CAPTURED_IMAGE_STRUCT_DEF,
+    },
+    capture_gallery_remove: {
+      parameters: ["u64"],
+      result: "i32", // ErrorCode
+    },
+    capture_compose_grid: {
+      // *const CapturedImage, count, columns, padding, bg_color (0xRRGGBBAA)
+      parameters: ["buffer", "usize", "u32", "u32", "u32"],
+      result: CAPTURED_IMAGE_STRUCT_DEF,
+    },
+    capture_image_view: {
+      parameters: [CAPTURED_IMAGE_STRUCT_DEF, CAPTURE_RECT_STRUCT_DEF],
+      result: IMAGE_VIEW_STRUCT_DEF,
+    },
+    capture_image_view_make_mut: {
+      parameters: ["buffer"], // *mut CapturedImageView, updated in place
+      result: "i32", // ErrorCode
+    },
+    capture_image_view_to_image: {
+      parameters: ["u64"],
+      result: CAPTURED_IMAGE_STRUCT_DEF,
+    },
+    capture_free_image_view: {
+      parameters: ["u64"],
+      result: "i32", // ErrorCode
+    },
     capture_last_error_message: {
       parameters: [],
       result: "pointer", // *const c_char
     },
+    capture_last_error_code: {
+      parameters: [],
+      result: "i32", // ErrorCode
+    },
+    capture_supports: {
+      parameters: ["i32"], // Feature
+      result: "bool",
+    },
+    capture_set_max_pixels: {
+      parameters: ["u64", "i32"], // max_pixels, OversizePolicy
+      result: "i32", // ErrorCode
+    },
+    capture_set_degradation_ladder: {
+      parameters: ["buffer", "usize"], // *const DegradationStep, count
+      result: "i32", // ErrorCode
+    },
+    capture_register_frame_processor: {
+      parameters: ["function", "pointer"], // native process fn, user_data
+      result: "u32", // processor ID, 0 on error
+    },
+    capture_unregister_frame_processor: {
+      parameters: ["u32"],
+      result: "i32", // ErrorCode
+      nonblocking: true, // Waits for calls in progress
+    },
+    capture_load_config: {
+      parameters: ["buffer"], // NUL-terminated path, or NULL to unload
+      result: "i32", // ErrorCode
+    },
+    capture_enums_json: {
+      parameters: [],
+      result: "pointer", // *mut c_char
+    },
   } as const;
 
   const name = "xcap_c_api";
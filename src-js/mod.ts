@@ -26,7 +26,12 @@
  *
  * @module
  */
-import { library } from "./ffi.ts";
+import {
+  EVENT_CALLBACK_DEF,
+  FRAME_CALLBACK_DEF,
+  library,
+  WRITE_CALLBACK_DEF,
+} from "./ffi.ts";
 /**
  * Represents information about a display monitor.
  */
@@ -67,8 +72,223 @@ export function getLastError(): string | null {
   return new Deno.UnsafePointerView(ptr).getCString();
 }
 
+/**
+ * Copies a CapturedImage struct returned by the native library into JS memory
+ * and frees the native buffer.
+ * @returns The image, or null if the struct holds no data.
+ */
+function takeCapturedImage(rawStruct: Uint8Array): CapturedImageData | null {
+  // Manual extraction from the struct without byte_type
+  // In FFI structs are returned as TypedArrays
+  const structData = new DataView(rawStruct.buffer);
+
+  // Extract fields based on memory layout:
+  // Assume 64-bit architecture (8-byte pointer and size_t)
+  const dataPtr = Deno.UnsafePointer.create(structData.getBigUint64(0, true));
+  const lenValue = Number(structData.getBigUint64(8, true));
+  const width = structData.getUint32(16, true);
+  const height = structData.getUint32(20, true);
+  const stride = Number(structData.getBigUint64(24, true));
+
+  if (dataPtr === null || lenValue === 0) {
+    // Need to free the struct, but with null data pointer
+    library.symbols.capture_free_image(rawStruct);
+    return null;
+  }
+
+  let imageData: Uint8Array | null = null;
+  try {
+    // Create a view into the Rust-allocated memory
+    const dataView = new Deno.UnsafePointerView(dataPtr);
+    // Copy the data into a JS-managed Uint8Array
+    imageData = new Uint8Array(width * height * 4);
+    if (stride === width * 4) {
+      dataView.copyInto(imageData);
+    } else {
+      // Drop the padding at the end of each row
+      for (let row = 0; row < height; row++) {
+        dataView.copyInto(
+          imageData.subarray(row * width * 4, (row + 1) * width * 4),
+          row * stride,
+        );
+      }
+    }
+  } catch (e) {
+    console.error("Error reading image data buffer:", e);
+  } finally {
+    // IMPORTANT: Free the image buffer allocated by Rust
+    library.symbols.capture_free_image(rawStruct);
+  }
+
+  if (!imageData) {
+    throw new Error("Failed to read image data from memory");
+  }
+
+  return { data: imageData, width, height };
+}
+
 // --- Public API ---
 
+/**
+ * Retrieves the code of the last error from the native library, e.g. `CAPTURE_ERROR_INVALID_INDEX`.
+ * Compare it against the values from {@link getEnums}.
+ * @returns The error code, or 0 (`CAPTURE_OK`) if there's no error.
+ */
+export function getLastErrorCode(): number {
+  return library.symbols.capture_last_error_code();
+}
+
+/**
+ * The enums of the native C API, keyed by enum name and then by constant name,
+ * e.g. `getEnums().enums.Feature.CAPTURE_FEATURE_OVERLAY`.
+ */
+export interface NativeEnums {
+  /** Version of the native library the values come from. */
+  version: string;
+  enums: Record<string, Record<string, number>>;
+}
+
+let nativeEnums: NativeEnums | null = null;
+
+/**
+ * Queries the native library for the numeric values of all its enums.
+ * @returns The enum tables; the result is cached after the first call.
+ */
+export function getEnums(): NativeEnums {
+  if (nativeEnums === null) {
+    const ptr = library.symbols.capture_enums_json();
+    if (ptr === null) {
+      throw new Error("Failed to query native enums");
+    }
+    try {
+      nativeEnums = JSON.parse(new Deno.UnsafePointerView(ptr).getCString());
+    } finally {
+      library.symbols.capture_free_string(ptr);
+    }
+  }
+  return nativeEnums!;
+}
+
+/**
+ * Checks whether an optional capability is available on this platform.
+ * @param feature A `CAPTURE_FEATURE_*` constant name, e.g. `"CAPTURE_FEATURE_OVERLAY"`.
+ */
+export function supports(feature: string): boolean {
+  const value = getEnums().enums.Feature[feature];
+  return value !== undefined && library.symbols.capture_supports(value);
+}
+
+/** What happens to captures larger than the limit set with {@link setMaxPixels}. */
+export type OversizePolicy = "reject" | "downscale";
+
+/**
+ * Limits the size of every capture, including stream frames.
+ * @param maxPixels Maximum width × height of a capture; 0 removes the limit.
+ * @param policy Whether larger captures fail or are scaled down to fit.
+ * @throws Error if the native library rejects the arguments.
+ */
+export function setMaxPixels(
+  maxPixels: number,
+  policy: OversizePolicy = "reject",
+): void {
+  const constant = policy === "downscale"
+    ? "CAPTURE_OVERSIZE_DOWNSCALE"
+    : "CAPTURE_OVERSIZE_REJECT";
+  const code = library.symbols.capture_set_max_pixels(
+    BigInt(maxPixels),
+    getEnums().enums.OversizePolicy[constant],
+  );
+  if (code !== 0) {
+    throw new Error(getLastError() ?? "Failed to set the capture size limit");
+  }
+}
+
+/** One way for a stream that keeps missing its frame deadlines to shed load. */
+export type DegradationStep =
+  /** Cap the frame rate at `fps`. */
+  | { action: "reduce-fps"; fps: number }
+  /** Scale frames to `percent` (1 to 99) of their current size. */
+  | { action: "downscale"; percent: number };
+
+/**
+ * Sets what streams started from now on give up, in order, when they keep
+ * missing their frame deadlines, e.g. first 15 fps, then half the resolution.
+ * Every step taken is reported with a "stream-degraded" event.
+ * @param steps The ladder; an empty one (the default) disables degradation.
+ * @throws Error if a step is invalid.
+ */
+export function setDegradationLadder(steps: DegradationStep[]): void {
+  const actions = getEnums().enums.DegradeAction;
+  // Layout of DegradationStep: i32 action, u32 value
+  const raw = new Uint8Array(steps.length * 8);
+  const view = new DataView(raw.buffer);
+  steps.forEach((step, i) => {
+    const [constant, value] = step.action === "reduce-fps"
+      ? ["CAPTURE_DEGRADE_REDUCE_FPS", step.fps]
+      : ["CAPTURE_DEGRADE_DOWNSCALE", step.percent];
+    view.setInt32(i * 8, actions[constant], true);
+    view.setUint32(i * 8 + 4, value, true);
+  });
+  const code = library.symbols.capture_set_degradation_ladder(
+    raw,
+    BigInt(steps.length),
+  );
+  if (code !== 0) {
+    throw new Error(getLastError() ?? "Failed to set the degradation ladder");
+  }
+}
+
+/**
+ * Loads process-wide defaults (format, quality, cursor, output directory and
+ * backend limits) from a `.toml` or `.json` file, replacing any loaded before.
+ * Values passed explicitly to other functions always win.
+ * @param path The config file, or null to unload the file's defaults.
+ * @throws Error if the file can't be read or contains unknown keys or bad values.
+ */
+export function loadConfig(path: string | null): void {
+  const code = library.symbols.capture_load_config(
+    path === null ? null : new TextEncoder().encode(path + "\0"),
+  );
+  if (code !== 0) {
+    throw new Error(getLastError() ?? "Failed to load config");
+  }
+}
+
+/**
+ * Registers a frame processor from a native plugin, e.g. a function looked up
+ * with `Deno.dlopen`. It runs on every captured frame before the library uses
+ * it and may modify the pixels in place or reject the frame.
+ * @param process Pointer to the plugin's
+ *   `int process(ProcessorFrame *frame, void *user_data)`.
+ * @param userData Passed back to `process` unchanged.
+ * @returns An ID for {@link unregisterFrameProcessor}.
+ * @throws Error if `process` is null.
+ */
+export function registerFrameProcessor(
+  process: Deno.PointerValue,
+  userData: Deno.PointerValue = null,
+): number {
+  const id = library.symbols.capture_register_frame_processor(
+    process,
+    userData,
+  );
+  if (id === 0) {
+    throw new Error(getLastError() ?? "Failed to register frame processor");
+  }
+  return id;
+}
+
+/**
+ * Removes a frame processor, waiting for calls to it already in progress.
+ * @throws Error if no processor has this ID.
+ */
+export async function unregisterFrameProcessor(id: number): Promise<void> {
+  const code = await library.symbols.capture_unregister_frame_processor(id);
+  if (code !== 0) {
+    throw new Error(`No frame processor with ID ${id}`);
+  }
+}
+
 /**
  * Retrieves a list of all connected monitors.
  * @returns An array of MonitorInfo objects.
@@ -122,32 +342,252 @@ export function getMonitors(): MonitorInfo[] {
   return monitors;
 }
 
+/**
+ * Represents information about an open window.
+ */
+export interface WindowInfo {
+  /** Platform ID, stable while the window exists. */
+  id: number;
+  title: string;
+}
+
+/**
+ * Retrieves a list of all open windows, topmost first.
+ * @throws Error if the native library fails to retrieve windows.
+ */
+export function getWindows(): WindowInfo[] {
+  const count = library.symbols.capture_window_count();
+  if (count === 0n) {
+    const error = getLastError();
+    if (error) {
+      throw new Error(`Failed to get windows: ${error}`);
+    }
+    return [];
+  }
+
+  const windows: WindowInfo[] = [];
+  for (let i = 0n; i < count; i++) {
+    const titlePtr = library.symbols.capture_window_title(i);
+    let title = "";
+    if (titlePtr !== null) {
+      try {
+        title = new Deno.UnsafePointerView(titlePtr).getCString();
+      } finally {
+        library.symbols.capture_free_string(titlePtr);
+      }
+    }
+    windows.push({ id: library.symbols.capture_window_id(i), title });
+  }
+  return windows;
+}
+
+/** A monitor or window, by platform ID. */
+export type CaptureTarget = { monitorId: number } | { windowId: number };
+
+/** Builds a native CaptureTarget struct. */
+function captureTarget(target: CaptureTarget): Uint8Array {
+  const kinds = getEnums().enums.TargetKind;
+  const raw = new Uint8Array(8);
+  const view = new DataView(raw.buffer);
+  if ("monitorId" in target) {
+    view.setInt32(0, kinds.CAPTURE_TARGET_MONITOR, true);
+    view.setUint32(4, target.monitorId, true);
+  } else {
+    view.setInt32(0, kinds.CAPTURE_TARGET_WINDOW, true);
+    view.setUint32(4, target.windowId, true);
+  }
+  return raw;
+}
+
+/**
+ * Briefly draws a native border around a monitor or window, e.g. to show
+ * what a picker is about to capture. A new highlight replaces the previous one.
+ * @param target The monitor or window to outline.
+ * @param color Border color as 0xRRGGBBAA; the alpha is ignored.
+ * @param durationMs How long the border stays up; 0 hides the current one.
+ * @throws Error if the target doesn't exist or overlays aren't supported.
+ */
+export function highlightTarget(
+  target: CaptureTarget,
+  color = 0xff3b30ff,
+  durationMs = 1000,
+): void {
+  const code = library.symbols.capture_highlight_target(
+    captureTarget(target),
+    color,
+    durationMs,
+  );
+  if (code !== 0) {
+    throw new Error(
+      `Failed to highlight target: ${getLastError() || "Unknown error"}`,
+    );
+  }
+}
+
+/**
+ * Waits `delaySecs` seconds, then captures a monitor or window.
+ * @param target The monitor or window to capture.
+ * @param delaySecs Seconds to wait before capturing.
+ * @param options.showCountdown Shows the seconds left in a native overlay
+ *   over the target; it is removed before the capture is taken.
+ * @param options.cancel Aborts the wait, rejecting the promise.
+ * @returns A Promise resolving to the captured image.
+ * @throws Error if the target doesn't exist, capturing fails or the wait was cancelled.
+ */
+export async function captureDelayed(
+  target: CaptureTarget,
+  delaySecs: number,
+  options: { showCountdown?: boolean; cancel?: CancelToken } = {},
+): Promise<CapturedImageData> {
+  const rawStruct = await library.symbols.capture_delayed(
+    captureTarget(target),
+    delaySecs,
+    options.showCountdown ?? false,
+    options.cancel?.handle ?? 0n,
+  );
+  const image = takeCapturedImage(rawStruct);
+  if (!image) {
+    throw new Error(
+      `Delayed capture failed: ${getLastError() || "Unknown error"}`,
+    );
+  }
+  return image;
+}
+
+/** Options for {@link captureMonitor} and {@link captureWindow}. */
+export interface CaptureOptions {
+  /**
+   * Delivers images at this multiple of the target's logical size whatever
+   * the monitor's scale factor, e.g. 1 for 1x logical pixels, so captures
+   * from mixed-DPI machines are directly comparable. Up to 8. Defaults to the
+   * monitor's native pixels.
+   */
+  outputScale?: number;
+}
+
+/** Builds a native CaptureOptions struct. */
+function captureOptions(options: CaptureOptions): Uint8Array {
+  const raw = library.symbols.capture_options_default();
+  // Layout of CaptureOptions: usize struct_size, usize row_alignment,
+  // u32 output_scale
+  const view = new DataView(raw.buffer);
+  if (options.outputScale !== undefined) {
+    view.setUint32(16, Math.round(options.outputScale * 100), true);
+  }
+  return raw;
+}
+
+/**
+ * Captures a window by its platform ID (from {@link getWindows}).
+ * @param windowId The window to capture.
+ * @param options.frame Moves and resizes the window so its content covers
+ *   exactly this rectangle for the capture, then puts it back, for identically
+ *   framed screenshots on every run and machine.
+ * @returns A Promise resolving to the captured image.
+ * @throws Error if the window doesn't exist, can't be given that exact frame,
+ *   or capturing fails.
+ */
+export async function captureWindow(
+  windowId: number,
+  options: CaptureOptions & { frame?: Rect } = {},
+): Promise<CapturedImageData> {
+  let rawFrame: Uint8Array | null = null;
+  if (options.frame) {
+    rawFrame = new Uint8Array(16);
+    const view = new DataView(rawFrame.buffer);
+    view.setInt32(0, options.frame.x, true);
+    view.setInt32(4, options.frame.y, true);
+    view.setUint32(8, options.frame.width, true);
+    view.setUint32(12, options.frame.height, true);
+  }
+  const rawStruct = await library.symbols.capture_window_image_ex(
+    windowId,
+    captureOptions(options),
+    rawFrame,
+  );
+  const image = takeCapturedImage(rawStruct);
+  if (!image) {
+    throw new Error(
+      `Failed to capture window ${windowId}: ${
+        getLastError() || "Unknown error"
+      }`,
+    );
+  }
+  return image;
+}
+
+/** What {@link snap} should capture and where the result goes. */
+export interface SnapSpec {
+  /** Defaults to the first monitor. */
+  target?: { monitorIndex: number } | CaptureTarget;
+  /** Part of the target to keep, in its captured pixels. */
+  region?: Rect;
+  format?: "png" | "jpeg";
+  /** JPEG quality from 1 to 100. */
+  quality?: number;
+  /** Output size relative to the target's logical size, as {@link CaptureOptions.outputScale}. */
+  scale?: number;
+  /** Include the cursor; throws where the backend can't. */
+  cursor?: boolean;
+  /** Output path template as for {@link saveMonitor}; without one the image is returned as base64. */
+  path?: string;
+}
+
+/** The outcome of a successful {@link snap}. */
+export interface SnapResult {
+  width: number;
+  height: number;
+  format: "png" | "jpeg";
+  target: CaptureTarget;
+  /** The file written, if the spec had a `path`. */
+  path?: string;
+  /** The encoded image, if the spec had no `path`. */
+  base64?: string;
+}
+
+/**
+ * Takes a screenshot described by one spec in a single native call.
+ * @throws Error if the spec is invalid or capturing or saving fails.
+ */
+export async function snap(spec: SnapSpec): Promise<SnapResult> {
+  const ptr = await library.symbols.capture_snap(
+    new TextEncoder().encode(JSON.stringify(spec) + "\0"),
+  );
+  if (ptr === null) {
+    throw new Error(`Snap failed: ${getLastError() || "Unknown error"}`);
+  }
+  let result;
+  try {
+    result = JSON.parse(new Deno.UnsafePointerView(ptr).getCString());
+  } finally {
+    library.symbols.capture_free_string(ptr);
+  }
+  if (!result.ok) {
+    throw new Error(result.message);
+  }
+  delete result.ok;
+  return result as SnapResult;
+}
+
 /**
  * Captures a screenshot of the specified monitor by its index.
  * @param monitorIndex The index of the monitor (from MonitorInfo.index).
+ * @param options Capture options, e.g. {@link CaptureOptions.outputScale}.
  * @returns A Promise resolving to CapturedImageData containing the screenshot.
  * @throws Error if the monitor index is invalid or capturing fails.
  */
 export async function captureMonitor(
   monitorIndex: bigint,
+  options: CaptureOptions = {},
 ): Promise<CapturedImageData> {
   // The FFI call is potentially blocking, so use await if nonblocking: true
-  const rawStruct = await library.symbols.capture_monitor_image(monitorIndex);
-
-  // Manual extraction from the struct without byte_type
-  // In FFI structs are returned as TypedArrays
-  const structData = new DataView(rawStruct.buffer);
-
-  // Extract fields based on memory layout:
-  // Assume 64-bit architecture (8-byte pointer and size_t)
-  const dataPtr = Deno.UnsafePointer.create(structData.getBigUint64(0, true));
-  const lenValue = Number(structData.getBigUint64(8, true));
-  const width = structData.getUint32(16, true);
-  const height = structData.getUint32(20, true);
+  const rawStruct = await library.symbols.capture_monitor_image_ex(
+    monitorIndex,
+    captureOptions(options),
+  );
 
-  if (dataPtr === null || lenValue === 0) {
-    // Need to free the struct, but with null data pointer
-    library.symbols.capture_free_image(rawStruct);
+  const image = takeCapturedImage(rawStruct);
+  if (!image) {
     const error = getLastError();
     throw new Error(
       `Failed to capture image for monitor index ${monitorIndex}: ${
@@ -155,25 +595,1581 @@ export async function captureMonitor(
       }`,
     );
   }
+  return image;
+}
+
+/** An image kept in the capture gallery. */
+export interface GalleryEntry {
+  /** Pass to {@link getGalleryImage}; stops working once the entry is evicted. */
+  id: bigint;
+  /** When the capture was taken, in milliseconds since the Unix epoch. */
+  timestamp: number;
+  /** Platform ID of the captured monitor (see MonitorInfo.id). */
+  monitorId: number;
+  width: number;
+  height: number;
+}
+
+/** Filters for {@link listGallery}; all optional. */
+export interface GalleryFilter {
+  /** Only entries taken at or after this time (ms since the Unix epoch). */
+  since?: number;
+  /** Only entries taken at or before this time (ms since the Unix epoch). */
+  until?: number;
+  /** Only captures of this monitor (see MonitorInfo.id). */
+  monitorId?: number;
+}
+
+/** Size in bytes of the native GalleryEntry struct. */
+const GALLERY_ENTRY_SIZE = 32;
+
+/**
+ * Keeps the most recent `capacity` one-shot captures (not stream frames) in a
+ * native gallery, so they can be fetched again later without holding on to
+ * them. 0, the default, disables the gallery and empties it.
+ */
+export function setGalleryCapacity(capacity: number): void {
+  library.symbols.capture_gallery_set_capacity(capacity);
+}
+
+/**
+ * Lists the captures in the gallery, newest first.
+ * @param filter Restricts the result by time or monitor.
+ */
+export function listGallery(filter: GalleryFilter = {}): GalleryEntry[] {
+  const query = library.symbols.capture_gallery_query_default();
+  // Layout of GalleryQuery: usize struct_size, u64 since_ms, u64 until_ms,
+  // i32 match_target, i32 target.kind, u32 target.id
+  const view = new DataView(query.buffer);
+  view.setBigUint64(8, BigInt(filter.since ?? 0), true);
+  view.setBigUint64(16, BigInt(filter.until ?? 0), true);
+  if (filter.monitorId !== undefined) {
+    view.setInt32(24, 1, true);
+    view.setInt32(28, getEnums().enums.TargetKind.CAPTURE_TARGET_MONITOR, true);
+    view.setUint32(32, filter.monitorId, true);
+  }
 
-  let imageData: Uint8Array | null = null;
+  // Entries can be added between sizing and listing; retry until they fit.
+  let capacity = Number(library.symbols.capture_gallery_list(query, null, 0n));
+  for (;;) {
+    const out = new Uint8Array(Math.max(capacity, 1) * GALLERY_ENTRY_SIZE);
+    const total = Number(
+      library.symbols.capture_gallery_list(query, out, BigInt(capacity)),
+    );
+    if (total <= capacity) {
+      const entries = new DataView(out.buffer);
+      return Array.from({ length: total }, (_, i) => {
+        const offset = i * GALLERY_ENTRY_SIZE;
+        // Layout of GalleryEntry: u64 id, u64 timestamp_ms, i32 target.kind,
+        // u32 target.id, u32 width, u32 height
+        return {
+          id: entries.getBigUint64(offset, true),
+          timestamp: Number(entries.getBigUint64(offset + 8, true)),
+          monitorId: entries.getUint32(offset + 20, true),
+          width: entries.getUint32(offset + 24, true),
+          height: entries.getUint32(offset + 28, true),
+        };
+      });
+    }
+    capacity = total;
+  }
+}
+
+/**
+ * Fetches a capture from the gallery.
+ * @param id The entry's id from {@link listGallery}.
+ * @throws Error if the entry has been evicted or removed.
+ */
+export function getGalleryImage(id: bigint): CapturedImageData {
+  const image = takeCapturedImage(library.symbols.capture_gallery_get(id));
+  if (!image) {
+    throw new Error(
+      `Failed to get gallery entry ${id}: ${getLastError() || "Unknown error"}`,
+    );
+  }
+  return image;
+}
+
+/**
+ * Removes a capture from the gallery, freeing its memory unless it is still
+ * referenced elsewhere.
+ * @throws Error if the entry has already been evicted or removed.
+ */
+export function removeGalleryEntry(id: bigint): void {
+  if (library.symbols.capture_gallery_remove(id) !== 0) {
+    throw new Error(
+      `Failed to remove gallery entry ${id}: ${
+        getLastError() || "Unknown error"
+      }`,
+    );
+  }
+}
+
+/** Compressed formats {@link saveMonitor} can write. */
+export type ImageFormat = "png" | "jpeg";
+
+/** Options for {@link saveMonitor} and {@link encodeMonitor}. */
+export interface SaveOptions {
+  /** Defaults to "png". */
+  format?: ImageFormat;
+  /** JPEG quality from 1 to 100; defaults to 90. */
+  quality?: number;
+  /**
+   * Write files under a temporary name and rename them into place when
+   * complete, so readers never see a partial file. Defaults to true; turn off
+   * for filesystems without atomic rename.
+   */
+  atomic?: boolean;
+  /** Flush files to disk before returning. Defaults to false. */
+  fsync?: boolean;
+  /**
+   * What to do when the file exists: replace it ("overwrite", the default),
+   * save as name-1.ext, name-2.ext, ... instead ("increment"), or fail
+   * ("error").
+   */
+  collision?: CollisionPolicy;
+}
+
+/** See {@link SaveOptions.collision}. */
+export type CollisionPolicy = "overwrite" | "increment" | "error";
+
+const COLLISION_CONSTANTS: Record<CollisionPolicy, string> = {
+  overwrite: "CAPTURE_COLLISION_OVERWRITE",
+  increment: "CAPTURE_COLLISION_INCREMENT",
+  error: "CAPTURE_COLLISION_ERROR",
+};
+
+/** Builds a native EncodeOptions struct. */
+function encodeOptions(options: SaveOptions): Uint8Array {
+  const raw = library.symbols.capture_encode_options_default();
+  // Layout of EncodeOptions: usize struct_size, i32 format, i32 quality,
+  // i32 atomic_write, i32 fsync, i32 collision
+  const view = new DataView(raw.buffer);
+  const formats = getEnums().enums.ImageFormat;
+  view.setInt32(
+    8,
+    options.format === "jpeg"
+      ? formats.CAPTURE_FORMAT_JPEG
+      : formats.CAPTURE_FORMAT_PNG,
+    true,
+  );
+  view.setInt32(12, options.quality ?? 0, true);
+  if (options.atomic !== undefined) {
+    view.setInt32(16, options.atomic ? 1 : 0, true);
+  }
+  if (options.fsync !== undefined) {
+    view.setInt32(20, options.fsync ? 1 : 0, true);
+  }
+  if (options.collision !== undefined) {
+    const policies = getEnums().enums.CollisionPolicy;
+    view.setInt32(24, policies[COLLISION_CONSTANTS[options.collision]], true);
+  }
+  return raw;
+}
+
+/**
+ * Captures a monitor and saves it as PNG or JPEG, encoding natively straight
+ * from the captured frame. This never copies the pixels into JavaScript, which
+ * keeps peak memory low on large displays.
+ * Requires --allow-write permission for the native library to create the file.
+ * @param monitorIndex The index of the monitor (from MonitorInfo.index).
+ * @param path The file to write. Placeholders `{monitor}`, `{title}`,
+ *   `{timestamp}` and `{seq}` are expanded, with characters illegal in file
+ *   names replaced; write `{{` and `}}` for literal braces.
+ * @returns The path actually written.
+ * @throws Error if capturing, encoding or writing fails.
+ */
+export async function saveMonitor(
+  monitorIndex: bigint,
+  path: string,
+  options: SaveOptions = {},
+): Promise<string> {
+  const savedPtr = await library.symbols.capture_monitor_save_as(
+    monitorIndex,
+    new TextEncoder().encode(path + "\0"),
+    encodeOptions(options),
+  );
+  if (savedPtr === null) {
+    const error = getLastError();
+    throw new Error(
+      `Failed to save monitor index ${monitorIndex} to ${path}: ${
+        error || "Unknown error"
+      }`,
+    );
+  }
   try {
-    // Create a view into the Rust-allocated memory
-    const dataView = new Deno.UnsafePointerView(dataPtr);
-    // Copy the data into a JS-managed Uint8Array
-    imageData = new Uint8Array(dataView.getArrayBuffer(lenValue));
-  } catch (e) {
-    console.error("Error reading image data buffer:", e);
+    return new Deno.UnsafePointerView(savedPtr).getCString();
   } finally {
-    // IMPORTANT: Free the image buffer allocated by Rust
-    library.symbols.capture_free_image(rawStruct);
+    library.symbols.capture_free_string(savedPtr);
   }
+}
 
-  if (!imageData) {
-    throw new Error("Failed to read image data from memory");
+/** A file descriptor as the native library takes it: a HANDLE on Windows. */
+function nativeFd(fd: number) {
+  const target = Deno.build.os === "windows"
+    ? Deno.UnsafePointer.create(BigInt(fd))
+    : fd;
+  // deno-lint-ignore no-explicit-any
+  return target as any; // The parameter type depends on the platform
+}
+
+/**
+ * Like {@link saveMonitor}, but writes to a file descriptor the host already
+ * opened (a pipe, socket, or sandbox-provided file) instead of a path.
+ * The descriptor is not closed.
+ * @param monitorIndex The index of the monitor (from MonitorInfo.index).
+ * @param fd An open, writable file descriptor; a HANDLE value on Windows.
+ * @throws Error if capturing, encoding or writing fails.
+ */
+export async function saveMonitorToFd(
+  monitorIndex: bigint,
+  fd: number,
+  options: SaveOptions = {},
+): Promise<void> {
+  const code = await library.symbols.capture_monitor_save_fd(
+    monitorIndex,
+    nativeFd(fd),
+    encodeOptions(options),
+  );
+  if (code !== 0) {
+    const error = getLastError();
+    throw new Error(
+      `Failed to save monitor index ${monitorIndex} to fd ${fd}: ${
+        error || "Unknown error"
+      }`,
+    );
   }
+}
 
-  return { data: imageData, width, height };
+/**
+ * Captures a window by its platform ID (from {@link getWindows}) and writes it
+ * to a file descriptor like {@link saveMonitorToFd}. The descriptor is not
+ * closed.
+ * @param windowId The window to capture.
+ * @param fd An open, writable file descriptor; a HANDLE value on Windows.
+ * @throws Error if the window doesn't exist, or capturing, encoding or
+ *   writing fails.
+ */
+export async function saveWindowToFd(
+  windowId: number,
+  fd: number,
+  options: SaveOptions = {},
+): Promise<void> {
+  const code = await library.symbols.capture_window_save_fd(
+    windowId,
+    nativeFd(fd),
+    encodeOptions(options),
+  );
+  if (code !== 0) {
+    const error = getLastError();
+    throw new Error(
+      `Failed to save window ${windowId} to fd ${fd}: ${
+        error || "Unknown error"
+      }`,
+    );
+  }
+}
+
+/**
+ * Captures a monitor and encodes it as PNG or JPEG natively, handing the
+ * compressed bytes to `onChunk` piece by piece as they are produced, e.g. to
+ * upload them without holding the whole file in memory.
+ * @param monitorIndex The index of the monitor (from MonitorInfo.index).
+ * @param onChunk Receives each chunk of encoded bytes (a copy it may keep).
+ *   Return false to stop encoding early.
+ * @throws Error if capturing or encoding fails, or `onChunk` stopped it.
+ */
+export async function encodeMonitor(
+  monitorIndex: bigint,
+  onChunk: (chunk: Uint8Array) => boolean | void,
+  options: SaveOptions = {},
+): Promise<void> {
+  const callback = Deno.UnsafeCallback.threadSafe(
+    WRITE_CALLBACK_DEF,
+    (data, len) => {
+      if (data === null) {
+        return 0;
+      }
+      const chunk = new Uint8Array(Number(len));
+      Deno.UnsafePointerView.copyInto(data, chunk);
+      return onChunk(chunk) === false ? 1 : 0;
+    },
+  );
+  try {
+    const code = await library.symbols.capture_monitor_encode(
+      monitorIndex,
+      encodeOptions(options),
+      callback.pointer,
+      null,
+    );
+    if (code !== 0) {
+      const error = getLastError();
+      throw new Error(
+        `Failed to encode monitor index ${monitorIndex}: ${
+          error || "Unknown error"
+        }`,
+      );
+    }
+  } finally {
+    callback.close();
+  }
+}
+
+/**
+ * Copies an image into the native library, runs `use` on it, and frees the
+ * native copy again.
+ */
+async function withNativeImage<T>(
+  image: CapturedImageData,
+  use: (raw: Uint8Array) => Promise<T>,
+): Promise<T> {
+  const raw = library.symbols.capture_image_from_rgba(
+    image.data,
+    image.width,
+    image.height,
+    0n,
+  );
+  if (new DataView(raw.buffer).getBigUint64(0, true) === 0n) {
+    const error = getLastError();
+    throw new Error(`Invalid image: ${error || "Unknown error"}`);
+  }
+  try {
+    return await use(raw);
+  } finally {
+    library.symbols.capture_free_image(raw);
+  }
+}
+
+/**
+ * Saves an RGBA image that came from anywhere (not necessarily a capture) as
+ * PNG or JPEG with the native encoder, like {@link saveMonitor}. This also
+ * works with builds of the native library that have no capture backend.
+ * `{monitor}` and `{title}` expand to `_` in `path`.
+ * @throws Error if the image is malformed or encoding or writing fails.
+ */
+export async function saveImage(
+  image: CapturedImageData,
+  path: string,
+  options: SaveOptions = {},
+): Promise<void> {
+  await withNativeImage(image, async (raw) => {
+    const code = await library.symbols.capture_image_save(
+      raw,
+      new TextEncoder().encode(path + "\0"),
+      encodeOptions(options),
+    );
+    if (code !== 0) {
+      const error = getLastError();
+      throw new Error(
+        `Failed to save image to ${path}: ${error || "Unknown error"}`,
+      );
+    }
+  });
+}
+
+/**
+ * Like {@link saveImage}, but writes to a file descriptor the host already
+ * opened, like {@link saveMonitorToFd}. The descriptor is not closed.
+ * @param fd An open, writable file descriptor; a HANDLE value on Windows.
+ * @throws Error if the image is malformed or encoding or writing fails.
+ */
+export async function saveImageToFd(
+  image: CapturedImageData,
+  fd: number,
+  options: SaveOptions = {},
+): Promise<void> {
+  await withNativeImage(image, async (raw) => {
+    const code = await library.symbols.capture_image_save_fd(
+      raw,
+      nativeFd(fd),
+      encodeOptions(options),
+    );
+    if (code !== 0) {
+      const error = getLastError();
+      throw new Error(
+        `Failed to save image to fd ${fd}: ${error || "Unknown error"}`,
+      );
+    }
+  });
+}
+
+/**
+ * Encodes an RGBA image that came from anywhere as PNG or JPEG with the native
+ * encoder, handing the compressed bytes to `onChunk` like
+ * {@link encodeMonitor}.
+ * @throws Error if the image is malformed, encoding fails, or `onChunk`
+ *   stopped it.
+ */
+export async function encodeImage(
+  image: CapturedImageData,
+  onChunk: (chunk: Uint8Array) => boolean | void,
+  options: SaveOptions = {},
+): Promise<void> {
+  const callback = Deno.UnsafeCallback.threadSafe(
+    WRITE_CALLBACK_DEF,
+    (data, len) => {
+      if (data === null) {
+        return 0;
+      }
+      const chunk = new Uint8Array(Number(len));
+      Deno.UnsafePointerView.copyInto(data, chunk);
+      return onChunk(chunk) === false ? 1 : 0;
+    },
+  );
+  try {
+    await withNativeImage(image, async (raw) => {
+      const code = await library.symbols.capture_image_encode(
+        raw,
+        encodeOptions(options),
+        callback.pointer,
+        null,
+      );
+      if (code !== 0) {
+        const error = getLastError();
+        throw new Error(`Failed to encode image: ${error || "Unknown error"}`);
+      }
+    });
+  } finally {
+    callback.close();
+  }
+}
+
+/**
+ * Encodes frames into a compact stream for remote viewing: a keyframe with the
+ * whole frame, then packets of only the tiles that changed. Decode them on the
+ * receiving side with {@link DeltaDecoder}. Call {@link DeltaEncoder.free}
+ * when done with it.
+ */
+export class DeltaEncoder {
+  #handle: bigint;
+
+  /**
+   * @param options.tileSize Tile side in pixels, 8 to 1024 (default 64).
+   * @param options.keyframeInterval Send a keyframe every this many packets so
+   *   late or lossy viewers recover (default: only when needed).
+   * @throws Error if an option is out of range.
+   */
+  constructor(
+    options: SaveOptions & { tileSize?: number; keyframeInterval?: number } =
+      {},
+  ) {
+    this.#handle = library.symbols.capture_delta_encoder_new(
+      options.tileSize ?? 0,
+      options.keyframeInterval ?? 0,
+      encodeOptions(options),
+    );
+    if (this.#handle === 0n) {
+      throw new Error(
+        `Failed to create delta encoder: ${getLastError() || "Unknown error"}`,
+      );
+    }
+  }
+
+  /**
+   * Encodes the next frame and returns its packet, to be sent as one message.
+   * @throws Error if the image is malformed or the encoder was freed.
+   */
+  async encode(image: CapturedImageData): Promise<Uint8Array> {
+    let packet = new Uint8Array(0);
+    const callback = Deno.UnsafeCallback.threadSafe(
+      WRITE_CALLBACK_DEF,
+      (data, len) => {
+        if (data !== null) {
+          packet = new Uint8Array(Number(len));
+          Deno.UnsafePointerView.copyInto(data, packet);
+        }
+        return 0;
+      },
+    );
+    try {
+      await withNativeImage(image, async (raw) => {
+        const code = await library.symbols.capture_delta_encode(
+          this.#handle,
+          raw,
+          callback.pointer,
+          null,
+        );
+        if (code !== 0) {
+          const error = getLastError();
+          throw new Error(`Failed to encode frame: ${error || "Unknown error"}`);
+        }
+      });
+    } finally {
+      callback.close();
+    }
+    return packet;
+  }
+
+  /** Makes the next packet a keyframe, e.g. when a viewer (re)connects. */
+  requestKeyframe(): void {
+    if (this.#handle !== 0n) {
+      library.symbols.capture_delta_encoder_request_keyframe(this.#handle);
+    }
+  }
+
+  /** Frees the native encoder. */
+  free(): void {
+    if (this.#handle !== 0n) {
+      library.symbols.capture_delta_encoder_free(this.#handle);
+      this.#handle = 0n;
+    }
+  }
+}
+
+/**
+ * Puts frames back together from the packets of a {@link DeltaEncoder}. Call
+ * {@link DeltaDecoder.free} when done with it.
+ */
+export class DeltaDecoder {
+  #handle: bigint;
+
+  constructor() {
+    this.#handle = library.symbols.capture_delta_decoder_new();
+  }
+
+  /**
+   * Applies the next packet and returns the whole frame so far.
+   * @throws Error if the packet is malformed, or a delta packet doesn't follow
+   *   the previous one; ask the sender for a keyframe then.
+   */
+  async decode(packet: Uint8Array): Promise<CapturedImageData> {
+    const image = takeCapturedImage(
+      await library.symbols.capture_delta_decode(
+        this.#handle,
+        packet,
+        BigInt(packet.length),
+      ),
+    );
+    if (!image) {
+      throw new Error(
+        `Failed to decode packet: ${getLastError() || "Unknown error"}`,
+      );
+    }
+    return image;
+  }
+
+  /** Frees the native decoder. */
+  free(): void {
+    if (this.#handle !== 0n) {
+      library.symbols.capture_delta_decoder_free(this.#handle);
+      this.#handle = 0n;
+    }
+  }
+}
+
+/** Options for {@link Archive.open} and {@link estimateArchive}. */
+export interface ArchiveOptions {
+  /** zlib level from 1 (fastest) to 9 (smallest); defaults to 6. */
+  level?: number;
+  /** Frames between keyframes; defaults to 300. */
+  keyframeInterval?: number;
+  /**
+   * What to do when the file exists; unlike {@link SaveOptions.collision}
+   * this defaults to "error", so a recording never replaces an earlier one.
+   */
+  collision?: CollisionPolicy;
+  /** Sync the file to disk when closing. Defaults to false. */
+  fsync?: boolean;
+}
+
+/** Builds a native ArchiveOptions struct. */
+function archiveOptions(options: ArchiveOptions): Uint8Array {
+  const raw = library.symbols.capture_archive_options_default();
+  // Layout of ArchiveOptions: usize struct_size, i32 level,
+  // u32 keyframe_interval, i32 collision, i32 fsync
+  const view = new DataView(raw.buffer);
+  view.setInt32(8, options.level ?? 0, true);
+  view.setUint32(12, options.keyframeInterval ?? 0, true);
+  if (options.collision !== undefined) {
+    const policies = getEnums().enums.CollisionPolicy;
+    view.setInt32(16, policies[COLLISION_CONSTANTS[options.collision]], true);
+  }
+  if (options.fsync !== undefined) {
+    view.setInt32(20, options.fsync ? 1 : 0, true);
+  }
+  return raw;
+}
+
+/** What an {@link Archive} holds so far. */
+export interface ArchiveStats {
+  frames: bigint;
+  keyframes: bigint;
+  /** Size the frames would take uncompressed. */
+  rawBytes: bigint;
+  /** Size of the archive file so far. */
+  writtenBytes: bigint;
+  /** Time between the first and the latest frame in milliseconds. */
+  durationMs: bigint;
+  /** File size of an hour at the rate so far; 0n before the second frame. */
+  bytesPerHour: bigint;
+}
+
+/** Projected archive size, from {@link estimateArchive}. */
+export interface ArchiveEstimate {
+  rawBytesPerFrame: bigint;
+  keyframeBytes: bigint;
+  deltaBytes: bigint;
+  bytesPerHour: bigint;
+}
+
+/**
+ * A lossless recording: every frame is stored bit-exact, compressed whole or
+ * as its difference from the previous frame. Read it back with
+ * {@link ArchiveReader}.
+ * Requires --allow-write permission for the native library to create the file.
+ */
+export class Archive {
+  #handle: bigint;
+
+  private constructor(handle: bigint) {
+    this.#handle = handle;
+  }
+
+  /**
+   * Creates an archive file. `path` takes the placeholders of
+   * {@link saveMonitor}; `{monitor}` and `{title}` expand to `_`.
+   * @throws Error if the file can't be created or an option is invalid.
+   */
+  static open(path: string, options: ArchiveOptions = {}): Archive {
+    const handle = library.symbols.capture_archive_open(
+      new TextEncoder().encode(path + "\0"),
+      archiveOptions(options),
+    );
+    if (handle === 0n) {
+      throw new Error(
+        `Failed to open archive ${path}: ${getLastError() || "Unknown error"}`,
+      );
+    }
+    return new Archive(handle);
+  }
+
+  /**
+   * Records into a file descriptor the host already opened, like
+   * {@link saveMonitorToFd}. The archive keeps its own duplicate, so `fd`
+   * may be closed once this returns; `collision` doesn't apply.
+   * @param fd An open, writable file descriptor; a HANDLE value on Windows.
+   * @throws Error if the header can't be written or an option is invalid.
+   */
+  static openFd(fd: number, options: ArchiveOptions = {}): Archive {
+    const handle = library.symbols.capture_archive_open_fd(
+      nativeFd(fd),
+      archiveOptions(options),
+    );
+    if (handle === 0n) {
+      throw new Error(
+        `Failed to open archive on fd ${fd}: ${
+          getLastError() || "Unknown error"
+        }`,
+      );
+    }
+    return new Archive(handle);
+  }
+
+  /**
+   * Appends a frame, stamped with the current time.
+   * @throws Error if the image is malformed or writing fails.
+   */
+  async write(image: CapturedImageData): Promise<void> {
+    await withNativeImage(image, async (raw) => {
+      const code = await library.symbols.capture_archive_write(
+        this.#handle,
+        raw,
+      );
+      if (code !== 0) {
+        const error = getLastError();
+        throw new Error(`Failed to archive frame: ${error || "Unknown error"}`);
+      }
+    });
+  }
+
+  /** Gets how many frames the archive holds and how well they compressed. */
+  stats(): ArchiveStats {
+    // Layout of ArchiveStats: six u64 fields
+    const view = new DataView(
+      library.symbols.capture_archive_stats(this.#handle).buffer,
+    );
+    const field = (i: number) => view.getBigUint64(i * 8, true);
+    return {
+      frames: field(0),
+      keyframes: field(1),
+      rawBytes: field(2),
+      writtenBytes: field(3),
+      durationMs: field(4),
+      bytesPerHour: field(5),
+    };
+  }
+
+  /**
+   * Flushes and closes the archive.
+   * @throws Error if the final flush fails.
+   */
+  async close(): Promise<void> {
+    if (this.#handle === 0n) {
+      return;
+    }
+    const handle = this.#handle;
+    this.#handle = 0n;
+    const code = await library.symbols.capture_archive_close(handle);
+    if (code !== 0) {
+      throw new Error(
+        `Failed to close archive: ${getLastError() || "Unknown error"}`,
+      );
+    }
+  }
+}
+
+/**
+ * Estimates how large a lossless {@link Archive} of content like two
+ * consecutive sample frames gets, before committing disk space to it.
+ * @param fps Frame rate to project an hour at (default 30).
+ * @throws Error if the frames are malformed or differ in size.
+ */
+export async function estimateArchive(
+  first: CapturedImageData,
+  second: CapturedImageData,
+  options: ArchiveOptions & { fps?: number } = {},
+): Promise<ArchiveEstimate> {
+  const rawStruct = await withNativeImage(
+    first,
+    (a) =>
+      withNativeImage(
+        second,
+        (b) =>
+          library.symbols.capture_archive_estimate(
+            a,
+            b,
+            archiveOptions(options),
+            options.fps ?? 0,
+          ),
+      ),
+  );
+  // Layout of ArchiveEstimate: four u64 fields
+  const view = new DataView(rawStruct.buffer);
+  const estimate: ArchiveEstimate = {
+    rawBytesPerFrame: view.getBigUint64(0, true),
+    keyframeBytes: view.getBigUint64(8, true),
+    deltaBytes: view.getBigUint64(16, true),
+    bytesPerHour: view.getBigUint64(24, true),
+  };
+  if (estimate.rawBytesPerFrame === 0n) {
+    throw new Error(
+      `Failed to estimate archive size: ${getLastError() || "Unknown error"}`,
+    );
+  }
+  return estimate;
+}
+
+/**
+ * Reads an {@link Archive} back frame by frame. Call
+ * {@link ArchiveReader.free} when done.
+ */
+export class ArchiveReader {
+  #handle: bigint;
+
+  /**
+   * Requires --allow-read permission for the native library to open the file.
+   * A relative `path` resolves against the configured output directory.
+   * @throws Error if the file can't be opened or isn't an archive.
+   */
+  constructor(path: string) {
+    this.#handle = library.symbols.capture_archive_reader_open(
+      new TextEncoder().encode(path + "\0"),
+    );
+    if (this.#handle === 0n) {
+      throw new Error(
+        `Failed to open archive ${path}: ${getLastError() || "Unknown error"}`,
+      );
+    }
+  }
+
+  /**
+   * Reads the next frame and its capture time in Unix milliseconds, or
+   * returns null after the last one.
+   * @throws Error if the archive is damaged or ends in a partial frame. The
+   * frame is not skipped, so calling again returns it once a recording still
+   * in progress has finished writing it.
+   */
+  async next(): Promise<
+    { image: CapturedImageData; timestampMs: bigint } | null
+  > {
+    const timestamp = new BigUint64Array(1);
+    const image = takeCapturedImage(
+      await library.symbols.capture_archive_reader_next(
+        this.#handle,
+        timestamp,
+      ),
+    );
+    if (image) {
+      return { image, timestampMs: timestamp[0] };
+    }
+    const codes = getEnums().enums.ErrorCode;
+    if (getLastErrorCode() === codes.CAPTURE_ERROR_END_OF_DATA) {
+      return null;
+    }
+    throw new Error(
+      `Failed to read archive: ${getLastError() || "Unknown error"}`,
+    );
+  }
+
+  /** Frees the native reader. */
+  free(): void {
+    if (this.#handle !== 0n) {
+      library.symbols.capture_archive_reader_free(this.#handle);
+      this.#handle = 0n;
+    }
+  }
+}
+
+/**
+ * A native cancellation token. Pass one token to several long-running
+ * operations to abort all of them with a single {@link CancelToken.cancel}.
+ * Call {@link CancelToken.free} when done with it.
+ */
+export class CancelToken {
+  #handle: bigint;
+
+  constructor() {
+    this.#handle = library.symbols.capture_cancel_token_new();
+  }
+
+  /** Creates a token that is cancelled when `signal` aborts. */
+  static fromSignal(signal: AbortSignal): CancelToken {
+    const token = new CancelToken();
+    if (signal.aborted) {
+      token.cancel();
+    } else {
+      signal.addEventListener("abort", () => token.cancel(), { once: true });
+    }
+    return token;
+  }
+
+  /** The native handle. */
+  get handle(): bigint {
+    return this.#handle;
+  }
+
+  /** Whether {@link CancelToken.cancel} has been called. */
+  get cancelled(): boolean {
+    return this.#handle !== 0n &&
+      library.symbols.capture_cancel_token_is_cancelled(this.#handle);
+  }
+
+  /** Cancels every operation using this token. Does nothing once freed. */
+  cancel(): void {
+    if (this.#handle !== 0n) {
+      library.symbols.capture_cancel_token_cancel(this.#handle);
+    }
+  }
+
+  /**
+   * Frees the native token. Operations still using it keep running but can no
+   * longer be cancelled through it.
+   */
+  free(): void {
+    if (this.#handle !== 0n) {
+      library.symbols.capture_cancel_token_free(this.#handle);
+      this.#handle = 0n;
+    }
+  }
+}
+
+/**
+ * Photon-to-buffer latency statistics, in milliseconds.
+ */
+export interface LatencyStats {
+  /** Number of samples whose pattern change was observed in a capture. */
+  samples: number;
+  /** Number of samples that gave up waiting for the change to show up. */
+  timeouts: number;
+  minMs: number;
+  maxMs: number;
+  meanMs: number;
+  medianMs: number;
+  p95Ms: number;
+  stddevMs: number;
+}
+
+/**
+ * Measures how long it takes for a change on screen to show up in a captured buffer.
+ * A small native overlay in the monitor's top-left corner is toggled between black and
+ * white `samples` times, and the monitor is captured until each change is visible.
+ * Currently only supported on X11.
+ *
+ * @param monitorIndex The index of the monitor (from MonitorInfo.index).
+ * @param samples Number of pattern changes to time.
+ * @param options.cancel Stops the measurement early, keeping the samples so far.
+ * @returns A Promise resolving to the latency statistics.
+ * @throws Error if the overlay can't be shown or capturing fails.
+ */
+export async function measureLatency(
+  monitorIndex: bigint,
+  samples = 30,
+  options: { cancel?: CancelToken } = {},
+): Promise<LatencyStats> {
+  const rawStruct = await library.symbols.capture_measure_latency_ex(
+    monitorIndex,
+    samples,
+    options.cancel?.handle ?? 0n,
+  );
+  const view = new DataView(rawStruct.buffer);
+  const stats: LatencyStats = {
+    samples: view.getUint32(0, true),
+    timeouts: view.getUint32(4, true),
+    minMs: view.getFloat64(8, true),
+    maxMs: view.getFloat64(16, true),
+    meanMs: view.getFloat64(24, true),
+    medianMs: view.getFloat64(32, true),
+    p95Ms: view.getFloat64(40, true),
+    stddevMs: view.getFloat64(48, true),
+  };
+
+  if (stats.samples === 0 && stats.timeouts === 0) {
+    const error = getLastError();
+    throw new Error(
+      `Failed to measure latency for monitor index ${monitorIndex}: ${
+        error || "No samples taken"
+      }`,
+    );
+  }
+
+  return stats;
+}
+
+/** Color of a screen patch averaged over its pixels and over time. */
+export interface PatchSample {
+  /** Number of captures averaged. */
+  samples: number;
+  /** Number of captured pixels the patch covered in each. */
+  pixels: number;
+  /** Mean linear-light red in sRGB primaries, 1 for the display's white. */
+  r: number;
+  g: number;
+  b: number;
+  /** Mean relative luminance (CIE Y), 1 for the display's white. */
+  luminance: number;
+  /** Standard deviation of the luminance between samples. */
+  luminanceStddev: number;
+  /** Whether the monitor's ICC profile was applied; otherwise sRGB was assumed. */
+  profileApplied: boolean;
+}
+
+/**
+ * Measures the linear-light color of a patch of a monitor, averaged over its
+ * pixels and over `samples` captures, for display calibration and ambient-light
+ * tools. The monitor's ICC profile is applied where the platform publishes it
+ * (X11 color management).
+ *
+ * @param monitorIndex The index of the monitor (from MonitorInfo.index).
+ * @param rect The patch, relative to the monitor's top-left corner.
+ * @param samples Number of captures to average.
+ * @param intervalMs Time between captures.
+ * @param cancel Stops sampling early; the captures taken so far are averaged.
+ * @throws Error if the patch isn't inside the monitor or capturing fails.
+ */
+export async function samplePatch(
+  monitorIndex: bigint,
+  rect: Rect,
+  samples = 10,
+  intervalMs = 50,
+  cancel?: CancelToken,
+): Promise<PatchSample> {
+  const rawRect = new Uint8Array(16);
+  const rectView = new DataView(rawRect.buffer);
+  rectView.setInt32(0, rect.x, true);
+  rectView.setInt32(4, rect.y, true);
+  rectView.setUint32(8, rect.width, true);
+  rectView.setUint32(12, rect.height, true);
+  const rawStruct = await library.symbols.capture_sample_patch(
+    monitorIndex,
+    rawRect,
+    samples,
+    intervalMs,
+    cancel?.handle ?? 0n,
+  );
+  // Layout of PatchSample: u32 samples, u32 pixels, 5 × f64, bool profile_applied
+  const view = new DataView(rawStruct.buffer);
+  const sample: PatchSample = {
+    samples: view.getUint32(0, true),
+    pixels: view.getUint32(4, true),
+    r: view.getFloat64(8, true),
+    g: view.getFloat64(16, true),
+    b: view.getFloat64(24, true),
+    luminance: view.getFloat64(32, true),
+    luminanceStddev: view.getFloat64(40, true),
+    profileApplied: view.getUint8(48) !== 0,
+  };
+  if (sample.samples === 0) {
+    throw new Error(
+      `Failed to sample monitor index ${monitorIndex}: ${
+        getLastError() || "No samples taken"
+      }`,
+    );
+  }
+  return sample;
+}
+
+/** Layout of the pixels in delivered frames. */
+export type PixelFormat = "rgba8";
+/** Whether the mouse cursor is part of delivered frames, and who drew it. */
+export type CursorMode = "hidden" | "os" | "library" | "unknown";
+/** How the pixel values of delivered frames should be interpreted. */
+export type ColorSpace = "srgb" | "display-native";
+
+const PIXEL_FORMATS: PixelFormat[] = ["rgba8"];
+const CURSOR_MODES: CursorMode[] = ["hidden", "os", "library", "unknown"];
+const COLOR_SPACES: ColorSpace[] = ["srgb", "display-native"];
+
+/**
+ * Parameters a stream actually runs with, as opposed to what was requested.
+ */
+export interface StreamInfo {
+  /** Width of delivered frames in pixels. */
+  width: number;
+  /** Height of delivered frames in pixels. */
+  height: number;
+  pixelFormat: PixelFormat;
+  /** Upper bound on delivered frames per second. */
+  maxFps: number;
+  cursor: CursorMode;
+  colorSpace: ColorSpace;
+}
+
+/**
+ * Options for {@link startStream}.
+ */
+export interface StreamOptions {
+  /** Frame rate cap; the default lets the library pick. Never exceeds the monitor's refresh rate. */
+  maxFps?: number;
+  /**
+   * Stops the stream natively when cancelled, as if by a stream error; call
+   * {@link CaptureStream.stop} afterwards to release it.
+   */
+  cancel?: CancelToken;
+}
+
+/**
+ * A running capture stream. Call {@link CaptureStream.stop} to release it.
+ */
+export class CaptureStream {
+  #handle: bigint;
+  #callback: Deno.UnsafeCallback<typeof FRAME_CALLBACK_DEF>;
+  /** The parameters negotiated with the backend when the stream started. */
+  readonly info: StreamInfo;
+
+  /** @internal Use {@link startStream} instead. */
+  constructor(
+    handle: bigint,
+    callback: Deno.UnsafeCallback<typeof FRAME_CALLBACK_DEF>,
+  ) {
+    this.#handle = handle;
+    this.#callback = callback;
+    const view = new DataView(
+      library.symbols.capture_stream_info(handle).buffer,
+    );
+    this.info = {
+      width: view.getUint32(0, true),
+      height: view.getUint32(4, true),
+      pixelFormat: PIXEL_FORMATS[view.getInt32(8, true)],
+      maxFps: view.getUint32(12, true),
+      cursor: CURSOR_MODES[view.getInt32(16, true)],
+      colorSpace: COLOR_SPACES[view.getInt32(20, true)],
+    };
+  }
+
+  /** Stops the stream; no frames are delivered after the returned Promise resolves. */
+  async stop(): Promise<void> {
+    if (this.#handle === 0n) {
+      return;
+    }
+    const handle = this.#handle;
+    this.#handle = 0n;
+    await library.symbols.capture_stream_stop(handle);
+    this.#callback.close();
+  }
+}
+
+/**
+ * Starts capturing a monitor continuously on a native background thread.
+ * @param monitorIndex The index of the monitor (from MonitorInfo.index).
+ * @param onFrame Called with every captured frame.
+ * @param options Stream options.
+ * @returns The running stream; its `info` says what the backend actually delivers.
+ * @throws Error if the monitor index is invalid or the first capture fails.
+ */
+export function startStream(
+  monitorIndex: bigint,
+  onFrame: (frame: CapturedImageData) => void,
+  options: StreamOptions = {},
+): CaptureStream {
+  const callback = Deno.UnsafeCallback.threadSafe(
+    FRAME_CALLBACK_DEF,
+    (rawStruct) => {
+      const frame = takeCapturedImage(rawStruct);
+      if (frame) {
+        onFrame(frame);
+      }
+    },
+  );
+  const handle = library.symbols.capture_stream_start_ex(
+    monitorIndex,
+    options.maxFps ?? 0,
+    callback.pointer,
+    null,
+    options.cancel?.handle ?? 0n,
+  );
+  if (handle === 0n) {
+    callback.close();
+    const error = getLastError();
+    throw new Error(
+      `Failed to start stream for monitor index ${monitorIndex}: ${
+        error || "Unknown error"
+      }`,
+    );
+  }
+  return new CaptureStream(handle, callback);
+}
+
+/** A rectangle in desktop coordinates. */
+export interface Rect {
+  x: number;
+  y: number;
+  width: number;
+  height: number;
+}
+
+/** Options for {@link watchRegion}. */
+export interface WatchOptions {
+  /** How often to check the region, in milliseconds (at least 10). Defaults to 250. */
+  intervalMs?: number;
+  /**
+   * Fraction of the region's pixels that must change before it is reported,
+   * from 0 (any change, the default) up to but excluding 1.
+   */
+  threshold?: number;
+  /** Stops the watch natively when cancelled; call {@link RegionWatch.stop} afterwards. */
+  cancel?: CancelToken;
+}
+
+/**
+ * A running region watch. Call {@link RegionWatch.stop} to release it.
+ */
+export class RegionWatch {
+  #handle: bigint;
+  #callback: Deno.UnsafeCallback<typeof FRAME_CALLBACK_DEF>;
+
+  /** @internal Use {@link watchRegion} instead. */
+  constructor(
+    handle: bigint,
+    callback: Deno.UnsafeCallback<typeof FRAME_CALLBACK_DEF>,
+  ) {
+    this.#handle = handle;
+    this.#callback = callback;
+  }
+
+  /** Stops watching; `onChange` isn't called after the returned Promise resolves. */
+  async stop(): Promise<void> {
+    if (this.#handle === 0n) {
+      return;
+    }
+    const handle = this.#handle;
+    this.#handle = 0n;
+    await library.symbols.capture_region_watch_stop(handle);
+    this.#callback.close();
+  }
+}
+
+/**
+ * Watches a rectangle of the screen and calls `onChange` with an image of just
+ * that region: once right away, then whenever it changes. Cheaper than a
+ * stream for watching a progress bar, a log pane or a game HUD.
+ * @param rect The region in desktop coordinates; must lie within one monitor.
+ * @param onChange Receives each new image of the region.
+ * @throws Error if the region is invalid or can't be captured.
+ */
+export function watchRegion(
+  rect: Rect,
+  onChange: (image: CapturedImageData) => void,
+  options: WatchOptions = {},
+): RegionWatch {
+  const callback = Deno.UnsafeCallback.threadSafe(
+    FRAME_CALLBACK_DEF,
+    (rawStruct) => {
+      const image = takeCapturedImage(rawStruct);
+      if (image) {
+        onChange(image);
+      }
+    },
+  );
+  const rawRect = new Uint8Array(16);
+  const view = new DataView(rawRect.buffer);
+  view.setInt32(0, rect.x, true);
+  view.setInt32(4, rect.y, true);
+  view.setUint32(8, rect.width, true);
+  view.setUint32(12, rect.height, true);
+  const handle = library.symbols.capture_region_watch(
+    rawRect,
+    options.intervalMs ?? 250,
+    options.threshold ?? 0,
+    callback.pointer,
+    null,
+    options.cancel?.handle ?? 0n,
+  );
+  if (handle === 0n) {
+    callback.close();
+    const error = getLastError();
+    throw new Error(`Failed to watch region: ${error || "Unknown error"}`);
+  }
+  return new RegionWatch(handle, callback);
+}
+
+/** Size and progress of a {@link Heatmap}. */
+export interface HeatmapInfo {
+  /** Size of the map in cells. */
+  width: number;
+  height: number;
+  /** Monitor pixels each cell covers along either side. */
+  cellSize: number;
+  /** Captures taken so far. */
+  samples: bigint;
+  /** Time between captures in milliseconds. */
+  intervalMs: number;
+}
+
+/**
+ * A running map of how static each part of a monitor stays, created by
+ * {@link startHeatmap}.
+ */
+export class Heatmap {
+  #handle: bigint;
+
+  /** @internal Use {@link startHeatmap} instead. */
+  constructor(handle: bigint) {
+    this.#handle = handle;
+  }
+
+  /**
+   * Renders the map so far, one pixel per cell, from black (changed between
+   * every pair of captures) to white (never changed).
+   * @throws Error if the heatmap has been stopped.
+   */
+  get(): CapturedImageData {
+    const image = takeCapturedImage(
+      library.symbols.capture_heatmap_get(this.#handle),
+    );
+    if (!image) {
+      throw new Error(
+        `Failed to get heatmap: ${getLastError() || "Unknown error"}`,
+      );
+    }
+    return image;
+  }
+
+  /**
+   * Gets the map's size and how many captures it has taken.
+   * @throws Error if the heatmap has been stopped.
+   */
+  info(): HeatmapInfo {
+    const rawStruct = library.symbols.capture_heatmap_info(this.#handle);
+    // Layout of HeatmapInfo: u32 width, u32 height, f64 cell_size,
+    // u64 samples, u32 interval_ms
+    const view = new DataView(rawStruct.buffer);
+    const info: HeatmapInfo = {
+      width: view.getUint32(0, true),
+      height: view.getUint32(4, true),
+      cellSize: view.getFloat64(8, true),
+      samples: view.getBigUint64(16, true),
+      intervalMs: view.getUint32(24, true),
+    };
+    if (info.width === 0) {
+      throw new Error(
+        `Failed to get heatmap info: ${getLastError() || "Unknown error"}`,
+      );
+    }
+    return info;
+  }
+
+  /** Stops sampling and discards the map; call {@link get} first to keep it. */
+  async stop(): Promise<void> {
+    if (this.#handle === 0n) {
+      return;
+    }
+    const handle = this.#handle;
+    this.#handle = 0n;
+    await library.symbols.capture_heatmap_stop(handle);
+  }
+}
+
+/**
+ * Starts mapping how static each part of a monitor stays over time, e.g. to
+ * find content at risk of OLED burn-in. Meant to run for hours; memory stays
+ * bounded by the map size.
+ * @param monitorIndex The index of the monitor (from MonitorInfo.index).
+ * @param options.intervalMs Time between captures (default 1000).
+ * @param options.maxSide Cells along the map's longer side (default 256).
+ * @param options.cancel Stops sampling; the map stays readable.
+ * @throws Error if the monitor can't be captured.
+ */
+export async function startHeatmap(
+  monitorIndex: bigint,
+  options: { intervalMs?: number; maxSide?: number; cancel?: CancelToken } =
+    {},
+): Promise<Heatmap> {
+  const handle = await library.symbols.capture_heatmap_start(
+    monitorIndex,
+    options.intervalMs ?? 0,
+    options.maxSide ?? 0,
+    options.cancel?.handle ?? 0n,
+  );
+  if (handle === 0n) {
+    throw new Error(
+      `Failed to start heatmap: ${getLastError() || "Unknown error"}`,
+    );
+  }
+  return new Heatmap(handle);
+}
+
+/** One monitor or window placed in a composite recording. */
+export interface CompositeLayer {
+  target: CaptureTarget;
+  /** Part of the target to show, in its captured pixels. Defaults to all of it. */
+  source?: Rect;
+  /** Where the layer's top-left corner goes in the output frame. Defaults to 0. */
+  x?: number;
+  /** Defaults to 0. */
+  y?: number;
+  /** Size relative to the source, up to 8. Defaults to 1. */
+  scale?: number;
+  /**
+   * Layers with a higher `z` are drawn over lower ones, and layers with the
+   * same `z` in array order. Defaults to 0.
+   */
+  z?: number;
+  /** From 0 (invisible) to 1 (the default). */
+  opacity?: number;
+  /** Radius of rounded corners in output pixels. Defaults to 0. */
+  cornerRadius?: number;
+  /** Width of a border inside the layer's edge in output pixels. Defaults to 0. */
+  borderWidth?: number;
+  /** Border color as 0xRRGGBBAA; defaults to opaque white. */
+  borderColor?: number;
+}
+
+/** Options for {@link startComposite}. */
+export interface CompositeOptions {
+  /** Output frame width; defaults to fitting the layers. */
+  width?: number;
+  /** Output frame height; defaults to fitting the layers. */
+  height?: number;
+  /** Frame rate cap; defaults to 30. */
+  maxFps?: number;
+  /** Color of uncovered areas as 0xRRGGBBAA; defaults to opaque black. */
+  background?: number;
+  /** Stops the recording natively when cancelled; call {@link CompositeRecording.stop} afterwards. */
+  cancel?: CancelToken;
+}
+
+/** Builds the native array of CompositeLayer structs. */
+function compositeLayers(layers: CompositeLayer[]): Uint8Array {
+  const template = library.symbols.capture_composite_layer_default();
+  // Layout of CompositeLayer: usize struct_size, CaptureTarget target (8),
+  // CaptureRect source (16), i32 x, i32 y, u32 scale, i32 z, u32 opacity,
+  // u32 corner_radius, u32 border_width, u32 border_color
+  const size = template.byteLength;
+  const raw = new Uint8Array(size * layers.length);
+  layers.forEach((layer, i) => {
+    raw.set(template, i * size);
+    const view = new DataView(raw.buffer, i * size, size);
+    raw.set(captureTarget(layer.target), i * size + 8);
+    if (layer.source) {
+      view.setInt32(16, layer.source.x, true);
+      view.setInt32(20, layer.source.y, true);
+      view.setUint32(24, layer.source.width, true);
+      view.setUint32(28, layer.source.height, true);
+    }
+    view.setInt32(32, layer.x ?? 0, true);
+    view.setInt32(36, layer.y ?? 0, true);
+    view.setUint32(40, Math.round((layer.scale ?? 1) * 100), true);
+    view.setInt32(44, layer.z ?? 0, true);
+    if (layer.opacity !== undefined) {
+      view.setUint32(48, Math.round(layer.opacity * 100), true);
+    }
+    view.setUint32(52, layer.cornerRadius ?? 0, true);
+    view.setUint32(56, layer.borderWidth ?? 0, true);
+    if (layer.borderColor !== undefined) {
+      view.setUint32(60, layer.borderColor, true);
+    }
+  });
+  return raw;
+}
+
+/**
+ * A running composite recording. Call {@link CompositeRecording.stop} to release it.
+ */
+export class CompositeRecording {
+  #handle: bigint;
+  #callback: Deno.UnsafeCallback<typeof FRAME_CALLBACK_DEF>;
+
+  /** @internal Use {@link startComposite} instead. */
+  constructor(
+    handle: bigint,
+    callback: Deno.UnsafeCallback<typeof FRAME_CALLBACK_DEF>,
+  ) {
+    this.#handle = handle;
+    this.#callback = callback;
+  }
+
+  /** Stops recording; no frames are delivered after the returned Promise resolves. */
+  async stop(): Promise<void> {
+    if (this.#handle === 0n) {
+      return;
+    }
+    const handle = this.#handle;
+    this.#handle = 0n;
+    await library.symbols.capture_composite_stop(handle);
+    this.#callback.close();
+  }
+}
+
+/**
+ * Records several monitors and windows into one frame on a native background
+ * thread, e.g. a window with a region of another monitor picture-in-picture.
+ * The recording reports the same events as a stream.
+ * @param layers The targets to draw, with their placement.
+ * @param onFrame Called with every composited frame.
+ * @throws Error if a layer is invalid or the first frame can't be captured.
+ */
+export async function startComposite(
+  layers: CompositeLayer[],
+  onFrame: (frame: CapturedImageData) => void,
+  options: CompositeOptions = {},
+): Promise<CompositeRecording> {
+  const callback = Deno.UnsafeCallback.threadSafe(
+    FRAME_CALLBACK_DEF,
+    (rawStruct) => {
+      const frame = takeCapturedImage(rawStruct);
+      if (frame) {
+        onFrame(frame);
+      }
+    },
+  );
+  const rawOptions = library.symbols.capture_composite_options_default();
+  // Layout of CompositeOptions: usize struct_size, u32 width, u32 height,
+  // u32 max_fps, u32 background
+  const view = new DataView(rawOptions.buffer);
+  view.setUint32(8, options.width ?? 0, true);
+  view.setUint32(12, options.height ?? 0, true);
+  view.setUint32(16, options.maxFps ?? 0, true);
+  if (options.background !== undefined) {
+    view.setUint32(20, options.background, true);
+  }
+  const handle = await library.symbols.capture_composite_start(
+    compositeLayers(layers),
+    BigInt(layers.length),
+    rawOptions,
+    callback.pointer,
+    null,
+    options.cancel?.handle ?? 0n,
+  );
+  if (handle === 0n) {
+    callback.close();
+    throw new Error(
+      `Failed to start composite recording: ${
+        getLastError() || "Unknown error"
+      }`,
+    );
+  }
+  return new CompositeRecording(handle, callback);
+}
+
+/** Kinds of events reported by the native library. */
+export type CaptureEventType =
+  | "stream-started"
+  | "stream-stopped"
+  | "stream-error"
+  | "watch-error"
+  | "stream-degraded";
+
+const EVENT_TYPES: CaptureEventType[] = [
+  "stream-started",
+  "stream-stopped",
+  "stream-error",
+  "watch-error",
+  "stream-degraded",
+];
+
+/** An event reported by the native library. */
+export interface CaptureEvent {
+  type: CaptureEventType;
+  /** Handle of the native object the event is about (e.g. a stream). */
+  source: bigint;
+  /** When the event happened, in milliseconds since the Unix epoch. */
+  timestamp: number;
+  /** Details, if any. */
+  message: string | null;
+}
+
+/**
+ * The current event listener, registered by ID so the library never calls it
+ * after it has been closed.
+ */
+let eventCallback: {
+  callback: Deno.UnsafeCallback<typeof EVENT_CALLBACK_DEF>;
+  id: number;
+} | null = null;
+
+/**
+ * Sets the function receiving native library events, replacing any previous one.
+ * Pass null to stop receiving events.
+ */
+export function setEventListener(
+  listener: ((event: CaptureEvent) => void) | null,
+): void {
+  const previous = eventCallback;
+  const callback = listener &&
+    Deno.UnsafeCallback.threadSafe(EVENT_CALLBACK_DEF, (eventPtr) => {
+      if (eventPtr === null) {
+        return;
+      }
+      // Layout of CaptureEvent: i32 type, u64 source, u64 timestamp, pointer message
+      const view = new Deno.UnsafePointerView(eventPtr);
+      const messagePtr = view.getPointer(24);
+      listener({
+        type: EVENT_TYPES[view.getInt32(0)],
+        source: view.getBigUint64(8),
+        timestamp: Number(view.getBigUint64(16)),
+        message: messagePtr === null
+          ? null
+          : new Deno.UnsafePointerView(messagePtr).getCString(),
+      });
+    });
+  const kind = getEnums().enums.CallbackKind.CAPTURE_CALLBACK_EVENT;
+  let next: typeof eventCallback = null;
+  if (callback) {
+    const id = library.symbols.capture_callback_register(
+      kind,
+      callback.pointer,
+      null,
+    );
+    if (id === 0) {
+      callback.close();
+      throw new Error(
+        `Failed to set event listener: ${getLastError() || "Unknown error"}`,
+      );
+    }
+    next = { callback, id };
+  }
+  library.symbols.capture_set_event_callback(
+    next && library.symbols.capture_callback_trampoline(kind),
+    next && Deno.UnsafePointer.create(BigInt(next.id)),
+  );
+  eventCallback = next;
+  if (previous) {
+    // A library thread may still be inside the previous listener; the
+    // unregister resolves once it has returned.
+    library.symbols.capture_callback_unregister(previous.id).then(() =>
+      previous.callback.close()
+    );
+  }
 }
 
 /**
@@ -0,0 +1,751 @@
+// capture-ffi/src/archive.rs
+//! Lossless archives of recorded frames, for medical, legal and other archival captures where
+//! JPEG artifacts aren't acceptable, plus size estimates to weigh that against disk space first.
+//!
+//! An archive is an 8-byte header, "XCLA", a version byte (1), a codec byte (0 for zlib) and two
+//! zero bytes, followed by frames, each a 24-byte header, all integers little-endian:
+//!
+//! ```text
+//! offset  size  field
+//!      0     1  kind: 0 keyframe, 1 delta
+//!      1     3  zero
+//!      4     4  width
+//!      8     4  height
+//!     12     8  capture time, Unix milliseconds
+//!     20     4  length of the compressed data that follows
+//! ```
+//!
+//! and then a zlib stream of the frame's tightly packed RGBA rows for a keyframe, or of their
+//! bytewise differences (wrapping) from the previous frame for a delta; static screen content
+//! differs in zeros, which compress to almost nothing.
+//!
+//! zlib stands in for zstd, which isn't a dependency yet; the codec byte leaves room to add it
+//! without changing the version.
+
+use crate::{
+    CaptureError, CapturedImage, ErrorCode, buffer, config,
+    encode::{CaptureFd, borrow_fd, read_path},
+    event::now_ms,
+    handle::{CaptureHandle, HandleType, Registry},
+    limits,
+    options::read_sized,
+    output::{self, CollisionPolicy, TemplateFields},
+    set_last_error, usage,
+};
+use flate2::{Compression, read::ZlibDecoder, write::ZlibEncoder};
+use image::RgbaImage;
+use libc::{c_char, c_int, c_uint, size_t};
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    mem,
+    path::PathBuf,
+    ptr,
+    sync::Mutex,
+};
+
+const MAGIC: &[u8; 4] = b"XCLA";
+const VERSION: u8 = 1;
+const CODEC_ZLIB: u8 = 0;
+const KEYFRAME: u8 = 0;
+const DELTA: u8 = 1;
+const FRAME_HEADER_LEN: usize = 24;
+/// zlib level used when ArchiveOptions.level is 0.
+const DEFAULT_LEVEL: c_int = 6;
+/// Keyframe interval used when ArchiveOptions.keyframe_interval is 0.
+const DEFAULT_KEYFRAME_INTERVAL: c_uint = 300;
+/// Frame rate capture_archive_estimate() assumes when passed 0.
+const DEFAULT_FPS: c_uint = 30;
+const MS_PER_HOUR: u64 = 3_600_000;
+/// Largest frame an archive takes, in bytes of RGBA: the image decoder's default allocation
+/// limit. Readers reject bigger frame headers as damage before allocating anything.
+const MAX_FRAME_BYTES: u64 = 512 * 1024 * 1024;
+
+fn frame_bytes(width: u32, height: u32) -> u64 {
+    width as u64 * height as u64 * 4
+}
+
+/// Options for capture_archive_open() and capture_archive_estimate(). Versioned like
+/// CaptureOptions: always start from capture_archive_options_default().
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct ArchiveOptions {
+    /// Size of this struct in bytes as known by the caller.
+    pub struct_size: size_t,
+    /// zlib compression level from 1 (fastest) to 9 (smallest); 0 means 6. Frames come out
+    /// bit-exact either way.
+    pub level: c_int,
+    /// Frames between keyframes; 0 means 300. A damaged frame corrupts the frames after it up to
+    /// the next keyframe, so shorter intervals limit the loss at the cost of size.
+    pub keyframe_interval: c_uint,
+    /// A CollisionPolicy (CAPTURE_COLLISION_*) for when the archive file already exists.
+    /// Defaults to failing, so a recording never replaces an earlier one.
+    pub collision: c_int,
+    /// Non-zero syncs the archive to disk when it is closed. Off by default.
+    pub fsync: c_int,
+}
+
+impl Default for ArchiveOptions {
+    fn default() -> Self {
+        ArchiveOptions {
+            struct_size: mem::size_of::<ArchiveOptions>(),
+            level: 0,
+            keyframe_interval: 0,
+            collision: CollisionPolicy::Error as c_int,
+            fsync: 0,
+        }
+    }
+}
+
+impl ArchiveOptions {
+    /// Reads caller-provided options like CaptureOptions::read().
+    ///
+    /// # Safety
+    /// `options` must be NULL or point to at least `(*options).struct_size` readable bytes.
+    unsafe fn read(options: *const ArchiveOptions) -> Result<Self, CaptureError> {
+        let resolved = match options.is_null() {
+            true => ArchiveOptions::default(),
+            false => unsafe {
+                read_sized(
+                    options,
+                    ArchiveOptions::default(),
+                    "ArchiveOptions",
+                    "capture_archive_options_default",
+                )?
+            },
+        };
+        resolved.compression()?;
+        resolved.collision_policy()?;
+        Ok(resolved)
+    }
+
+    fn compression(&self) -> Result<Compression, CaptureError> {
+        match self.level {
+            0 => Ok(Compression::new(DEFAULT_LEVEL as u32)),
+            1..=9 => Ok(Compression::new(self.level as u32)),
+            level => Err(CaptureError::new(
+                ErrorCode::InvalidArgument,
+                format!("Compression level must be between 1 and 9, got {}", level),
+            )),
+        }
+    }
+
+    fn collision_policy(&self) -> Result<CollisionPolicy, CaptureError> {
+        CollisionPolicy::from_raw(self.collision).ok_or_else(|| {
+            CaptureError::new(
+                ErrorCode::InvalidArgument,
+                format!("Unknown collision policy: {}", self.collision),
+            )
+        })
+    }
+
+    fn keyframe_interval(&self) -> c_uint {
+        match self.keyframe_interval {
+            0 => DEFAULT_KEYFRAME_INTERVAL,
+            interval => interval,
+        }
+    }
+}
+
+/// What an archive has taken so far, as returned by capture_archive_stats().
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct ArchiveStats {
+    /// Frames written, and how many of them were keyframes.
+    pub frames: u64,
+    pub keyframes: u64,
+    /// Size the frames would take uncompressed.
+    pub raw_bytes: u64,
+    /// Size of the archive file so far.
+    pub written_bytes: u64,
+    /// Time between the first and the latest frame in milliseconds.
+    pub duration_ms: u64,
+    /// Archive size an hour of recording at the rate so far would take, or 0 before the second
+    /// frame.
+    pub bytes_per_hour: u64,
+}
+
+/// Projected archive size for recording content like two sample frames, as returned by
+/// capture_archive_estimate().
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct ArchiveEstimate {
+    /// Size of one frame uncompressed.
+    pub raw_bytes_per_frame: u64,
+    /// Size of the first frame stored as a keyframe.
+    pub keyframe_bytes: u64,
+    /// Size of the second frame stored as a delta from the first.
+    pub delta_bytes: u64,
+    /// Archive size of an hour of recording at the given frame rate, if every frame changes as
+    /// much as the second did.
+    pub bytes_per_hour: u64,
+}
+
+/// Compresses a frame as a keyframe, or as a delta from `previous`, which has the same size.
+fn compress(
+    image: &RgbaImage,
+    previous: Option<&RgbaImage>,
+    level: Compression,
+) -> io::Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), level);
+    match previous {
+        None => encoder.write_all(image.as_raw())?,
+        Some(previous) => {
+            // Differences of one row at a time, so no second full-size buffer is made.
+            let row_len = image.width() as usize * 4;
+            let mut row = vec![0; row_len];
+            for (new, old) in image
+                .as_raw()
+                .chunks_exact(row_len)
+                .zip(previous.as_raw().chunks_exact(row_len))
+            {
+                for ((out, new), old) in row.iter_mut().zip(new).zip(old) {
+                    *out = new.wrapping_sub(*old);
+                }
+                encoder.write_all(&row)?;
+            }
+        }
+    }
+    encoder.finish()
+}
+
+fn frame_header(kind: u8, image: &RgbaImage, timestamp_ms: u64, len: usize) -> [u8; 24] {
+    let mut header = [0; FRAME_HEADER_LEN];
+    header[0] = kind;
+    header[4..8].copy_from_slice(&image.width().to_le_bytes());
+    header[8..12].copy_from_slice(&image.height().to_le_bytes());
+    header[12..20].copy_from_slice(&timestamp_ms.to_le_bytes());
+    header[20..24].copy_from_slice(&(len as u32).to_le_bytes());
+    header
+}
+
+struct Writer {
+    file: BufWriter<File>,
+    /// The last frame written, which the next delta is taken from.
+    previous: Option<RgbaImage>,
+    since_keyframe: c_uint,
+    first_ms: u64,
+    stats: ArchiveStats,
+    /// Set once writing to the file fails, after which it may end in a partial frame that any
+    /// further frame would be written behind, unreachable for readers.
+    failed: bool,
+}
+
+/// An archive being recorded, created by capture_archive_open().
+struct Archive {
+    level: Compression,
+    keyframe_interval: c_uint,
+    fsync: bool,
+    writer: Mutex<Writer>,
+}
+
+static ARCHIVES: Registry<Archive> = Registry::new(HandleType::Archive);
+
+impl Archive {
+    fn write(&self, image: RgbaImage) -> Result<(), CaptureError> {
+        if frame_bytes(image.width(), image.height()) > MAX_FRAME_BYTES {
+            return Err(CaptureError::new(
+                ErrorCode::TooLarge,
+                format!(
+                    "{}x{} frame is over the archive limit of {} MiB",
+                    image.width(),
+                    image.height(),
+                    MAX_FRAME_BYTES >> 20
+                ),
+            ));
+        }
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        let writer = &mut *writer;
+        if writer.failed {
+            return Err(CaptureError::new(
+                ErrorCode::Io,
+                "Archive takes no more frames after an earlier write error",
+            ));
+        }
+        let previous = writer
+            .previous
+            .as_ref()
+            .filter(|previous| previous.dimensions() == image.dimensions())
+            .filter(|_| writer.since_keyframe < self.keyframe_interval);
+        let kind = if previous.is_some() { DELTA } else { KEYFRAME };
+        let data = compress(&image, previous, self.level)?;
+        let timestamp_ms = now_ms();
+        let header = frame_header(kind, &image, timestamp_ms, data.len());
+        let file = &mut writer.file;
+        // Flushed frame by frame, so a crash loses at most the frame being written.
+        let written = file
+            .write_all(&header)
+            .and_then(|()| file.write_all(&data))
+            .and_then(|()| file.flush());
+        if let Err(err) = written {
+            writer.failed = true;
+            return Err(err.into());
+        }
+
+        let stats = &mut writer.stats;
+        if stats.frames == 0 {
+            writer.first_ms = timestamp_ms;
+        }
+        stats.frames += 1;
+        stats.raw_bytes += image.as_raw().len() as u64;
+        stats.written_bytes += (FRAME_HEADER_LEN + data.len()) as u64;
+        stats.duration_ms = timestamp_ms.saturating_sub(writer.first_ms);
+        if stats.duration_ms > 0 {
+            stats.bytes_per_hour = (stats.written_bytes as u128 * MS_PER_HOUR as u128
+                / stats.duration_ms as u128) as u64;
+        }
+        if kind == KEYFRAME {
+            stats.keyframes += 1;
+            writer.since_keyframe = 0;
+        }
+        writer.since_keyframe += 1;
+        writer.previous = Some(image);
+        Ok(())
+    }
+}
+
+fn open(path: &str, options: &ArchiveOptions) -> Result<CaptureHandle, CaptureError> {
+    let path = output::expand_template(
+        path,
+        &TemplateFields {
+            monitor: "",
+            title: "",
+        },
+    )?;
+    let (path, file) = output::create_target(&path, options.collision_policy()?)?;
+    let file = write_header(file)
+        .map_err(|e| CaptureError::from(e).context(format!("Error writing {}", path.display())))?;
+    start(file, options)
+}
+
+fn open_fd(fd: CaptureFd, options: &ArchiveOptions) -> Result<CaptureHandle, CaptureError> {
+    // A duplicate, so the archive can keep writing, and close it, independently of the caller.
+    // SAFETY: the caller promised `fd` stays open until this returns; the borrow ends here.
+    let file = unsafe { borrow_fd(fd)? }
+        .try_clone()
+        .map_err(|e| CaptureError::from(e).context("Error duplicating file descriptor"))?;
+    let file = write_header(file)
+        .map_err(|e| CaptureError::from(e).context("Error writing to file descriptor"))?;
+    start(file, options)
+}
+
+fn write_header(file: File) -> io::Result<BufWriter<File>> {
+    let mut file = BufWriter::new(file);
+    file.write_all(MAGIC)?;
+    file.write_all(&[VERSION, CODEC_ZLIB, 0, 0])?;
+    file.flush()?;
+    Ok(file)
+}
+
+fn start(file: BufWriter<File>, options: &ArchiveOptions) -> Result<CaptureHandle, CaptureError> {
+    Ok(ARCHIVES.insert(Archive {
+        level: options.compression()?,
+        keyframe_interval: options.keyframe_interval(),
+        fsync: options.fsync != 0,
+        writer: Mutex::new(Writer {
+            file,
+            previous: None,
+            since_keyframe: 0,
+            first_ms: 0,
+            stats: ArchiveStats {
+                written_bytes: 8,
+                ..ArchiveStats::default()
+            },
+            failed: false,
+        }),
+    }))
+}
+
+fn estimate(
+    first: &CapturedImage,
+    second: &CapturedImage,
+    options: &ArchiveOptions,
+    fps: c_uint,
+) -> Result<ArchiveEstimate, CaptureError> {
+    let (first, second) = (buffer::to_rgba(first)?, buffer::to_rgba(second)?);
+    if first.dimensions() != second.dimensions() {
+        return Err(CaptureError::new(
+            ErrorCode::InvalidArgument,
+            format!(
+                "Sample frames differ in size: {}x{} and {}x{}",
+                first.width(),
+                first.height(),
+                second.width(),
+                second.height()
+            ),
+        ));
+    }
+    let level = options.compression()?;
+    let keyframe_bytes = (FRAME_HEADER_LEN + compress(&first, None, level)?.len()) as u64;
+    let delta_bytes = (FRAME_HEADER_LEN + compress(&second, Some(&first), level)?.len()) as u64;
+    let frames_per_hour = match fps {
+        0 => DEFAULT_FPS,
+        fps => fps,
+    } as u64
+        * 3600;
+    let interval = options.keyframe_interval() as u64;
+    let keyframes = frames_per_hour.div_ceil(interval);
+    Ok(ArchiveEstimate {
+        raw_bytes_per_frame: first.as_raw().len() as u64,
+        keyframe_bytes,
+        delta_bytes,
+        bytes_per_hour: keyframes * keyframe_bytes + (frames_per_hour - keyframes) * delta_bytes,
+    })
+}
+
+fn status(result: Result<(), CaptureError>) -> c_int {
+    match result {
+        Ok(()) => ErrorCode::Ok as c_int,
+        Err(err) => {
+            let code = err.code as c_int;
+            set_last_error(err);
+            code
+        }
+    }
+}
+
+/// Returns ArchiveOptions with every field at its default value and `struct_size` filled in.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_archive_options_default() -> ArchiveOptions {
+    ArchiveOptions::default()
+}
+
+/// Creates a lossless archive at `path` to record frames into with capture_archive_write(). Every
+/// frame is stored bit-exact, zlib-compressed as a whole (keyframes) or as its difference from
+/// the previous frame (deltas), which keeps screen content that changes little small; see
+/// src/archive.rs for the file layout, and capture_archive_estimate() for what to expect. The
+/// file is written as frames arrive, so a crash loses at most the frame being written.
+/// `path` is a template like for capture_monitor_save(); an archive isn't tied to one monitor or
+/// window, so `{monitor}` and `{title}` expand to `_`. `options` may be NULL for defaults.
+/// The caller MUST call capture_archive_close() on the returned handle.
+/// Returns 0 on error; see capture_last_error_message() for details.
+///
+/// # Safety
+/// `path` must be a valid NUL-terminated UTF-8 string. `options` must be NULL or point to
+/// ArchiveOptions initialized with capture_archive_options_default().
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_archive_open(
+    path: *const c_char,
+    options: *const ArchiveOptions,
+) -> CaptureHandle {
+    let result = unsafe { read_path(path) }.and_then(|path| {
+        let options = unsafe { ArchiveOptions::read(options)? };
+        open(path, &options)
+    });
+    match result {
+        Ok(handle) => handle,
+        Err(err) => {
+            let err = err.context("Failed to open archive");
+            eprintln!("{}", err);
+            set_last_error(err);
+            0
+        }
+    }
+}
+
+/// Like capture_archive_open(), but records into an already open file descriptor (a HANDLE on
+/// Windows), e.g. a file handed out by a sandbox portal or a pipe to another process. Writing
+/// starts at the descriptor's current position. The archive writes to a duplicate of the
+/// descriptor, so the caller may close theirs at any time. ArchiveOptions.collision doesn't
+/// apply, and ArchiveOptions.fsync fails the close on descriptors that can't be synced, like
+/// pipes.
+/// The caller MUST call capture_archive_close() on the returned handle.
+/// Returns 0 on error; see capture_last_error_message() for details.
+///
+/// # Safety
+/// `fd` must be open and writable until this function returns. `options` must be NULL or point
+/// to ArchiveOptions initialized with capture_archive_options_default().
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_archive_open_fd(
+    fd: CaptureFd,
+    options: *const ArchiveOptions,
+) -> CaptureHandle {
+    let result = unsafe { ArchiveOptions::read(options) }.and_then(|options| open_fd(fd, &options));
+    match result {
+        Ok(handle) => handle,
+        Err(err) => {
+            let err = err.context("Failed to open archive");
+            eprintln!("{}", err);
+            set_last_error(err);
+            0
+        }
+    }
+}
+
+/// Appends a frame to an archive, typically one delivered by a stream, region watch or composite
+/// recording, stamped with the current time. Frames may change size; the first of a new size
+/// becomes a keyframe. Compression runs on the calling thread. The image is not consumed.
+/// Frames over 512 MiB of RGBA fail with CAPTURE_ERROR_TOO_LARGE, since no reader would load them.
+/// Returns CAPTURE_OK or an error code. After a write fails with CAPTURE_ERROR_IO the archive may
+/// end in a partial frame, which capture_archive_reader_next() reports when it gets there, so
+/// every later write fails with CAPTURE_ERROR_IO too; close it and start a new one.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_archive_write(archive: CaptureHandle, image: CapturedImage) -> c_int {
+    status(ARCHIVES.get(archive).and_then(|archive| {
+        usage::encoding(buffer::owner(image.data), || {
+            archive.write(buffer::to_rgba(&image)?)
+        })
+    }))
+}
+
+/// Gets how many frames an archive holds, how well they compressed and the file size an hour of
+/// recording would take at the rate so far.
+/// Returns a zeroed struct and sets CAPTURE_ERROR_INVALID_HANDLE if `archive` isn't an open
+/// archive.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_archive_stats(archive: CaptureHandle) -> ArchiveStats {
+    match ARCHIVES.get(archive) {
+        Ok(archive) => {
+            archive
+                .writer
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .stats
+        }
+        Err(err) => {
+            set_last_error(err);
+            ArchiveStats::default()
+        }
+    }
+}
+
+/// Closes an archive, flushing it (and syncing it to disk with ArchiveOptions.fsync), and
+/// invalidates the handle.
+/// Returns CAPTURE_OK, CAPTURE_ERROR_IO if the final flush fails, or
+/// CAPTURE_ERROR_INVALID_HANDLE if `archive` isn't an open archive.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_archive_close(archive: CaptureHandle) -> c_int {
+    status(ARCHIVES.remove(archive).and_then(|archive| {
+        let mut writer = archive.writer.lock().unwrap_or_else(|e| e.into_inner());
+        writer.file.flush()?;
+        if archive.fsync {
+            writer.file.get_ref().sync_all()?;
+        }
+        Ok(())
+    }))
+}
+
+/// Estimates the archive size of recording content like two consecutive sample frames of it,
+/// e.g. two captures a frame apart, before committing disk space to a recording: compresses
+/// the first as a keyframe and the second as a delta with `options` (may be NULL), and projects
+/// an hour at `fps` frames per second (0 means 30). Compare `raw_bytes_per_frame` for the
+/// uncompressed size. Content that moves more than between the samples takes more.
+/// Returns a zeroed struct and sets the last error if the frames are invalid or differ in size.
+///
+/// # Safety
+/// `options` must be NULL or point to ArchiveOptions initialized with
+/// capture_archive_options_default().
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_archive_estimate(
+    first: CapturedImage,
+    second: CapturedImage,
+    options: *const ArchiveOptions,
+    fps: c_uint,
+) -> ArchiveEstimate {
+    let result = unsafe { ArchiveOptions::read(options) }
+        .and_then(|options| estimate(&first, &second, &options, fps));
+    match result {
+        Ok(estimate) => estimate,
+        Err(err) => {
+            set_last_error(err);
+            ArchiveEstimate::default()
+        }
+    }
+}
+
+struct ReaderState {
+    file: BufReader<File>,
+    previous: Option<RgbaImage>,
+}
+
+/// Reads an archive back frame by frame, created by capture_archive_reader_open().
+struct ArchiveReader {
+    state: Mutex<ReaderState>,
+}
+
+static READERS: Registry<ArchiveReader> = Registry::new(HandleType::ArchiveReader);
+
+fn damaged(message: impl std::fmt::Display) -> CaptureError {
+    CaptureError::new(
+        ErrorCode::Io,
+        format!("Archive is damaged or cut off: {}", message),
+    )
+}
+
+impl ReaderState {
+    /// The next frame and its capture time, or None at the end of the archive.
+    /// Reads the next frame. A frame that fails to read is left unread, so the next call tries
+    /// it again: one that was cut off because it is still being written reads in full once the
+    /// writer has finished it.
+    fn next(&mut self) -> Result<Option<(RgbaImage, u64)>, CaptureError> {
+        let start = self.file.stream_position()?;
+        let frame = self.read_frame();
+        if frame.is_err() {
+            self.file.seek(SeekFrom::Start(start))?;
+        }
+        frame
+    }
+
+    fn read_frame(&mut self) -> Result<Option<(RgbaImage, u64)>, CaptureError> {
+        let mut header = [0; FRAME_HEADER_LEN];
+        match self.file.read(&mut header[..1])? {
+            0 => return Ok(None),
+            _ => self
+                .file
+                .read_exact(&mut header[1..])
+                .map_err(|_| damaged("truncated frame header"))?,
+        }
+        let u32_at = |offset: usize| {
+            u32::from_le_bytes(header[offset..offset + 4].try_into().unwrap_or_default())
+        };
+        let (kind, width, height) = (header[0], u32_at(4), u32_at(8));
+        let timestamp_ms = u64::from_le_bytes(header[12..20].try_into().unwrap_or_default());
+        let len = u32_at(20) as u64;
+        if width == 0 || height == 0 {
+            return Err(damaged(format!("empty {}x{} frame", width, height)));
+        }
+        if frame_bytes(width, height) > MAX_FRAME_BYTES {
+            return Err(damaged(format!(
+                "{}x{} frame is over the size limit",
+                width, height
+            )));
+        }
+        limits::check_nominal(width, height)?;
+        if self.file.stream_position()? + len > self.file.get_ref().metadata()?.len() {
+            return Err(damaged("truncated frame"));
+        }
+
+        let mut pixels = vec![0; frame_bytes(width, height) as usize];
+        let mut compressed = (&mut self.file).take(len);
+        let mut decoder = ZlibDecoder::new(&mut compressed);
+        decoder.read_exact(&mut pixels).map_err(damaged)?;
+        // Reading on to the end of the stream checks its checksum.
+        if decoder.read(&mut [0])? != 0 {
+            return Err(damaged("frame holds more data than its size"));
+        }
+        drop(decoder);
+        io::copy(&mut compressed, &mut io::sink())?;
+        if compressed.limit() > 0 {
+            return Err(damaged("truncated frame"));
+        }
+        match kind {
+            KEYFRAME => {}
+            DELTA => {
+                let previous = self
+                    .previous
+                    .as_ref()
+                    .filter(|previous| previous.dimensions() == (width, height))
+                    .ok_or_else(|| damaged("delta frame without a keyframe before it"))?;
+                for (byte, old) in pixels.iter_mut().zip(previous.as_raw()) {
+                    *byte = byte.wrapping_add(*old);
+                }
+            }
+            kind => return Err(damaged(format!("unknown frame kind {}", kind))),
+        }
+        let image = RgbaImage::from_raw(width, height, pixels)
+            .ok_or_else(|| damaged("frame size mismatch"))?;
+        self.previous = Some(image.clone());
+        Ok(Some((image, timestamp_ms)))
+    }
+}
+
+fn open_reader(path: &str) -> Result<CaptureHandle, CaptureError> {
+    let path = config::output_path(PathBuf::from(path));
+    let file = File::open(&path)
+        .map_err(|e| CaptureError::from(e).context(format!("Error reading {}", path.display())))?;
+    let mut file = BufReader::new(file);
+    let mut header = [0; 8];
+    file.read_exact(&mut header)
+        .map_err(|_| damaged("truncated file header"))?;
+    if &header[0..4] != MAGIC {
+        return Err(CaptureError::new(
+            ErrorCode::InvalidArgument,
+            format!("{} is not a capture archive", path.display()),
+        ));
+    }
+    if header[4] != VERSION {
+        return Err(CaptureError::new(
+            ErrorCode::Unsupported,
+            format!("Unsupported archive version {}", header[4]),
+        ));
+    }
+    if header[5] != CODEC_ZLIB {
+        return Err(CaptureError::new(
+            ErrorCode::Unsupported,
+            format!("Unsupported archive codec {}", header[5]),
+        ));
+    }
+    Ok(READERS.insert(ArchiveReader {
+        state: Mutex::new(ReaderState {
+            file,
+            previous: None,
+        }),
+    }))
+}
+
+/// Opens an archive written by capture_archive_open() for reading, also while it is still
+/// being recorded: a frame that isn't fully written yet reads as CAPTURE_ERROR_IO, and reading
+/// again once it is returns it. A relative `path` is resolved against the configured output
+/// directory, like the files capture_archive_open() creates.
+/// The caller MUST call capture_archive_reader_free() on the returned handle.
+/// Returns 0 on error; see capture_last_error_message() for details.
+///
+/// # Safety
+/// `path` must be a valid NUL-terminated UTF-8 string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_archive_reader_open(path: *const c_char) -> CaptureHandle {
+    match unsafe { read_path(path) }.and_then(open_reader) {
+        Ok(handle) => handle,
+        Err(err) => {
+            let err = err.context("Failed to open archive");
+            eprintln!("{}", err);
+            set_last_error(err);
+            0
+        }
+    }
+}
+
+/// Reads the next frame of an archive, exactly as it was written, and stores its capture time
+/// in Unix milliseconds in `timestamp_ms` unless that is NULL.
+/// The caller MUST call capture_free_image() on the returned struct to free the data buffer.
+/// Returns a struct with NULL data pointer and sets CAPTURE_ERROR_END_OF_DATA after the last
+/// frame, or CAPTURE_ERROR_IO if the archive is damaged or ends in a partial frame. A frame that
+/// fails to read is not skipped: the next call reads it again, which returns it once a recording
+/// still in progress has finished writing it.
+///
+/// # Safety
+/// `timestamp_ms` must be NULL or valid for writing a u64.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_archive_reader_next(
+    reader: CaptureHandle,
+    timestamp_ms: *mut u64,
+) -> CapturedImage {
+    let result = READERS.get(reader).and_then(|reader| {
+        let next = reader
+            .state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .next()?;
+        next.ok_or_else(|| CaptureError::new(ErrorCode::EndOfData, "No more frames in archive"))
+    });
+    match result {
+        Ok((image, timestamp)) => {
+            if !timestamp_ms.is_null() {
+                // SAFETY: the caller promised `timestamp_ms` is writable.
+                unsafe { ptr::write_unaligned(timestamp_ms, timestamp) };
+            }
+            CapturedImage::from_rgba(image)
+        }
+        Err(err) => {
+            set_last_error(err);
+            CapturedImage::empty()
+        }
+    }
+}
+
+/// Frees a reader handle.
+/// Returns CAPTURE_OK, or CAPTURE_ERROR_INVALID_HANDLE if `reader` isn't a live reader.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_archive_reader_free(reader: CaptureHandle) -> c_int {
+    status(READERS.remove(reader).map(drop))
+}
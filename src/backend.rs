@@ -0,0 +1,155 @@
+// capture-ffi/src/backend.rs
+//! The capture backend, picked at build time: xcap with the `native` feature, frames the host
+//! supplies with the `wasm` feature, or none at all for processing-only builds. All of them
+//! expose the same Monitor and Window API, so the rest of the library doesn't care which one it
+//! talks to.
+
+use crate::{CaptureError, ErrorCode};
+
+#[cfg(all(feature = "native", feature = "wasm"))]
+compile_error!(
+    "features `native` and `wasm` are mutually exclusive; build `wasm` with --no-default-features"
+);
+
+#[cfg(feature = "native")]
+pub(crate) use xcap::{Monitor, Window};
+
+#[cfg(feature = "wasm")]
+pub(crate) use crate::host::{Monitor, Window};
+
+#[cfg(not(any(feature = "native", feature = "wasm")))]
+pub(crate) use none::{Monitor, Window};
+
+/// Whether this build can capture anything at all.
+pub(crate) const CAPTURE: bool = cfg!(any(feature = "native", feature = "wasm"));
+
+/// Whether background threads can be started, which streams, region watches and composite
+/// recordings run on. Plain wasm32-wasip1 has none; wasm32-wasip1-threads does.
+pub(crate) const THREADS: bool = cfg!(any(not(target_family = "wasm"), target_feature = "atomics"));
+
+/// Fails with CAPTURE_ERROR_UNSUPPORTED where background threads can't be started, rather than
+/// letting the spawn abort the host.
+pub(crate) fn require_threads() -> Result<(), CaptureError> {
+    if THREADS {
+        Ok(())
+    } else {
+        Err(CaptureError::new(
+            ErrorCode::Unsupported,
+            "Background capture needs thread support, which this build doesn't have",
+        ))
+    }
+}
+
+/// The backend of processing-only builds: there are no monitors or windows, and asking for them
+/// fails with CAPTURE_ERROR_UNSUPPORTED.
+#[cfg(not(any(feature = "native", feature = "wasm")))]
+mod none {
+    use crate::{CaptureError, ErrorCode};
+    use image::RgbaImage;
+    use std::{convert::Infallible, fmt};
+
+    #[derive(Debug)]
+    pub(crate) struct NoBackend;
+
+    impl fmt::Display for NoBackend {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("This build has no capture backend")
+        }
+    }
+
+    impl From<NoBackend> for CaptureError {
+        fn from(err: NoBackend) -> Self {
+            CaptureError::new(ErrorCode::Unsupported, err.to_string())
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    pub(crate) struct Monitor {
+        never: Infallible,
+    }
+
+    impl Monitor {
+        pub(crate) fn all() -> Result<Vec<Monitor>, NoBackend> {
+            Err(NoBackend)
+        }
+
+        pub(crate) fn from_point(_x: i32, _y: i32) -> Result<Monitor, NoBackend> {
+            Err(NoBackend)
+        }
+
+        pub(crate) fn id(&self) -> u32 {
+            match self.never {}
+        }
+
+        pub(crate) fn name(&self) -> &str {
+            match self.never {}
+        }
+
+        pub(crate) fn x(&self) -> i32 {
+            match self.never {}
+        }
+
+        pub(crate) fn y(&self) -> i32 {
+            match self.never {}
+        }
+
+        pub(crate) fn width(&self) -> u32 {
+            match self.never {}
+        }
+
+        pub(crate) fn height(&self) -> u32 {
+            match self.never {}
+        }
+
+        pub(crate) fn frequency(&self) -> f32 {
+            match self.never {}
+        }
+
+        pub(crate) fn capture_image(&self) -> Result<RgbaImage, NoBackend> {
+            match self.never {}
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    pub(crate) struct Window {
+        never: Infallible,
+    }
+
+    impl Window {
+        pub(crate) fn all() -> Result<Vec<Window>, NoBackend> {
+            Err(NoBackend)
+        }
+
+        pub(crate) fn id(&self) -> u32 {
+            match self.never {}
+        }
+
+        pub(crate) fn title(&self) -> &str {
+            match self.never {}
+        }
+
+        pub(crate) fn x(&self) -> i32 {
+            match self.never {}
+        }
+
+        pub(crate) fn y(&self) -> i32 {
+            match self.never {}
+        }
+
+        pub(crate) fn width(&self) -> u32 {
+            match self.never {}
+        }
+
+        pub(crate) fn height(&self) -> u32 {
+            match self.never {}
+        }
+
+        pub(crate) fn current_monitor(&self) -> Monitor {
+            match self.never {}
+        }
+
+        pub(crate) fn capture_image(&self) -> Result<RgbaImage, NoBackend> {
+            match self.never {}
+        }
+    }
+}
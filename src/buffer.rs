@@ -0,0 +1,190 @@
+// capture-ffi/src/buffer.rs
+//! Ownership of the pixel buffers handed out in CapturedImage structs.
+//! Every buffer is registered here under its data pointer with a reference count, so one frame
+//! can be shared between several consumers and freeing a pointer twice (or one that never came
+//! from this library) reports an error instead of corrupting the heap.
+
+use crate::{CaptureError, CapturedImage, ErrorCode, usage::Usage};
+use image::RgbaImage;
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock, Mutex},
+};
+
+const BYTES_PER_PIXEL: usize = 4;
+
+struct SharedBuffer {
+    bytes: Box<[u8]>,
+    /// Where the caller-visible data starts inside `bytes`, to honor alignment requests.
+    offset: usize,
+    refs: usize,
+    /// The background capture the buffer is accounted to while it lives.
+    owner: Option<Arc<Usage>>,
+}
+
+static BUFFERS: LazyLock<Mutex<HashMap<usize, SharedBuffer>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn unknown(data: *const u8) -> CaptureError {
+    CaptureError::new(
+        ErrorCode::InvalidHandle,
+        format!(
+            "Image data {:p} was already freed or not allocated by this library",
+            data
+        ),
+    )
+}
+
+/// Takes ownership of a buffer with a reference count of 1 and returns the pointer to its
+/// data, which starts `offset` bytes in and must be non-empty.
+fn register(mut bytes: Box<[u8]>, offset: usize, owner: Option<Arc<Usage>>) -> *mut u8 {
+    debug_assert!(
+        offset < bytes.len(),
+        "empty buffers share a dangling pointer"
+    );
+    let data = bytes[offset..].as_mut_ptr();
+    if let Some(owner) = &owner {
+        owner.hold(bytes.len());
+    }
+    BUFFERS.lock().unwrap_or_else(|e| e.into_inner()).insert(
+        data as usize,
+        SharedBuffer {
+            bytes,
+            offset,
+            refs: 1,
+            owner,
+        },
+    );
+    data
+}
+
+/// Registers the pixels of a frame and returns `(data, len, stride)`, or None for an empty frame.
+/// With a `row_alignment` above 1 (a power of two), `data` and the start of every row are aligned
+/// to it, padding rows at the end; otherwise rows are tightly packed. The frame's own allocation
+/// is reused whenever it already has the requested layout. An `owner` has the buffer counted
+/// against its usage until it is freed.
+pub(crate) fn register_rgba(
+    image: RgbaImage,
+    row_alignment: usize,
+    owner: Option<Arc<Usage>>,
+) -> Option<(*mut u8, usize, usize)> {
+    let width = image.width() as usize;
+    let height = image.height() as usize;
+    let row_len = width * BYTES_PER_PIXEL;
+    let align = row_alignment.max(1);
+    let stride = row_len.next_multiple_of(align);
+    let len = stride * height;
+    if len == 0 {
+        return None;
+    }
+
+    let pixels = image.into_raw();
+    if stride == row_len && pixels.as_ptr().align_offset(align) == 0 {
+        return Some((register(pixels.into_boxed_slice(), 0, owner), len, stride));
+    }
+
+    // Over-allocate so an aligned start exists somewhere in the first `align` bytes.
+    let mut bytes = vec![0u8; len + align - 1].into_boxed_slice();
+    let offset = bytes.as_ptr().align_offset(align);
+    for (row, src) in pixels.chunks_exact(row_len).enumerate() {
+        let start = offset + row * stride;
+        bytes[start..start + row_len].copy_from_slice(src);
+    }
+    Some((register(bytes, offset, owner), len, stride))
+}
+
+/// Adds a reference to a registered buffer and returns how many bytes are valid from `data` on.
+pub(crate) fn retain(data: *const u8) -> Result<usize, CaptureError> {
+    let mut buffers = BUFFERS.lock().unwrap_or_else(|e| e.into_inner());
+    let buffer = buffers
+        .get_mut(&(data as usize))
+        .ok_or_else(|| unknown(data))?;
+    buffer.refs += 1;
+    Ok(buffer.bytes.len() - buffer.offset)
+}
+
+/// Drops a reference to a registered buffer, freeing it when it was the last one.
+pub(crate) fn release(data: *const u8) -> Result<(), CaptureError> {
+    let mut buffers = BUFFERS.lock().unwrap_or_else(|e| e.into_inner());
+    let buffer = buffers
+        .get_mut(&(data as usize))
+        .ok_or_else(|| unknown(data))?;
+    buffer.refs -= 1;
+    if buffer.refs == 0 {
+        let buffer = buffers.remove(&(data as usize));
+        // Free outside the lock; large frames take a moment to unmap.
+        drop(buffers);
+        if let Some(buffer) = buffer {
+            if let Some(owner) = &buffer.owner {
+                owner.release(buffer.bytes.len());
+            }
+            drop(buffer.bytes);
+        }
+    }
+    Ok(())
+}
+
+/// The background capture a registered buffer is accounted to, if any.
+pub(crate) fn owner(data: *const u8) -> Option<Arc<Usage>> {
+    let buffers = BUFFERS.lock().unwrap_or_else(|e| e.into_inner());
+    buffers.get(&(data as usize))?.owner.clone()
+}
+
+/// Copies RGBA8 rows that are `stride` bytes apart (0 for tightly packed) into a new frame.
+///
+/// # Safety
+/// `data` must be NULL or point to at least `stride * (height - 1) + width * 4` readable bytes.
+pub(crate) unsafe fn copy_rgba(
+    data: *const u8,
+    width: u32,
+    height: u32,
+    stride: usize,
+) -> Result<RgbaImage, CaptureError> {
+    let row_len = width as usize * BYTES_PER_PIXEL;
+    let stride = if stride == 0 { row_len } else { stride };
+    if data.is_null() || width == 0 || height == 0 {
+        return Err(CaptureError::new(
+            ErrorCode::InvalidArgument,
+            format!("Image has no data or a zero size ({}x{})", width, height),
+        ));
+    }
+    if stride < row_len {
+        return Err(CaptureError::new(
+            ErrorCode::InvalidArgument,
+            format!("Image stride {} is too small for its width", stride),
+        ));
+    }
+    let mut pixels = Vec::with_capacity(row_len * height as usize);
+    for row in 0..height as usize {
+        // SAFETY: the caller promised every row is readable.
+        pixels.extend_from_slice(unsafe {
+            std::slice::from_raw_parts(data.add(row * stride), row_len)
+        });
+    }
+    Ok(RgbaImage::from_raw(width, height, pixels).expect("buffer matches the dimensions"))
+}
+
+/// Copies the pixels of a registered image into a new frame, e.g. to encode it.
+pub(crate) fn to_rgba(image: &CapturedImage) -> Result<RgbaImage, CaptureError> {
+    if image.data.is_null() {
+        return Err(CaptureError::new(
+            ErrorCode::InvalidArgument,
+            "Image has no data",
+        ));
+    }
+    // Holding a reference both checks the image is live and keeps it alive while copying.
+    let len = retain(image.data)?;
+    let row_len = image.width as usize * BYTES_PER_PIXEL;
+    let needed = (image.height as usize).saturating_sub(1) * image.stride + row_len;
+    let result = if image.height == 0 || image.stride < row_len || needed > len {
+        Err(CaptureError::new(
+            ErrorCode::InvalidArgument,
+            "Image dimensions don't match its buffer",
+        ))
+    } else {
+        // SAFETY: checked against the buffer's length above.
+        unsafe { copy_rgba(image.data, image.width, image.height, image.stride) }
+    };
+    let _ = release(image.data);
+    result
+}
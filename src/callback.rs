@@ -0,0 +1,206 @@
+// capture-ffi/src/callback.rs
+//! Callbacks referenced by ID instead of by function pointer, for runtimes whose function
+//! pointers can move or be invalidated by their garbage collector, or that need to know exactly
+//! when the library is done with one.
+//! The caller registers its function once and passes the library's own trampoline for that kind
+//! of callback, with the ID as user data, to any API that takes a callback. The trampoline looks
+//! the ID up on every call, so once capture_callback_unregister() returns the caller's function
+//! is never called again, even by streams that are still running.
+
+use crate::{
+    CaptureError, CapturedImage, ErrorCode, UserData, buffer, event::CaptureEvent, set_last_error,
+};
+use libc::{c_int, c_uint, c_void, size_t};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    mem,
+    sync::{
+        Arc, Condvar, LazyLock, Mutex,
+        atomic::{AtomicU32, Ordering},
+    },
+};
+
+ffi_enum! {
+    /// The signature of a callback registered with capture_callback_register().
+    pub enum CallbackKind {
+        /// A FrameCallback, as taken by streams, region watches and composite recordings.
+        Frame = 0 => CAPTURE_CALLBACK_FRAME,
+        /// An EventCallback, as taken by capture_set_event_callback().
+        Event = 1 => CAPTURE_CALLBACK_EVENT,
+        /// A WriteCallback, as taken by the encode functions.
+        Write = 2 => CAPTURE_CALLBACK_WRITE,
+    }
+}
+
+struct Registered {
+    kind: CallbackKind,
+    function: UserData,
+    user_data: UserData,
+}
+
+static CALLBACKS: LazyLock<Mutex<HashMap<c_uint, Arc<Registered>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+/// Signalled whenever a call through a trampoline finishes.
+static CALL_DONE: Condvar = Condvar::new();
+static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+thread_local! {
+    /// IDs of the callbacks this thread is inside of, so unregistering one from within itself
+    /// doesn't wait for its own call to finish.
+    static RUNNING: RefCell<Vec<c_uint>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Calls `call` with the registered callback behind the user data a trampoline received, if it
+/// is still registered with the expected kind.
+fn dispatch<R>(
+    user_data: *mut c_void,
+    kind: CallbackKind,
+    call: impl FnOnce(&Registered) -> R,
+) -> Option<R> {
+    let id = user_data as usize as c_uint;
+    let callback = CALLBACKS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(&id)
+        .filter(|callback| callback.kind == kind)
+        .cloned()?;
+    RUNNING.with(|running| running.borrow_mut().push(id));
+    let result = call(&callback);
+    RUNNING.with(|running| running.borrow_mut().pop());
+    drop(callback);
+    // Take the lock so the notification can't slip in between an unregister's check and wait.
+    let _callbacks = CALLBACKS.lock().unwrap_or_else(|e| e.into_inner());
+    CALL_DONE.notify_all();
+    Some(result)
+}
+
+unsafe extern "C" fn frame_trampoline(frame: CapturedImage, user_data: *mut c_void) {
+    let delivered = dispatch(user_data, CallbackKind::Frame, |callback| {
+        // SAFETY: registered as a FrameCallback, and valid until unregistered.
+        let function: unsafe extern "C" fn(CapturedImage, *mut c_void) =
+            unsafe { mem::transmute(callback.function.0) };
+        unsafe { function(frame, callback.user_data.0) }
+    });
+    // Nobody is left to free the frame.
+    if delivered.is_none() && !frame.data.is_null() {
+        let _ = buffer::release(frame.data);
+    }
+}
+
+unsafe extern "C" fn event_trampoline(event: *const CaptureEvent, user_data: *mut c_void) {
+    dispatch(user_data, CallbackKind::Event, |callback| {
+        // SAFETY: registered as an EventCallback, and valid until unregistered.
+        let function: unsafe extern "C" fn(*const CaptureEvent, *mut c_void) =
+            unsafe { mem::transmute(callback.function.0) };
+        unsafe { function(event, callback.user_data.0) }
+    });
+}
+
+unsafe extern "C" fn write_trampoline(
+    data: *const u8,
+    len: size_t,
+    user_data: *mut c_void,
+) -> c_int {
+    dispatch(user_data, CallbackKind::Write, |callback| {
+        // SAFETY: registered as a WriteCallback, and valid until unregistered.
+        let function: unsafe extern "C" fn(*const u8, size_t, *mut c_void) -> c_int =
+            unsafe { mem::transmute(callback.function.0) };
+        unsafe { function(data, len, callback.user_data.0) }
+    })
+    // Abort the encode rather than pretend the bytes were written.
+    .unwrap_or(1)
+}
+
+fn invalid_kind(kind: c_int) -> CaptureError {
+    CaptureError::new(
+        ErrorCode::InvalidArgument,
+        format!("Unknown callback kind: {}", kind),
+    )
+}
+
+/// Registers `function`, a callback with the signature `kind` (a CallbackKind,
+/// CAPTURE_CALLBACK_*) describes, and returns an ID for it, or 0 on error.
+/// To use it, pass capture_callback_trampoline(kind) as the callback and the ID, cast to a
+/// pointer, as the user data; `function` then gets called with `user_data` instead. A frame
+/// delivered to an unregistered ID is freed, and a write to one aborts the encode.
+///
+/// # Safety
+/// `function` must have the signature of `kind` and stay valid, and safe to call from any thread,
+/// until capture_callback_unregister() returns for its ID.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_callback_register(
+    kind: c_int,
+    function: *const c_void,
+    user_data: *mut c_void,
+) -> c_uint {
+    let result = CallbackKind::from_raw(kind)
+        .ok_or_else(|| invalid_kind(kind))
+        .and_then(|kind| {
+            if function.is_null() {
+                return Err(CaptureError::new(
+                    ErrorCode::InvalidArgument,
+                    "Callback must not be NULL",
+                ));
+            }
+            Ok(kind)
+        });
+    match result {
+        Ok(kind) => {
+            let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+            CALLBACKS.lock().unwrap_or_else(|e| e.into_inner()).insert(
+                id,
+                Arc::new(Registered {
+                    kind,
+                    function: UserData(function as *mut c_void),
+                    user_data: UserData(user_data),
+                }),
+            );
+            id
+        }
+        Err(err) => {
+            set_last_error(err);
+            0
+        }
+    }
+}
+
+/// Unregisters a callback. Waits for calls to it already in progress on other threads, so the
+/// function and its user data may be released once this returns; it may also be called from
+/// within the callback itself.
+/// Returns CAPTURE_OK, or CAPTURE_ERROR_INVALID_ARGUMENT if no callback has this ID.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_callback_unregister(id: c_uint) -> c_int {
+    let mut callbacks = CALLBACKS.lock().unwrap_or_else(|e| e.into_inner());
+    let Some(callback) = callbacks.remove(&id) else {
+        drop(callbacks);
+        let err = CaptureError::new(
+            ErrorCode::InvalidArgument,
+            format!("No callback with ID {}", id),
+        );
+        let code = err.code as c_int;
+        set_last_error(err);
+        return code;
+    };
+    let own_calls = RUNNING.with(|running| running.borrow().iter().filter(|&&r| r == id).count());
+    while Arc::strong_count(&callback) > 1 + own_calls {
+        callbacks = CALL_DONE.wait(callbacks).unwrap_or_else(|e| e.into_inner());
+    }
+    ErrorCode::Ok as c_int
+}
+
+/// Returns the library's trampoline for a CallbackKind (CAPTURE_CALLBACK_*), to pass wherever a
+/// callback of that kind is expected together with an ID from capture_callback_register().
+/// Returns NULL for an unknown kind.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_callback_trampoline(kind: c_int) -> *const c_void {
+    match CallbackKind::from_raw(kind) {
+        Some(CallbackKind::Frame) => frame_trampoline as *const c_void,
+        Some(CallbackKind::Event) => event_trampoline as *const c_void,
+        Some(CallbackKind::Write) => write_trampoline as *const c_void,
+        None => {
+            set_last_error(invalid_kind(kind));
+            std::ptr::null()
+        }
+    }
+}
@@ -0,0 +1,158 @@
+// capture-ffi/src/cancel.rs
+//! Cancellation tokens shared between long-running operations.
+//! One token can be passed to any number of operations; cancelling it makes every one of them
+//! stop at its next check, and wakes those that are sleeping. Operations keep their own
+//! reference, so the host may free a token while operations using it still run.
+
+use crate::{
+    CaptureError, ErrorCode,
+    handle::{CaptureHandle, HandleType, Registry},
+    set_last_error,
+};
+use libc::c_int;
+use std::{
+    sync::{Arc, Condvar, Mutex, Weak},
+    time::{Duration, Instant},
+};
+
+#[derive(Default)]
+struct State {
+    cancelled: bool,
+    /// Tokens cancelled along with this one.
+    children: Vec<Weak<CancelToken>>,
+}
+
+#[derive(Default)]
+pub(crate) struct CancelToken {
+    state: Mutex<State>,
+    wake: Condvar,
+}
+
+impl CancelToken {
+    /// A new token that is also cancelled when `parent` is.
+    pub(crate) fn child_of(parent: Option<&CancelToken>) -> Arc<CancelToken> {
+        let child = Arc::new(CancelToken::default());
+        if let Some(parent) = parent {
+            let mut state = parent.lock();
+            if state.cancelled {
+                child.cancel();
+            } else {
+                state.children.retain(|c| c.strong_count() > 0);
+                state.children.push(Arc::downgrade(&child));
+            }
+        }
+        child
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.lock().cancelled
+    }
+
+    pub(crate) fn cancel(&self) {
+        let children = {
+            let mut state = self.lock();
+            state.cancelled = true;
+            std::mem::take(&mut state.children)
+        };
+        self.wake.notify_all();
+        for child in children.iter().filter_map(Weak::upgrade) {
+            child.cancel();
+        }
+    }
+
+    /// Sleeps for `duration` or until cancelled. Returns true if cancelled.
+    pub(crate) fn sleep(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        let mut state = self.lock();
+        while !state.cancelled {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            state = self
+                .wake
+                .wait_timeout(state, deadline - now)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+        state.cancelled
+    }
+
+    /// Fails with CAPTURE_ERROR_CANCELLED once cancelled, for use with `?` in loops.
+    pub(crate) fn check(&self) -> Result<(), CaptureError> {
+        if self.is_cancelled() {
+            return Err(CaptureError::new(
+                ErrorCode::Cancelled,
+                "Operation was cancelled",
+            ));
+        }
+        Ok(())
+    }
+}
+
+static TOKENS: Registry<CancelToken> = Registry::new(HandleType::CancelToken);
+
+/// Looks up the token an operation was given; handle 0 means the operation isn't cancellable.
+pub(crate) fn resolve(token: CaptureHandle) -> Result<Option<Arc<CancelToken>>, CaptureError> {
+    match token {
+        0 => Ok(None),
+        token => TOKENS.get(token).map(Some),
+    }
+}
+
+/// Creates a cancellation token, to be passed to any number of cancellable operations.
+/// The caller MUST call capture_cancel_token_free() on the returned handle.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_cancel_token_new() -> CaptureHandle {
+    TOKENS.insert(CancelToken::default())
+}
+
+/// Cancels every operation using the token, now and in the future. Sleeping operations wake up
+/// immediately. Cancelling twice is harmless.
+/// Returns CAPTURE_OK, or CAPTURE_ERROR_INVALID_HANDLE if `token` isn't a live token.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_cancel_token_cancel(token: CaptureHandle) -> c_int {
+    match TOKENS.get(token) {
+        Ok(token) => {
+            token.cancel();
+            ErrorCode::Ok as c_int
+        }
+        Err(err) => {
+            let code = err.code as c_int;
+            set_last_error(err);
+            code
+        }
+    }
+}
+
+/// Returns whether the token has been cancelled; false (and sets the last error) if `token`
+/// isn't a live token.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_cancel_token_is_cancelled(token: CaptureHandle) -> bool {
+    match TOKENS.get(token) {
+        Ok(token) => token.is_cancelled(),
+        Err(err) => {
+            set_last_error(err);
+            false
+        }
+    }
+}
+
+/// Frees a token handle. Operations already using the token keep running, but can no longer be
+/// cancelled through it.
+/// Returns CAPTURE_OK, or CAPTURE_ERROR_INVALID_HANDLE if `token` isn't a live token.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_cancel_token_free(token: CaptureHandle) -> c_int {
+    match TOKENS.remove(token) {
+        Ok(_) => ErrorCode::Ok as c_int,
+        Err(err) => {
+            let code = err.code as c_int;
+            set_last_error(err);
+            code
+        }
+    }
+}
@@ -0,0 +1,143 @@
+// capture-ffi/src/compose.rs
+//! Combining several captures into one image.
+
+use crate::{CaptureError, CaptureRect, CapturedImage, ErrorCode, buffer, limits, set_last_error};
+use image::{Rgba, RgbaImage, imageops};
+use libc::{c_uint, size_t};
+
+const BYTES_PER_PIXEL: usize = 4;
+
+/// Largest output side of a composed image. This alone still allows 32768x32768 pixels, 4 GiB of
+/// RGBA, so capture_compose_grid() also applies the capture_set_max_pixels() limit.
+pub(crate) const MAX_SIDE: u64 = 1 << 15;
+
+/// Copies the part of `image` inside `rect`, clipped to the image, or returns None if nothing of
+/// it is left.
+pub(crate) fn crop(image: &RgbaImage, rect: CaptureRect) -> Option<RgbaImage> {
+    let (width, height) = image.dimensions();
+    let left = rect.x.clamp(0, width as i32) as u32;
+    let top = rect.y.clamp(0, height as i32) as u32;
+    let right = (rect.x as i64 + rect.width as i64).clamp(0, width as i64) as u32;
+    let bottom = (rect.y as i64 + rect.height as i64).clamp(0, height as i64) as u32;
+    if left >= right || top >= bottom {
+        return None;
+    }
+    Some(imageops::crop_imm(image, left, top, right - left, bottom - top).to_image())
+}
+
+/// Copies a CapturedImage's pixels into `canvas` with its top-left corner at (`x`, `y`).
+fn blit(canvas: &mut RgbaImage, image: &CapturedImage, x: u32, y: u32) -> Result<(), CaptureError> {
+    let row_len = image.width as usize * BYTES_PER_PIXEL;
+    if image.stride < row_len {
+        return Err(CaptureError::new(
+            ErrorCode::InvalidArgument,
+            format!("Image stride {} is too small for its width", image.stride),
+        ));
+    }
+    // Holding a reference both checks the image is live and keeps it alive while copying.
+    let len = buffer::retain(image.data)?;
+    let needed = (image.height as usize).saturating_sub(1) * image.stride + row_len;
+    let result = if image.height == 0 || needed > len {
+        Err(CaptureError::new(
+            ErrorCode::InvalidArgument,
+            "Image dimensions don't match its buffer",
+        ))
+    } else {
+        let canvas_width = canvas.width() as usize;
+        let pixels: &mut [u8] = canvas;
+        for row in 0..image.height as usize {
+            // SAFETY: checked against the buffer's length above.
+            let src =
+                unsafe { std::slice::from_raw_parts(image.data.add(row * image.stride), row_len) };
+            let start = ((y as usize + row) * canvas_width + x as usize) * BYTES_PER_PIXEL;
+            pixels[start..start + row_len].copy_from_slice(src);
+        }
+        Ok(())
+    };
+    let _ = buffer::release(image.data);
+    result
+}
+
+fn compose_grid(
+    images: &[CapturedImage],
+    columns: usize,
+    padding: u32,
+    background: Rgba<u8>,
+) -> Result<RgbaImage, CaptureError> {
+    let invalid = |message: &str| CaptureError::new(ErrorCode::InvalidArgument, message);
+    if images.is_empty() {
+        return Err(invalid("No images to compose"));
+    }
+    if images.iter().any(|image| image.data.is_null()) {
+        return Err(invalid("Image has no data"));
+    }
+    let columns = match columns {
+        0 => (images.len() as f64).sqrt().ceil() as usize,
+        columns => columns.min(images.len()),
+    };
+    let rows = images.len().div_ceil(columns);
+    let cell_width = images.iter().map(|i| i.width).max().unwrap_or(0);
+    let cell_height = images.iter().map(|i| i.height).max().unwrap_or(0);
+
+    let side =
+        |cells: usize, cell: u32| cells as u64 * (cell as u64 + padding as u64) + padding as u64;
+    let (width, height) = (side(columns, cell_width), side(rows, cell_height));
+    if width > MAX_SIDE || height > MAX_SIDE {
+        return Err(CaptureError::new(
+            ErrorCode::TooLarge,
+            format!(
+                "A {}x{} grid would be {}x{} pixels; the limit is {} per side",
+                columns, rows, width, height, MAX_SIDE
+            ),
+        ));
+    }
+    limits::check_nominal(width as u32, height as u32)?;
+
+    let mut canvas = RgbaImage::from_pixel(width as u32, height as u32, background);
+    for (i, image) in images.iter().enumerate() {
+        let (column, row) = ((i % columns) as u32, (i / columns) as u32);
+        // Center each image in its cell.
+        let x = padding + column * (cell_width + padding) + (cell_width - image.width) / 2;
+        let y = padding + row * (cell_height + padding) + (cell_height - image.height) / 2;
+        blit(&mut canvas, image, x, y).map_err(|e| e.context(format!("Image {}", i)))?;
+    }
+    limits::apply(canvas)
+}
+
+/// Arranges `count` images in a grid with `columns` columns (0 picks a roughly square layout),
+/// e.g. to make a contact sheet of all monitors or of a burst of captures. Every cell is as large
+/// as the largest image, images are centered in their cells, and `padding` pixels separate cells
+/// from each other and from the edges. `bg_color` (0xRRGGBBAA) fills everything not covered.
+/// The input images are not consumed.
+/// The caller MUST call capture_free_image() on the returned struct to free the data buffer.
+/// Returns a struct with NULL data pointer on error, with CAPTURE_ERROR_TOO_LARGE if the grid
+/// would be over 32768 pixels on a side or, with CAPTURE_OVERSIZE_REJECT, over the
+/// capture_set_max_pixels() limit; with CAPTURE_OVERSIZE_DOWNSCALE a grid over that limit is
+/// scaled down to fit it.
+///
+/// # Safety
+/// `images` must point to `count` CapturedImage structs.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_compose_grid(
+    images: *const CapturedImage,
+    count: size_t,
+    columns: c_uint,
+    padding: c_uint,
+    bg_color: u32,
+) -> CapturedImage {
+    let images = if images.is_null() || count == 0 {
+        &[]
+    } else {
+        unsafe { std::slice::from_raw_parts(images, count) }
+    };
+    let background = Rgba(bg_color.to_be_bytes());
+    match compose_grid(images, columns as usize, padding, background) {
+        Ok(canvas) => CapturedImage::from_rgba(canvas),
+        Err(err) => {
+            let err = err.context("Failed to compose grid");
+            eprintln!("{}", err);
+            set_last_error(err);
+            CapturedImage::empty()
+        }
+    }
+}
@@ -0,0 +1,541 @@
+// capture-ffi/src/composite.rs
+//! Recording several targets into one composited frame, e.g. a window with a small region of
+//! another monitor picture-in-picture in its corner.
+
+use crate::{
+    CaptureError, CaptureRect, ErrorCode, UserData, backend, cancel, compose,
+    event::{self, EventType},
+    handle::{CaptureHandle, HandleType, Registry},
+    limits,
+    options::read_sized,
+    poll::FrameSink,
+    priority, set_last_error,
+    stream::FrameCallback,
+    target::{CaptureTarget, Source, TargetKind},
+    usage,
+    worker::{Task, Worker},
+};
+use image::{Rgba, RgbaImage, imageops};
+use libc::{c_int, c_uint, c_void, size_t};
+use std::{
+    mem, ptr,
+    time::{Duration, Instant},
+};
+
+/// Frame rate used when CompositeOptions.max_fps is 0.
+const DEFAULT_FPS: c_uint = 30;
+
+/// Largest CompositeLayer.scale, in percent.
+const MAX_SCALE: c_uint = 800;
+
+/// One target placed in a composited frame. Versioned like CaptureOptions: always start from
+/// capture_composite_layer_default().
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct CompositeLayer {
+    /// Size of this struct in bytes as known by the caller; the same for every layer in an array.
+    pub struct_size: size_t,
+    /// The monitor or window this layer shows.
+    pub target: CaptureTarget,
+    /// The part of the target to show, in the target's captured pixels. A zero width or height
+    /// (the default) shows all of it.
+    pub source: CaptureRect,
+    /// Where the layer's top-left corner goes in the output frame.
+    pub x: c_int,
+    pub y: c_int,
+    /// Size of the layer in percent of its source size; 0 means 100. At most 800, and the scaled
+    /// layer must fit the same size limits as the output frame.
+    pub scale: c_uint,
+    /// Stacking order: layers with a higher `z` are drawn over lower ones, and layers with the
+    /// same `z` in array order.
+    pub z: c_int,
+    /// Opacity in percent, from 0 (invisible) to 100 (the default).
+    pub opacity: c_uint,
+    /// Radius in output pixels of the layer's rounded corners; 0 (the default) keeps them square.
+    pub corner_radius: c_uint,
+    /// Width in output pixels of a border drawn inside the layer's edge, following its corners.
+    /// 0 (the default) draws none.
+    pub border_width: c_uint,
+    /// Border color as 0xRRGGBBAA. Defaults to opaque white.
+    pub border_color: u32,
+}
+
+impl Default for CompositeLayer {
+    fn default() -> Self {
+        let empty = CaptureRect {
+            x: 0,
+            y: 0,
+            width: 0,
+            height: 0,
+        };
+        CompositeLayer {
+            struct_size: mem::size_of::<CompositeLayer>(),
+            target: CaptureTarget {
+                kind: TargetKind::Monitor as c_int,
+                id: 0,
+            },
+            source: empty,
+            x: 0,
+            y: 0,
+            scale: 0,
+            z: 0,
+            opacity: 100,
+            corner_radius: 0,
+            border_width: 0,
+            border_color: 0xffff_ffff,
+        }
+    }
+}
+
+/// Options for capture_composite_start(). Versioned like CaptureOptions: always start from
+/// capture_composite_options_default().
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct CompositeOptions {
+    /// Size of this struct in bytes as known by the caller.
+    pub struct_size: size_t,
+    /// Size of the output frames. 0 (the default) for either fits that side to the layers, as
+    /// placed in the first frame.
+    pub width: c_uint,
+    pub height: c_uint,
+    /// Upper bound on delivered frames per second; 0 means 30.
+    pub max_fps: c_uint,
+    /// Color (0xRRGGBBAA) of everything no layer covers. Defaults to opaque black.
+    pub background: u32,
+}
+
+impl Default for CompositeOptions {
+    fn default() -> Self {
+        CompositeOptions {
+            struct_size: mem::size_of::<CompositeOptions>(),
+            width: 0,
+            height: 0,
+            max_fps: 0,
+            background: 0x0000_00ff,
+        }
+    }
+}
+
+/// A layer with its target looked up.
+struct Layer {
+    /// Position in the caller's array, for error messages.
+    index: usize,
+    spec: CompositeLayer,
+    source: Source,
+}
+
+impl Layer {
+    /// Crops, scales and styles a capture of the layer's target to what the layer shows, or None
+    /// if the source rectangle lies outside the capture. Fails before scaling if the scaled layer
+    /// would be over MAX_SIDE on a side or over the pixel limit.
+    fn prepare(&self, image: &RgbaImage) -> Result<Option<RgbaImage>, CaptureError> {
+        let source = self.spec.source;
+        let cropped = if source.width == 0 || source.height == 0 {
+            image.clone()
+        } else {
+            match compose::crop(image, source) {
+                Some(cropped) => cropped,
+                None => return Ok(None),
+            }
+        };
+        let mut image = match self.spec.scale {
+            0 | 100 => cropped,
+            scale => {
+                let resize = |side: u32| (side as u64 * scale as u64 / 100).max(1);
+                let (width, height) = (resize(cropped.width()), resize(cropped.height()));
+                if width > compose::MAX_SIDE || height > compose::MAX_SIDE {
+                    return Err(CaptureError::new(
+                        ErrorCode::TooLarge,
+                        format!(
+                            "Layer {} scaled to {}x{} exceeds the limit of {} pixels per side",
+                            self.index,
+                            width,
+                            height,
+                            compose::MAX_SIDE
+                        ),
+                    ));
+                }
+                let (width, height) = (width as u32, height as u32);
+                limits::check_nominal(width, height)
+                    .map_err(|e| e.context(format!("Layer {}", self.index)))?;
+                imageops::resize(&cropped, width, height, imageops::FilterType::Triangle)
+            }
+        };
+        style(&self.spec, &mut image);
+        Ok(Some(image))
+    }
+}
+
+/// Applies a layer's border, rounded corners and opacity.
+fn style(spec: &CompositeLayer, image: &mut RgbaImage) {
+    let (width, height) = image.dimensions();
+    let radius = spec.corner_radius.min(width.min(height) / 2) as f32;
+    let border_width = spec.border_width.min(width.min(height)) as f32;
+    let Rgba(border) = Rgba(spec.border_color.to_be_bytes());
+    let opacity = spec.opacity as f32 / 100.0;
+    if radius == 0.0 && border_width == 0.0 && spec.opacity == 100 {
+        return;
+    }
+
+    // Only pixels this close to an edge can be touched by the corners or the border.
+    let band = radius.max(border_width) as u32 + 1;
+    let (half_width, half_height) = (width as f32 / 2.0, height as f32 / 2.0);
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        let near_edge = x < band
+            || y < band
+            || x >= width.saturating_sub(band)
+            || y >= height.saturating_sub(band);
+        let (inside, on_border) = if near_edge {
+            // Signed distance from the pixel's center to the rounded rectangle's outline,
+            // negative inside; one pixel of it is used to antialias the edges.
+            let qx = (x as f32 + 0.5 - half_width).abs() - (half_width - radius);
+            let qy = (y as f32 + 0.5 - half_height).abs() - (half_height - radius);
+            let outside = qx.max(0.0).hypot(qy.max(0.0));
+            let distance = outside + qx.max(qy).min(0.0) - radius;
+            let coverage = |d: f32| (0.5 - d).clamp(0.0, 1.0);
+            let inside = coverage(distance);
+            (inside, inside - coverage(distance + border_width))
+        } else {
+            (1.0, 0.0)
+        };
+
+        let mix = on_border * border[3] as f32 / 255.0;
+        for channel in 0..3 {
+            let blended = pixel[channel] as f32 * (1.0 - mix) + border[channel] as f32 * mix;
+            pixel[channel] = blended.round() as u8;
+        }
+        let alpha = pixel[3] as f32 * (1.0 - mix) + 255.0 * mix;
+        pixel[3] = (alpha * inside * opacity).round() as u8;
+    }
+}
+
+/// Captures every layer's target once (targets shared by several layers are captured once)
+/// and prepares each layer's image, in drawing order.
+fn capture_layers(layers: &[Layer]) -> Result<Vec<Option<RgbaImage>>, CaptureError> {
+    let mut captures: Vec<(CaptureTarget, RgbaImage)> = Vec::new();
+    layers
+        .iter()
+        .map(|layer| {
+            let target = layer.spec.target;
+            let index = match captures.iter().position(|(t, _)| *t == target) {
+                Some(index) => index,
+                None => {
+                    let image = layer.source.capture_background().map_err(|e| {
+                        e.context(format!(
+                            "Error capturing target {}:{}",
+                            target.kind, target.id
+                        ))
+                    })?;
+                    captures.push((target, image));
+                    captures.len() - 1
+                }
+            };
+            usage::converting(|| layer.prepare(&captures[index].1))
+        })
+        .collect()
+}
+
+fn draw(
+    layers: &[Layer],
+    images: Vec<Option<RgbaImage>>,
+    width: u32,
+    height: u32,
+    background: Rgba<u8>,
+) -> RgbaImage {
+    let mut canvas = RgbaImage::from_pixel(width, height, background);
+    for (layer, image) in layers.iter().zip(images) {
+        if let Some(image) = image {
+            imageops::overlay(
+                &mut canvas,
+                &image,
+                layer.spec.x as i64,
+                layer.spec.y as i64,
+            );
+        }
+    }
+    canvas
+}
+
+/// Reads a caller-provided array of layers, which may be of an older, shorter version.
+///
+/// # Safety
+/// `layers` must be NULL or point to `count` CompositeLayer structs of the size given by the
+/// first one's `struct_size`.
+unsafe fn read_layers(
+    layers: *const CompositeLayer,
+    count: usize,
+) -> Result<Vec<CompositeLayer>, CaptureError> {
+    if layers.is_null() || count == 0 {
+        return Err(CaptureError::new(
+            ErrorCode::InvalidArgument,
+            "No layers to composite",
+        ));
+    }
+    let stride = unsafe { ptr::read_unaligned(layers as *const size_t) };
+    (0..count)
+        .map(|i| {
+            let layer = unsafe { (layers as *const u8).add(i * stride) } as *const CompositeLayer;
+            let struct_size = unsafe { ptr::read_unaligned(layer as *const size_t) };
+            if struct_size != stride {
+                return Err(CaptureError::new(
+                    ErrorCode::InvalidArgument,
+                    format!(
+                        "Layer {} has struct_size {}, layer 0 has {}",
+                        i, struct_size, stride
+                    ),
+                ));
+            }
+            let layer = unsafe {
+                read_sized(
+                    layer,
+                    CompositeLayer::default(),
+                    "CompositeLayer",
+                    "capture_composite_layer_default",
+                )?
+            };
+            if layer.opacity > 100 {
+                return Err(CaptureError::new(
+                    ErrorCode::InvalidArgument,
+                    format!(
+                        "Layer {} opacity must be at most 100%, got {}%",
+                        i, layer.opacity
+                    ),
+                ));
+            }
+            if layer.scale > MAX_SCALE {
+                return Err(CaptureError::new(
+                    ErrorCode::InvalidArgument,
+                    format!(
+                        "Layer {} scale must be at most {}%, got {}%",
+                        i, MAX_SCALE, layer.scale
+                    ),
+                ));
+            }
+            Ok(layer)
+        })
+        .collect()
+}
+
+/// A running composite recording, created by capture_composite_start().
+struct CompositeRecording {
+    frames: FrameSink,
+    worker: Worker,
+}
+
+impl Task for CompositeRecording {
+    fn worker(&self) -> &Worker {
+        &self.worker
+    }
+}
+
+static RECORDINGS: Registry<CompositeRecording> = Registry::new(HandleType::CompositeRecording);
+
+fn start(
+    specs: Vec<CompositeLayer>,
+    options: CompositeOptions,
+    on_frame: FrameCallback,
+    user_data: UserData,
+    cancel: CaptureHandle,
+) -> Result<CaptureHandle, CaptureError> {
+    backend::require_threads()?;
+    let cancel = cancel::resolve(cancel)?;
+    let frames = FrameSink::new(on_frame, user_data);
+    let mut layers = specs
+        .into_iter()
+        .enumerate()
+        .map(|(i, spec)| {
+            let source = spec
+                .target
+                .source()
+                .map_err(|e| e.context(format!("Layer {}", i)))?;
+            Ok(Layer {
+                index: i,
+                spec,
+                source,
+            })
+        })
+        .collect::<Result<Vec<_>, CaptureError>>()?;
+    // A stable sort keeps array order among layers with the same z.
+    layers.sort_by_key(|layer| layer.spec.z);
+
+    // The first frame checks every layer, including that none is scaled past the size limits,
+    // and sizes the output to fit them.
+    let first = {
+        let _usage = frames.enter();
+        capture_layers(&layers)?
+    };
+    if let Some(i) = first.iter().position(Option::is_none) {
+        return Err(CaptureError::new(
+            ErrorCode::InvalidArgument,
+            format!(
+                "Source rectangle of layer {} is outside its target",
+                layers[i].index
+            ),
+        ));
+    }
+    let extent = |along: fn(&Layer, &RgbaImage) -> i64| {
+        layers
+            .iter()
+            .zip(&first)
+            .filter_map(|(layer, image)| image.as_ref().map(|image| along(layer, image)))
+            .max()
+            .unwrap_or(0)
+            .max(1) as u64
+    };
+    let width = match options.width {
+        0 => extent(|layer, image| layer.spec.x as i64 + image.width() as i64),
+        width => width as u64,
+    };
+    let height = match options.height {
+        0 => extent(|layer, image| layer.spec.y as i64 + image.height() as i64),
+        height => height as u64,
+    };
+    if width > compose::MAX_SIDE || height > compose::MAX_SIDE {
+        return Err(CaptureError::new(
+            ErrorCode::TooLarge,
+            format!(
+                "{}x{} composite frames exceed the limit of {} pixels per side",
+                width,
+                height,
+                compose::MAX_SIDE
+            ),
+        ));
+    }
+    let (width, height) = (width as u32, height as u32);
+    limits::check_nominal(width, height)?;
+    let background = Rgba(options.background.to_be_bytes());
+
+    let fps = if options.max_fps == 0 {
+        DEFAULT_FPS
+    } else {
+        options.max_fps
+    };
+    let recording = CompositeRecording {
+        frames: frames.clone(),
+        worker: Worker::new(cancel.as_deref()),
+    };
+    let interval = Duration::from_secs_f64(1.0 / fps as f64);
+    Ok(RECORDINGS.start(recording, move |handle, stop| {
+        event::emit(EventType::StreamStarted, handle, None);
+        let _usage = frames.enter();
+        let mut images = Some(first);
+        let mut next_tick = Instant::now();
+
+        while !stop.is_cancelled() {
+            let frame = match images.take() {
+                Some(images) => Ok(images),
+                None => {
+                    priority::yield_to_interactive(interval);
+                    capture_layers(&layers)
+                }
+            }
+            .and_then(|images| {
+                usage::converting(|| {
+                    limits::apply(draw(&layers, images, width, height, background))
+                })
+            });
+            match frame {
+                Ok(frame) => frames.deliver(frame),
+                Err(err) => {
+                    let err_msg = format!("Error compositing frame: {}", err);
+                    event::emit(EventType::StreamError, handle, Some(&err_msg));
+                }
+            }
+
+            next_tick += interval;
+            let now = Instant::now();
+            if next_tick > now {
+                stop.sleep(next_tick - now);
+            } else {
+                next_tick = now;
+            }
+        }
+
+        frames.close();
+        event::emit(EventType::StreamStopped, handle, None);
+    }))
+}
+
+/// Returns CompositeLayer with every field at its default value and `struct_size` filled in.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_composite_layer_default() -> CompositeLayer {
+    CompositeLayer::default()
+}
+
+/// Returns CompositeOptions with every field at its default value and `struct_size` filled in.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_composite_options_default() -> CompositeOptions {
+    CompositeOptions::default()
+}
+
+/// Starts recording several monitors and windows into one frame on a background thread: every
+/// tick captures each of the `count` layers' targets and draws them, cropped, scaled and stacked
+/// as the layers describe, over the background (picture-in-picture, side-by-side monitors...).
+/// Frames are passed to `on_frame` on the recording's thread like stream frames; the callback
+/// owns each frame and MUST call capture_free_image() on it, or with a NULL `on_frame` they are
+/// kept for capture_stream_poll_frame(). The recording reports
+/// CAPTURE_EVENT_STREAM_* events under its handle, and stops when `cancel` (a token from
+/// capture_cancel_token_new(), or 0 for none) is cancelled.
+/// Returns a recording handle, or 0 if a layer or the options are invalid or the first frame
+/// can't be captured; a layer scaled past the size limits fails with CAPTURE_ERROR_TOO_LARGE.
+///
+/// # Safety
+/// `layers` must point to `count` CompositeLayer structs initialized with
+/// capture_composite_layer_default(). `options` must be NULL or point to a CompositeOptions
+/// initialized with capture_composite_options_default(). `on_frame` must stay valid, and safe to
+/// call from another thread, until capture_composite_stop() returns.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_composite_start(
+    layers: *const CompositeLayer,
+    count: size_t,
+    options: *const CompositeOptions,
+    on_frame: FrameCallback,
+    user_data: *mut c_void,
+    cancel: CaptureHandle,
+) -> CaptureHandle {
+    let result = unsafe { read_layers(layers, count) }.and_then(|layers| {
+        let options = match options.is_null() {
+            true => CompositeOptions::default(),
+            false => unsafe {
+                read_sized(
+                    options,
+                    CompositeOptions::default(),
+                    "CompositeOptions",
+                    "capture_composite_options_default",
+                )?
+            },
+        };
+        start(layers, options, on_frame, UserData(user_data), cancel)
+    });
+    match result {
+        Ok(handle) => handle,
+        Err(err) => {
+            let err = err.context("Failed to start composite recording");
+            eprintln!("{}", err);
+            set_last_error(err);
+            0
+        }
+    }
+}
+
+/// Where a composite recording delivers its frames.
+pub(crate) fn frame_sink(recording: CaptureHandle) -> Result<FrameSink, CaptureError> {
+    Ok(RECORDINGS.get(recording)?.frames.clone())
+}
+
+/// Stops a composite recording, waits for its thread to deliver the last frame and invalidates
+/// the handle. No frame callbacks run after this returns.
+/// Returns CAPTURE_OK, CAPTURE_ERROR_INVALID_HANDLE if `recording` isn't a live recording, or
+/// CAPTURE_ERROR_INVALID_ARGUMENT, leaving the recording running, if called from the
+/// recording's own callbacks; cancel the recording's token from there instead.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_composite_stop(recording: CaptureHandle) -> c_int {
+    match RECORDINGS.stop(recording) {
+        Ok(()) => ErrorCode::Ok as c_int,
+        Err(err) => {
+            let code = err.code as c_int;
+            set_last_error(err);
+            code
+        }
+    }
+}
@@ -0,0 +1,167 @@
+// capture-ffi/src/config.rs
+//! Process-wide defaults loaded from a TOML or JSON file with capture_load_config(), so
+//! deployments can tune behavior without changing the host application.
+
+use crate::{
+    CaptureError, ErrorCode,
+    encode::{ImageFormat, read_path},
+    limits::{self, OversizePolicy},
+    set_last_error,
+};
+use libc::{c_char, c_int};
+use serde::Deserialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum ConfigFormat {
+    Png,
+    Jpeg,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum ConfigOversize {
+    Reject,
+    Downscale,
+}
+
+/// Overrides for how captures are taken, applied once when the file is loaded.
+#[derive(Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+struct BackendConfig {
+    /// As for capture_set_max_pixels().
+    max_pixels: Option<u64>,
+    oversize: Option<ConfigOversize>,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    format: Option<ConfigFormat>,
+    quality: Option<u8>,
+    cursor: Option<bool>,
+    output_dir: Option<PathBuf>,
+    #[serde(default)]
+    backend: BackendConfig,
+}
+
+static CONFIG: Mutex<Option<Config>> = Mutex::new(None);
+
+fn with_config<T>(read: impl FnOnce(&Config) -> Option<T>) -> Option<T> {
+    CONFIG
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+        .and_then(read)
+}
+
+/// The configured default image format, if any.
+pub(crate) fn image_format() -> Option<ImageFormat> {
+    with_config(|config| {
+        config.format.map(|format| match format {
+            ConfigFormat::Png => ImageFormat::Png,
+            ConfigFormat::Jpeg => ImageFormat::Jpeg,
+        })
+    })
+}
+
+/// The configured default JPEG quality, if any.
+pub(crate) fn quality() -> Option<u8> {
+    with_config(|config| config.quality)
+}
+
+/// Whether captures should include the cursor by default.
+pub(crate) fn cursor() -> bool {
+    with_config(|config| config.cursor).unwrap_or(false)
+}
+
+/// Resolves a relative output path against the configured output directory, if any.
+pub(crate) fn output_path(path: PathBuf) -> PathBuf {
+    match with_config(|config| config.output_dir.clone()) {
+        Some(dir) if path.is_relative() => dir.join(path),
+        _ => path,
+    }
+}
+
+fn parse(path: &Path) -> Result<Config, CaptureError> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| CaptureError::from(e).context(format!("Error reading {}", path.display())))?;
+    let invalid = |e: String| {
+        CaptureError::new(
+            ErrorCode::InvalidArgument,
+            format!("Invalid config file {}: {}", path.display(), e),
+        )
+    };
+    let config: Config = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => toml::from_str(&text).map_err(|e| invalid(e.to_string()))?,
+        Some("json") => serde_json::from_str(&text).map_err(|e| invalid(e.to_string()))?,
+        _ => {
+            return Err(invalid("expected a .toml or .json extension".to_string()));
+        }
+    };
+    if let Some(quality) = config.quality.filter(|q| !(1..=100).contains(q)) {
+        return Err(invalid(format!(
+            "quality must be between 1 and 100, got {}",
+            quality
+        )));
+    }
+    Ok(config)
+}
+
+fn load(path: Option<&Path>) -> Result<(), CaptureError> {
+    let Some(path) = path else {
+        *CONFIG.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        return Ok(());
+    };
+    let config = parse(path)?;
+    let backend = &config.backend;
+    if backend.max_pixels.is_some() || backend.oversize.is_some() {
+        let policy = match backend.oversize {
+            Some(ConfigOversize::Downscale) => OversizePolicy::Downscale,
+            Some(ConfigOversize::Reject) | None => OversizePolicy::Reject,
+        };
+        limits::set(backend.max_pixels.unwrap_or(0), policy);
+    }
+    *CONFIG.lock().unwrap_or_else(|e| e.into_inner()) = Some(config);
+    Ok(())
+}
+
+/// Loads process-wide defaults from a `.toml` or `.json` file, replacing any loaded before.
+/// Recognized keys, all optional:
+/// - `format` (`"png"` or `"jpeg"`) and `quality` (1 to 100): defaults for EncodeOptions, as
+///   returned by capture_encode_options_default() and used for fields a caller leaves out.
+/// - `cursor` (bool): default for the `cursor` field of capture_snap() specs, ignored on
+///   backends that can't include the cursor.
+/// - `output_dir`: relative output paths passed to the save functions are resolved against it.
+/// - `[backend]` with `max_pixels` and `oversize` (`"reject"` or `"downscale"`): applied once on
+///   load, as by capture_set_max_pixels().
+///
+/// Values passed explicitly in calls always win over the file. A NULL `path` unloads the file's
+/// defaults; backend settings it applied stay in effect.
+/// Returns CAPTURE_OK, or an error code if the file can't be read or has unknown keys or bad
+/// values, in which case the previous defaults are kept.
+///
+/// # Safety
+/// `path` must be NULL or a valid NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_load_config(path: *const c_char) -> c_int {
+    let path = match path.is_null() {
+        true => Ok(None),
+        false => unsafe { read_path(path) }.map(|path| Some(Path::new(path))),
+    };
+    match path.and_then(load) {
+        Ok(()) => ErrorCode::Ok as c_int,
+        Err(err) => {
+            let err = err.context("Failed to load config");
+            eprintln!("{}", err);
+            let code = err.code as c_int;
+            set_last_error(err);
+            code
+        }
+    }
+}
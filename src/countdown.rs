@@ -0,0 +1,158 @@
+// capture-ffi/src/countdown.rs
+//! Timed captures with an optional on-screen countdown.
+
+use crate::{
+    CaptureError, CaptureRect, CapturedImage,
+    cancel::{self, CancelToken},
+    handle::CaptureHandle,
+    overlay::{Overlay, Rgb},
+    set_last_error,
+    target::CaptureTarget,
+};
+use libc::c_uint;
+use std::{thread, time::Duration};
+
+const BACKGROUND: Rgb = 0x202020;
+const FOREGROUND: Rgb = 0xffffff;
+
+/// Size of one seven-segment digit and of its strokes, in pixels.
+const DIGIT_WIDTH: u32 = 60;
+const DIGIT_HEIGHT: u32 = 100;
+const STROKE: u32 = 12;
+/// Space around and between digits.
+const GAP: u32 = 20;
+
+/// How long to wait after removing the countdown so the compositor has repainted without it.
+const SETTLE: Duration = Duration::from_millis(100);
+
+/// Segments lit for each digit, as bits a (top) to g (middle) of a seven-segment display.
+const DIGIT_SEGMENTS: [u8; 10] = [
+    0b011_1111, // 0: a b c d e f
+    0b000_0110, // 1: b c
+    0b101_1011, // 2: a b d e g
+    0b100_1111, // 3: a b c d g
+    0b110_0110, // 4: b c f g
+    0b110_1101, // 5: a c d f g
+    0b111_1101, // 6: a c d e f g
+    0b000_0111, // 7: a b c
+    0b111_1111, // 8: all
+    0b110_1111, // 9: a b c d f g
+];
+
+/// The rectangles making up one digit with its top-left corner at `x`, `y`.
+fn digit_rects(digit: usize, x: i32, y: i32) -> Vec<CaptureRect> {
+    let (w, h, t) = (DIGIT_WIDTH, DIGIT_HEIGHT, STROKE);
+    let half = h / 2;
+    let segments = [
+        (0, 0, w, t),               // a: top
+        (w - t, 0, t, half),        // b: top right
+        (w - t, half, t, h - half), // c: bottom right
+        (0, h - t, w, t),           // d: bottom
+        (0, half, t, h - half),     // e: bottom left
+        (0, 0, t, half),            // f: top left
+        (0, (h - t) / 2, w, t),     // g: middle
+    ];
+    segments
+        .iter()
+        .enumerate()
+        .filter(|(bit, _)| DIGIT_SEGMENTS[digit] & (1 << bit) != 0)
+        .map(|(_, &(sx, sy, width, height))| CaptureRect {
+            x: x + sx as i32,
+            y: y + sy as i32,
+            width,
+            height,
+        })
+        .collect()
+}
+
+/// A box in the middle of the target showing the seconds left.
+struct Countdown {
+    overlay: Overlay,
+}
+
+impl Countdown {
+    fn new(target: &CaptureRect, digits: u32) -> Result<Self, CaptureError> {
+        let width = digits * (DIGIT_WIDTH + GAP) + GAP;
+        let height = DIGIT_HEIGHT + 2 * GAP;
+        let x = target.x + (target.width as i32 - width as i32) / 2;
+        let y = target.y + (target.height as i32 - height as i32) / 2;
+        Ok(Countdown {
+            overlay: Overlay::new(x, y, width, height, BACKGROUND)?,
+        })
+    }
+
+    fn show(&mut self, seconds: u32) -> Result<(), CaptureError> {
+        let rects: Vec<CaptureRect> = seconds
+            .to_string()
+            .bytes()
+            .enumerate()
+            .flat_map(|(i, digit)| {
+                let x = GAP + i as u32 * (DIGIT_WIDTH + GAP);
+                digit_rects((digit - b'0') as usize, x as i32, GAP as i32)
+            })
+            .collect();
+        self.overlay.draw_rects(&rects, FOREGROUND)
+    }
+}
+
+fn delayed(
+    target: CaptureTarget,
+    delay_secs: c_uint,
+    show_countdown: bool,
+    cancel: &CancelToken,
+) -> Result<CapturedImage, CaptureError> {
+    // Fail on a bad target now rather than after the wait.
+    let bounds = target.bounds()?;
+    let mut countdown = match show_countdown && delay_secs > 0 {
+        true => Some(Countdown::new(
+            &bounds,
+            delay_secs.to_string().len() as u32,
+        )?),
+        false => None,
+    };
+    for remaining in (1..=delay_secs).rev() {
+        if let Some(countdown) = &mut countdown {
+            countdown.show(remaining)?;
+        }
+        if cancel.sleep(Duration::from_secs(1)) {
+            break;
+        }
+    }
+    cancel.check()?;
+    if countdown.take().is_some() {
+        thread::sleep(SETTLE);
+    }
+    Ok(CapturedImage::from_rgba(target.capture()?))
+}
+
+/// Waits `delay_secs` seconds and then captures a monitor or window: the classic timed
+/// screenshot. With `show_countdown` a native overlay in the middle of the target counts down
+/// the seconds left; it is removed before the capture is taken. Blocks for the whole delay.
+/// `cancel` is a token from capture_cancel_token_new() that aborts the wait, or 0.
+/// The caller MUST call capture_free_image() on the returned struct to free the data buffer.
+/// Returns a struct with NULL data pointer on error, including CAPTURE_ERROR_CANCELLED.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_delayed(
+    target: CaptureTarget,
+    delay_secs: c_uint,
+    show_countdown: bool,
+    cancel: CaptureHandle,
+) -> CapturedImage {
+    let result = cancel::resolve(cancel).and_then(|parent| {
+        delayed(
+            target,
+            delay_secs,
+            show_countdown,
+            &CancelToken::child_of(parent.as_deref()),
+        )
+    });
+    match result {
+        Ok(image) => image,
+        Err(err) => {
+            let err = err.context("Delayed capture failed");
+            eprintln!("{}", err);
+            set_last_error(err);
+            CapturedImage::empty()
+        }
+    }
+}
@@ -0,0 +1,145 @@
+// capture-ffi/src/degrade.rs
+//! The degradation ladder: what a stream that can't keep up gives up first, second and so on,
+//! instead of silently falling further behind its frame rate.
+
+use crate::{CaptureError, ErrorCode, set_last_error};
+use libc::{c_int, c_uint, size_t};
+use std::sync::Mutex;
+
+/// Consecutive missed frame deadlines after which a stream takes the next step.
+pub(crate) const MISSES_PER_STEP: u32 = 5;
+
+ffi_enum! {
+    /// One way for a stream to shed load.
+    pub enum DegradeAction {
+        /// Cap the frame rate at `value` frames per second. Skipped if the stream already runs
+        /// that slow.
+        ReduceFps = 0 => CAPTURE_DEGRADE_REDUCE_FPS,
+        /// Scale frames to `value` percent (1 to 99) of their current size, so two steps of 50
+        /// leave a quarter of each side.
+        Downscale = 1 => CAPTURE_DEGRADE_DOWNSCALE,
+    }
+}
+
+/// A step of the degradation ladder set with capture_set_degradation_ladder().
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct DegradationStep {
+    /// A DegradeAction (CAPTURE_DEGRADE_*).
+    pub action: c_int,
+    /// Parameter of the action, as documented for each DegradeAction.
+    pub value: c_uint,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum Step {
+    ReduceFps(c_uint),
+    Downscale(c_uint),
+}
+
+static LADDER: Mutex<Vec<Step>> = Mutex::new(Vec::new());
+
+/// Tracks a stream's missed deadlines and how far down the ladder it has gone.
+pub(crate) struct Ladder {
+    steps: Vec<Step>,
+    next: usize,
+    missed: u32,
+}
+
+impl Ladder {
+    /// The ladder as currently configured; later changes don't affect it.
+    pub(crate) fn current() -> Self {
+        Ladder {
+            steps: LADDER.lock().unwrap_or_else(|e| e.into_inner()).clone(),
+            next: 0,
+            missed: 0,
+        }
+    }
+
+    /// Records whether the stream made its last frame deadline, and returns whether it should
+    /// degrade now. A step gets the next MISSES_PER_STEP deadlines to take effect.
+    pub(crate) fn tick(&mut self, missed: bool) -> bool {
+        if !missed {
+            self.missed = 0;
+            return false;
+        }
+        self.missed += 1;
+        if self.missed < MISSES_PER_STEP || self.next == self.steps.len() {
+            return false;
+        }
+        self.missed = 0;
+        true
+    }
+
+    /// The next step not taken yet, if any.
+    pub(crate) fn next_step(&mut self) -> Option<Step> {
+        let step = *self.steps.get(self.next)?;
+        self.next += 1;
+        Some(step)
+    }
+}
+
+fn read_step(index: usize, step: &DegradationStep) -> Result<Step, CaptureError> {
+    let invalid = |message: String| {
+        CaptureError::new(
+            ErrorCode::InvalidArgument,
+            format!("Degradation step {}: {}", index, message),
+        )
+    };
+    match DegradeAction::from_raw(step.action) {
+        Some(DegradeAction::ReduceFps) if step.value == 0 => {
+            Err(invalid("the frame rate must be at least 1".to_string()))
+        }
+        Some(DegradeAction::ReduceFps) => Ok(Step::ReduceFps(step.value)),
+        Some(DegradeAction::Downscale) if !(1..100).contains(&step.value) => Err(invalid(format!(
+            "scale must be between 1 and 99%, got {}%",
+            step.value
+        ))),
+        Some(DegradeAction::Downscale) => Ok(Step::Downscale(step.value)),
+        None => Err(invalid(format!("unknown action {}", step.action))),
+    }
+}
+
+/// Sets the degradation ladder of streams started from now on, replacing the previous one; 0
+/// steps (the default) removes it. When a stream misses 5 frame deadlines in a row it takes the
+/// next step, in order, and reports it with a CAPTURE_EVENT_STREAM_DEGRADED event saying what it
+/// gave up; capture_stream_info() reflects the new frame rate and size. Steps are never undone,
+/// and once the ladder is exhausted the stream falls behind as it would without one.
+/// A typical ladder first reduces to 15 fps, then downscales to 50%.
+/// Returns CAPTURE_OK, or CAPTURE_ERROR_INVALID_ARGUMENT if a step is invalid, in which case the
+/// previous ladder is kept.
+///
+/// # Safety
+/// `steps` must point to `count` DegradationStep structs, or may be NULL if `count` is 0.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_set_degradation_ladder(
+    steps: *const DegradationStep,
+    count: size_t,
+) -> c_int {
+    let result = if count == 0 {
+        Ok(Vec::new())
+    } else if steps.is_null() {
+        Err(CaptureError::new(
+            ErrorCode::InvalidArgument,
+            "Degradation step list is NULL",
+        ))
+    } else {
+        // SAFETY: the caller promised `count` readable steps.
+        unsafe { std::slice::from_raw_parts(steps, count) }
+            .iter()
+            .enumerate()
+            .map(|(i, step)| read_step(i, step))
+            .collect()
+    };
+    match result {
+        Ok(ladder) => {
+            *LADDER.lock().unwrap_or_else(|e| e.into_inner()) = ladder;
+            ErrorCode::Ok as c_int
+        }
+        Err(err) => {
+            let code = err.code as c_int;
+            set_last_error(err);
+            code
+        }
+    }
+}
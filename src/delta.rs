@@ -0,0 +1,467 @@
+// capture-ffi/src/delta.rs
+//! A wire format for remote viewing over constrained links: a keyframe with the whole frame,
+//! then packets with only the tiles that changed since the previous frame, each encoded on its
+//! own, and the matching decoder that puts the frames back together.
+//!
+//! Every packet starts with a 24-byte header, all integers little-endian:
+//!
+//! ```text
+//! offset  size  field
+//!      0     4  magic "XCDP"
+//!      4     1  version, 1
+//!      5     1  kind: 0 keyframe, 1 delta
+//!      6     2  tile size in pixels
+//!      8     4  frame width
+//!     12     4  frame height
+//!     16     4  sequence number, one more than the previous packet's
+//!     20     4  number of tiles that follow
+//! ```
+//!
+//! Each tile is its x and y in pixels (u32 each), the length of its encoded image (u32), then
+//! that PNG or JPEG image. A keyframe has a single tile at 0, 0 covering the whole frame; a delta
+//! tile covers `tile size` pixels square, less at the right and bottom edges.
+
+use crate::{
+    CaptureError, CapturedImage, ErrorCode, buffer,
+    encode::{self, WriteCallback},
+    handle::{CaptureHandle, HandleType, Registry},
+    limits,
+    options::EncodeOptions,
+    set_last_error, usage,
+};
+use image::{ImageReader, Limits, RgbaImage, imageops};
+use libc::{c_int, c_uint, c_void, size_t};
+use std::{io::Cursor, slice, sync::Mutex};
+
+const MAGIC: &[u8; 4] = b"XCDP";
+const VERSION: u8 = 1;
+const KEYFRAME: u8 = 0;
+const DELTA: u8 = 1;
+const HEADER_LEN: usize = 24;
+const TILE_HEADER_LEN: usize = 12;
+/// Tile size used when the caller passes 0, and the range allowed.
+const DEFAULT_TILE_SIZE: c_uint = 64;
+const MIN_TILE_SIZE: c_uint = 8;
+const MAX_TILE_SIZE: c_uint = 1024;
+
+struct EncoderState {
+    /// The last frame sent, which the receiving decoder now shows.
+    reference: Option<RgbaImage>,
+    sequence: u32,
+    /// Packets sent since the last keyframe.
+    since_keyframe: u32,
+    keyframe_requested: bool,
+}
+
+/// Turns frames into packets, created by capture_delta_encoder_new().
+struct DeltaEncoder {
+    tile_size: u32,
+    keyframe_interval: c_uint,
+    options: EncodeOptions,
+    state: Mutex<EncoderState>,
+}
+
+/// Puts frames back together from packets, created by capture_delta_decoder_new().
+struct DeltaDecoder {
+    /// The frame so far and the sequence number of the packet that produced it; None until the
+    /// first keyframe and after a packet was missed.
+    state: Mutex<Option<(RgbaImage, u32)>>,
+}
+
+static ENCODERS: Registry<DeltaEncoder> = Registry::new(HandleType::DeltaEncoder);
+static DECODERS: Registry<DeltaDecoder> = Registry::new(HandleType::DeltaDecoder);
+
+/// Whether the tile at `x`, `y` differs between two frames of the same size.
+fn tile_changed(previous: &RgbaImage, image: &RgbaImage, x: u32, y: u32, size: u32) -> bool {
+    let row_len = image.width() as usize * 4;
+    let start = x as usize * 4;
+    let end = (x + size).min(image.width()) as usize * 4;
+    (y..(y + size).min(image.height())).any(|row| {
+        let offset = row as usize * row_len;
+        previous.as_raw()[offset + start..offset + end]
+            != image.as_raw()[offset + start..offset + end]
+    })
+}
+
+fn push_tile(
+    packet: &mut Vec<u8>,
+    image: &RgbaImage,
+    x: u32,
+    y: u32,
+    options: &EncodeOptions,
+) -> Result<(), CaptureError> {
+    packet.extend_from_slice(&x.to_le_bytes());
+    packet.extend_from_slice(&y.to_le_bytes());
+    let len_at = packet.len();
+    packet.extend_from_slice(&[0; 4]);
+    encode::encode_to(image, options, &mut *packet)?;
+    let len = (packet.len() - len_at - 4) as u32;
+    packet[len_at..len_at + 4].copy_from_slice(&len.to_le_bytes());
+    Ok(())
+}
+
+impl DeltaEncoder {
+    fn encode(&self, image: RgbaImage) -> Result<Vec<u8>, CaptureError> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let (width, height) = image.dimensions();
+        let size = self.tile_size;
+        let changed: Vec<(u32, u32)> = match &state.reference {
+            Some(previous) if previous.dimensions() == (width, height) => (0..height)
+                .step_by(size as usize)
+                .flat_map(|y| (0..width).step_by(size as usize).map(move |x| (x, y)))
+                .filter(|&(x, y)| tile_changed(previous, &image, x, y, size))
+                .collect(),
+            _ => Vec::new(),
+        };
+        let tiles = width.div_ceil(size) as usize * height.div_ceil(size) as usize;
+        // Past half the tiles, one image of the whole frame compresses better than the pieces.
+        let keyframe = state.keyframe_requested
+            || state
+                .reference
+                .as_ref()
+                .is_none_or(|previous| previous.dimensions() != (width, height))
+            || (self.keyframe_interval > 0 && state.since_keyframe + 1 >= self.keyframe_interval)
+            || changed.len() * 2 > tiles;
+
+        let sequence = state.sequence.wrapping_add(1);
+        let mut packet = Vec::with_capacity(HEADER_LEN);
+        packet.extend_from_slice(MAGIC);
+        packet.push(VERSION);
+        packet.push(if keyframe { KEYFRAME } else { DELTA });
+        packet.extend_from_slice(&(size as u16).to_le_bytes());
+        packet.extend_from_slice(&width.to_le_bytes());
+        packet.extend_from_slice(&height.to_le_bytes());
+        packet.extend_from_slice(&sequence.to_le_bytes());
+        if keyframe {
+            packet.extend_from_slice(&1u32.to_le_bytes());
+            push_tile(&mut packet, &image, 0, 0, &self.options)?;
+        } else {
+            packet.extend_from_slice(&(changed.len() as u32).to_le_bytes());
+            for &(x, y) in &changed {
+                let tile = imageops::crop_imm(&image, x, y, size, size).to_image();
+                push_tile(&mut packet, &tile, x, y, &self.options)?;
+            }
+        }
+
+        // Only a packet that was built completely counts as sent.
+        state.reference = Some(image);
+        state.sequence = sequence;
+        state.since_keyframe = if keyframe {
+            0
+        } else {
+            state.since_keyframe + 1
+        };
+        state.keyframe_requested = false;
+        Ok(packet)
+    }
+}
+
+fn malformed(message: impl std::fmt::Display) -> CaptureError {
+    CaptureError::new(
+        ErrorCode::InvalidArgument,
+        format!("Malformed delta packet: {}", message),
+    )
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, CaptureError> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap_or_default()))
+        .ok_or_else(|| malformed("truncated"))
+}
+
+impl DeltaDecoder {
+    fn decode(&self, packet: &[u8]) -> Result<RgbaImage, CaptureError> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        // Taken up front, so a packet that fails for any reason leaves nothing to build on.
+        let last = state.take();
+        if packet.len() < HEADER_LEN || &packet[0..4] != MAGIC {
+            return Err(malformed("not a delta packet"));
+        }
+        if packet[4] != VERSION {
+            return Err(malformed(format!("unsupported version {}", packet[4])));
+        }
+        let kind = packet[5];
+        let tile_size = u16::from_le_bytes([packet[6], packet[7]]) as u32;
+        let (width, height) = (u32_at(packet, 8)?, u32_at(packet, 12)?);
+        let sequence = u32_at(packet, 16)?;
+        let count = u32_at(packet, 20)?;
+        if width == 0 || height == 0 {
+            return Err(malformed(format!("empty {}x{} frame", width, height)));
+        }
+
+        // A tile never decodes to more than the frame (keyframes) or one tile (deltas), and never
+        // to more than the decoder's default allocation limit, whatever the header claims.
+        let mut limits = Limits::default();
+        let max_side = |side: u32| if kind == KEYFRAME { side } else { tile_size };
+        limits.max_image_width = Some(max_side(width));
+        limits.max_image_height = Some(max_side(height));
+        let mut offset = HEADER_LEN;
+        let mut next_tile = || -> Result<(u32, u32, RgbaImage), CaptureError> {
+            let (x, y) = (u32_at(packet, offset)?, u32_at(packet, offset + 4)?);
+            let len = u32_at(packet, offset + 8)? as usize;
+            offset += TILE_HEADER_LEN;
+            let data = packet
+                .get(offset..offset.saturating_add(len))
+                .ok_or_else(|| malformed("truncated"))?;
+            offset += len;
+            let mut reader = ImageReader::new(Cursor::new(data))
+                .with_guessed_format()
+                .map_err(|e| malformed(format!("tile at {}, {}: {}", x, y, e)))?;
+            reader.limits(limits.clone());
+            let tile = reader
+                .decode()
+                .map_err(|e| malformed(format!("tile at {}, {}: {}", x, y, e)))?
+                .to_rgba8();
+            Ok((x, y, tile))
+        };
+
+        let frame = match (kind, last) {
+            (KEYFRAME, _) => {
+                limits::check_nominal(width, height)?;
+                if count != 1 {
+                    return Err(malformed(format!("keyframe with {} tiles", count)));
+                }
+                // The frame is the decoded tile, so nothing is allocated from the header alone.
+                let (x, y, tile) = next_tile()?;
+                if (x, y) != (0, 0) || tile.dimensions() != (width, height) {
+                    return Err(malformed(format!(
+                        "keyframe tile is {}x{} at {}, {}, not the whole {}x{} frame",
+                        tile.width(),
+                        tile.height(),
+                        x,
+                        y,
+                        width,
+                        height
+                    )));
+                }
+                tile
+            }
+            (DELTA, _) if tile_size == 0 => return Err(malformed("zero tile size")),
+            (DELTA, Some((mut frame, previous)))
+                if previous.wrapping_add(1) == sequence
+                    && frame.dimensions() == (width, height) =>
+            {
+                for _ in 0..count {
+                    let (x, y, tile) = next_tile()?;
+                    if x as u64 + tile.width() as u64 > width as u64
+                        || y as u64 + tile.height() as u64 > height as u64
+                    {
+                        return Err(malformed(format!(
+                            "{}x{} tile at {}, {} doesn't fit the {}x{} frame",
+                            tile.width(),
+                            tile.height(),
+                            x,
+                            y,
+                            width,
+                            height
+                        )));
+                    }
+                    imageops::replace(&mut frame, &tile, x as i64, y as i64);
+                }
+                frame
+            }
+            (DELTA, _) => {
+                return Err(CaptureError::new(
+                    ErrorCode::InvalidArgument,
+                    format!(
+                        "Delta packet {} doesn't follow the last packet decoded; a keyframe is \
+                         needed",
+                        sequence
+                    ),
+                ));
+            }
+            (kind, _) => return Err(malformed(format!("unknown kind {}", kind))),
+        };
+        let image = frame.clone();
+        *state = Some((frame, sequence));
+        Ok(image)
+    }
+}
+
+fn new_encoder(
+    tile_size: c_uint,
+    keyframe_interval: c_uint,
+    options: EncodeOptions,
+) -> Result<CaptureHandle, CaptureError> {
+    let tile_size = match tile_size {
+        0 => DEFAULT_TILE_SIZE,
+        MIN_TILE_SIZE..=MAX_TILE_SIZE => tile_size,
+        _ => {
+            return Err(CaptureError::new(
+                ErrorCode::InvalidArgument,
+                format!(
+                    "Tile size must be between {} and {} pixels, got {}",
+                    MIN_TILE_SIZE, MAX_TILE_SIZE, tile_size
+                ),
+            ));
+        }
+    };
+    Ok(ENCODERS.insert(DeltaEncoder {
+        tile_size,
+        keyframe_interval,
+        options,
+        state: Mutex::new(EncoderState {
+            reference: None,
+            sequence: 0,
+            since_keyframe: 0,
+            keyframe_requested: false,
+        }),
+    }))
+}
+
+fn status(result: Result<(), CaptureError>) -> c_int {
+    match result {
+        Ok(()) => ErrorCode::Ok as c_int,
+        Err(err) => {
+            let code = err.code as c_int;
+            set_last_error(err);
+            code
+        }
+    }
+}
+
+/// Creates an encoder for the delta wire format (see src/delta.rs for the packet layout): the
+/// first frame becomes a keyframe, later ones packets of only the `tile_size`-pixel tiles
+/// (0 means 64, 8 to 1024) that changed since the previous frame. A keyframe is sent instead
+/// when the frame size changes, when more than half the tiles changed, every
+/// `keyframe_interval` packets (0 means only then) so viewers that join late or lose packets
+/// recover, and after capture_delta_encoder_request_keyframe(). Tiles are encoded as
+/// `options` say, PNG by default; JPEG tiles make smaller packets but lose detail. `options` may
+/// be NULL.
+/// The caller MUST call capture_delta_encoder_free() on the returned handle.
+/// Returns 0 and sets CAPTURE_ERROR_INVALID_ARGUMENT if an argument is out of range.
+///
+/// # Safety
+/// `options` must be NULL or point to EncodeOptions initialized with
+/// capture_encode_options_default().
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_delta_encoder_new(
+    tile_size: c_uint,
+    keyframe_interval: c_uint,
+    options: *const EncodeOptions,
+) -> CaptureHandle {
+    let result = unsafe { EncodeOptions::read(options) }
+        .and_then(|options| new_encoder(tile_size, keyframe_interval, options));
+    match result {
+        Ok(handle) => handle,
+        Err(err) => {
+            set_last_error(err);
+            0
+        }
+    }
+}
+
+/// Encodes the next frame of a delta stream, typically one delivered by a stream or region
+/// watch, and passes the whole packet to `write` in a single call on the calling thread, so
+/// each call can become one message on the link. A frame identical to the previous one still
+/// makes a (header-only) packet, to keep sequence numbers contiguous. The image is not consumed.
+/// Returns CAPTURE_OK or an error code; if `write` fails with non-zero, the packet counts as
+/// lost and the next one is a keyframe.
+///
+/// # Safety
+/// `write` must be valid until this function returns.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_delta_encode(
+    encoder: CaptureHandle,
+    image: CapturedImage,
+    write: WriteCallback,
+    user_data: *mut c_void,
+) -> c_int {
+    let Some(callback) = write else {
+        return status(Err(CaptureError::new(
+            ErrorCode::InvalidArgument,
+            "Write callback must not be NULL",
+        )));
+    };
+    status(ENCODERS.get(encoder).and_then(|encoder| {
+        let packet = usage::encoding(buffer::owner(image.data), || {
+            encoder.encode(buffer::to_rgba(&image)?)
+        })?;
+        // SAFETY: the caller promised the callback is valid for the duration of the call.
+        let result = unsafe { callback(packet.as_ptr(), packet.len(), user_data) };
+        if result != 0 {
+            encoder
+                .state
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .keyframe_requested = true;
+            return Err(CaptureError::new(
+                ErrorCode::Io,
+                format!("Write callback aborted with status {}", result),
+            ));
+        }
+        Ok(())
+    }))
+}
+
+/// Makes the next packet of a delta stream a keyframe, e.g. when a viewer connects or reports
+/// that it lost a packet.
+/// Returns CAPTURE_OK, or CAPTURE_ERROR_INVALID_HANDLE if `encoder` isn't a live encoder.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_delta_encoder_request_keyframe(encoder: CaptureHandle) -> c_int {
+    status(ENCODERS.get(encoder).map(|encoder| {
+        encoder
+            .state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .keyframe_requested = true;
+    }))
+}
+
+/// Frees an encoder handle.
+/// Returns CAPTURE_OK, or CAPTURE_ERROR_INVALID_HANDLE if `encoder` isn't a live encoder.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_delta_encoder_free(encoder: CaptureHandle) -> c_int {
+    status(ENCODERS.remove(encoder).map(drop))
+}
+
+/// Creates a decoder for packets made by capture_delta_encode(), on the receiving side.
+/// The caller MUST call capture_delta_decoder_free() on the returned handle.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_delta_decoder_new() -> CaptureHandle {
+    DECODERS.insert(DeltaDecoder {
+        state: Mutex::new(None),
+    })
+}
+
+/// Applies the next packet of a delta stream and returns the whole frame it makes.
+/// Packets must arrive in order: after a missed or out-of-order delta packet this fails with
+/// CAPTURE_ERROR_INVALID_ARGUMENT until the next keyframe, which the sender can be asked for
+/// with capture_delta_encoder_request_keyframe(). Malformed packets fail the same way and leave
+/// the decoder waiting for a keyframe too.
+/// The caller MUST call capture_free_image() on the returned struct to free the data buffer.
+/// Returns a struct with NULL data pointer on error; see capture_last_error_message().
+///
+/// # Safety
+/// `data` must point to `len` readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_delta_decode(
+    decoder: CaptureHandle,
+    data: *const u8,
+    len: size_t,
+) -> CapturedImage {
+    let result = DECODERS.get(decoder).and_then(|decoder| {
+        if data.is_null() {
+            return Err(CaptureError::new(
+                ErrorCode::InvalidArgument,
+                "Packet data is NULL",
+            ));
+        }
+        // SAFETY: the caller promised `len` readable bytes.
+        decoder.decode(unsafe { slice::from_raw_parts(data, len) })
+    });
+    match result {
+        Ok(image) => CapturedImage::from_rgba(image),
+        Err(err) => {
+            set_last_error(err);
+            CapturedImage::empty()
+        }
+    }
+}
+
+/// Frees a decoder handle.
+/// Returns CAPTURE_OK, or CAPTURE_ERROR_INVALID_HANDLE if `decoder` isn't a live decoder.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_delta_decoder_free(decoder: CaptureHandle) -> c_int {
+    status(DECODERS.remove(decoder).map(drop))
+}
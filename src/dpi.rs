@@ -0,0 +1,36 @@
+// capture-ffi/src/dpi.rs
+//! Resampling captures to a fixed scale of logical pixels, so screenshots from monitors with
+//! different scale factors come out the same size.
+
+use crate::{CaptureError, limits};
+use image::{RgbaImage, imageops};
+use libc::c_uint;
+
+/// Largest CaptureOptions.output_scale, in percent.
+pub(crate) const MAX_OUTPUT_SCALE: c_uint = 800;
+
+/// Resamples a frame showing `logical` (width, height) of its target's logical pixels to
+/// `output_scale` percent of that size. 0 keeps the frame as captured. The size comes from
+/// `logical` rather than the frame, so a frame the size limit already shrank isn't shrunk again.
+pub(crate) fn normalize(
+    image: RgbaImage,
+    logical: (u32, u32),
+    output_scale: c_uint,
+) -> Result<RgbaImage, CaptureError> {
+    if output_scale == 0 || logical.0 == 0 || logical.1 == 0 {
+        return Ok(image);
+    }
+    let scaled = |side: u32| ((side as u64 * output_scale as u64 / 100) as u32).max(1);
+    let (new_width, new_height) = (scaled(logical.0), scaled(logical.1));
+    if (new_width, new_height) == image.dimensions() {
+        return Ok(image);
+    }
+    // Scaling up can push a frame over the size limit.
+    limits::check_nominal(new_width, new_height)?;
+    limits::apply(imageops::resize(
+        &image,
+        new_width,
+        new_height,
+        imageops::FilterType::CatmullRom,
+    ))
+}
@@ -0,0 +1,483 @@
+// capture-ffi/src/encode.rs
+//! Encoding captures straight from the backend frame, and images the caller brings.
+//! Captures encode without a second full-size copy of the pixels: PNG rows are fed to a
+//! streaming compressor and JPEG reads the frame in 8x8 blocks, so peak memory is one RGBA frame
+//! plus small encoder buffers. Images the caller brings are copied out of their buffer first,
+//! so encoding one briefly holds a second copy.
+
+use crate::{
+    CaptureError, CapturedImage, ErrorCode, UserData, buffer, capture_frame, gallery, monitor_at,
+    options::EncodeOptions,
+    output::{self, TemplateFields},
+    set_last_error,
+    target::CaptureTarget,
+    usage,
+};
+use image::{ImageError, RgbaImage, codecs::jpeg::JpegEncoder};
+use libc::{c_char, c_int, c_uint, c_void, size_t};
+use std::{
+    ffi::{CStr, CString},
+    fs::File,
+    io::{self, BufWriter, Write},
+    mem::ManuallyDrop,
+    path::PathBuf,
+    ptr,
+};
+
+ffi_enum! {
+    /// Compressed image formats for saving and encoding captures.
+    pub enum ImageFormat {
+        Png = 0 => CAPTURE_FORMAT_PNG,
+        Jpeg = 1 => CAPTURE_FORMAT_JPEG,
+    }
+}
+
+/// Bytes of compressed PNG data buffered before an IDAT chunk is written out.
+const PNG_CHUNK_SIZE: usize = 64 * 1024;
+/// Largest chunk passed to a WriteCallback.
+const CALLBACK_CHUNK_SIZE: usize = 64 * 1024;
+
+fn encode_error(err: impl std::fmt::Display) -> CaptureError {
+    CaptureError::new(ErrorCode::Failed, format!("Error encoding image: {}", err))
+}
+
+impl From<png::EncodingError> for CaptureError {
+    fn from(err: png::EncodingError) -> Self {
+        match err {
+            png::EncodingError::IoError(err) => err.into(),
+            err => encode_error(err),
+        }
+    }
+}
+
+impl From<ImageError> for CaptureError {
+    fn from(err: ImageError) -> Self {
+        match err {
+            ImageError::IoError(err) => err.into(),
+            err => encode_error(err),
+        }
+    }
+}
+
+fn encode_png<W: Write>(image: &RgbaImage, writer: W) -> Result<(), CaptureError> {
+    let (width, height) = image.dimensions();
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    let mut stream = writer.stream_writer_with_size(PNG_CHUNK_SIZE)?;
+    for row in image.as_raw().chunks_exact(width as usize * 4) {
+        stream.write_all(row)?;
+    }
+    stream.finish()?;
+    Ok(())
+}
+
+/// Encodes a frame into `writer` as described by `options`.
+pub(crate) fn encode_to<W: Write>(
+    image: &RgbaImage,
+    options: &EncodeOptions,
+    writer: W,
+) -> Result<(), CaptureError> {
+    match options.image_format()? {
+        ImageFormat::Png => encode_png(image, writer),
+        ImageFormat::Jpeg => {
+            // Rgba8 views are encoded block by block with the alpha channel dropped.
+            let mut encoder = JpegEncoder::new_with_quality(writer, options.jpeg_quality()?);
+            encoder.encode_image(image)?;
+            Ok(())
+        }
+    }
+}
+
+/// Receives the next `len` bytes of an encoded image, valid only during the call.
+/// Returns 0 to continue, anything else to abort the encode with CAPTURE_ERROR_IO.
+pub type WriteCallback =
+    Option<unsafe extern "C" fn(data: *const u8, len: size_t, user_data: *mut c_void) -> c_int>;
+
+/// Hands everything written to it to a WriteCallback.
+struct CallbackWriter {
+    callback: unsafe extern "C" fn(*const u8, size_t, *mut c_void) -> c_int,
+    user_data: UserData,
+}
+
+impl Write for CallbackWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // SAFETY: the caller promised the callback is valid for the duration of the encode.
+        let status = unsafe { (self.callback)(buf.as_ptr(), buf.len(), self.user_data.0) };
+        if status != 0 {
+            return Err(io::Error::other(format!(
+                "Write callback aborted with status {}",
+                status
+            )));
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Reads a NUL-terminated UTF-8 path.
+///
+/// # Safety
+/// `path` must be NULL or a valid NUL-terminated string.
+pub(crate) unsafe fn read_path<'a>(path: *const c_char) -> Result<&'a str, CaptureError> {
+    if path.is_null() {
+        return Err(CaptureError::new(
+            ErrorCode::InvalidArgument,
+            "Path is NULL",
+        ));
+    }
+    unsafe { CStr::from_ptr(path) }
+        .to_str()
+        .map_err(|_| CaptureError::new(ErrorCode::InvalidArgument, "Path is not valid UTF-8"))
+}
+
+fn capture_monitor(index: usize) -> Result<(RgbaImage, CaptureTarget), CaptureError> {
+    let monitor = monitor_at(index)?;
+    let image = capture_frame(&monitor).map_err(capture_error(index))?;
+    Ok((image, CaptureTarget::monitor(&monitor)))
+}
+
+fn capture_error(index: usize) -> impl FnOnce(CaptureError) -> CaptureError {
+    move |e| e.context(format!("Error capturing image for monitor {}", index))
+}
+
+fn save_monitor(
+    index: usize,
+    template: &str,
+    options: &EncodeOptions,
+) -> Result<PathBuf, CaptureError> {
+    let monitor = monitor_at(index)?;
+    let path = output::expand_template(
+        template,
+        &TemplateFields {
+            monitor: monitor.name(),
+            title: monitor.name(),
+        },
+    )?;
+    let image = capture_frame(&monitor).map_err(capture_error(index))?;
+    let saved = output::write_file(&path, options, |writer| encode_to(&image, options, writer))?;
+    gallery::record_frame(image, CaptureTarget::monitor(&monitor));
+    Ok(saved)
+}
+
+/// An open file descriptor (a HANDLE on Windows) to write output to: a regular file, pipe,
+/// socket, or anything else the host opened, e.g. a file handed out by a sandbox portal.
+/// The library only borrows it and never closes it.
+#[cfg(any(unix, target_os = "wasi"))]
+pub type CaptureFd = c_int;
+/// The Windows flavor of CaptureFd: a HANDLE.
+#[cfg(windows)]
+pub type CaptureFd = *mut c_void;
+
+/// Wraps a borrowed descriptor in a File that won't close it when dropped.
+///
+/// # Safety
+/// `fd` must be an open descriptor or handle that stays open for as long as the result is used.
+pub(crate) unsafe fn borrow_fd(fd: CaptureFd) -> Result<ManuallyDrop<File>, CaptureError> {
+    #[cfg(any(unix, target_os = "wasi"))]
+    {
+        use std::os::fd::FromRawFd;
+        if fd < 0 {
+            return Err(CaptureError::new(
+                ErrorCode::InvalidArgument,
+                format!("Invalid file descriptor: {}", fd),
+            ));
+        }
+        Ok(ManuallyDrop::new(unsafe { File::from_raw_fd(fd) }))
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::io::FromRawHandle;
+        // INVALID_HANDLE_VALUE is -1.
+        if fd.is_null() || fd as isize == -1 {
+            return Err(CaptureError::new(
+                ErrorCode::InvalidArgument,
+                format!("Invalid file handle: {:p}", fd),
+            ));
+        }
+        Ok(ManuallyDrop::new(unsafe { File::from_raw_handle(fd) }))
+    }
+}
+
+fn write_fd(image: &RgbaImage, file: &File, options: &EncodeOptions) -> Result<(), CaptureError> {
+    let mut writer = BufWriter::new(file);
+    encode_to(image, options, &mut writer)
+        .and_then(|()| writer.flush().map_err(CaptureError::from))
+        .map_err(|e| e.context("Error writing to file descriptor"))
+}
+
+fn save_monitor_fd(index: usize, file: &File, options: &EncodeOptions) -> Result<(), CaptureError> {
+    let (image, target) = capture_monitor(index)?;
+    write_fd(&image, file, options)?;
+    gallery::record_frame(image, target);
+    Ok(())
+}
+
+fn save_window_fd(
+    window_id: c_uint,
+    file: &File,
+    options: &EncodeOptions,
+) -> Result<(), CaptureError> {
+    let target = CaptureTarget::window(window_id);
+    let image = target
+        .capture()
+        .map_err(|e| e.context(format!("Error capturing window {}", window_id)))?;
+    write_fd(&image, file, options)?;
+    gallery::record_frame(image, target);
+    Ok(())
+}
+
+fn encode_to_callback(
+    image: &RgbaImage,
+    options: &EncodeOptions,
+    writer: CallbackWriter,
+) -> Result<(), CaptureError> {
+    // Batch the encoders' small writes into chunks of a useful size.
+    let mut writer = BufWriter::with_capacity(CALLBACK_CHUNK_SIZE, writer);
+    encode_to(image, options, &mut writer)
+        .and_then(|()| writer.flush().map_err(CaptureError::from))
+        .map_err(|e| e.context("Error encoding image"))
+}
+
+fn encode_monitor(
+    index: usize,
+    options: &EncodeOptions,
+    writer: CallbackWriter,
+) -> Result<(), CaptureError> {
+    let (image, target) = capture_monitor(index)?;
+    encode_to_callback(&image, options, writer)?;
+    gallery::record_frame(image, target);
+    Ok(())
+}
+
+fn status(result: Result<(), CaptureError>) -> c_int {
+    match result {
+        Ok(()) => ErrorCode::Ok as c_int,
+        Err(err) => {
+            eprintln!("{}", err);
+            let code = err.code as c_int;
+            set_last_error(err);
+            code
+        }
+    }
+}
+
+/// Captures a monitor and saves it to `path` as PNG or JPEG, encoding straight from the captured
+/// frame so no second copy of the pixels is made. Unless EncodeOptions.atomic_write is turned off
+/// the file is written under a temporary name and renamed into place when complete.
+/// `path` is a template: `{monitor}` (the monitor's name), `{title}` (the same for monitor
+/// captures), `{timestamp}` (UTC, like 20240131T235959Z) and `{seq}` (a per-process counter,
+/// 0001 up) are replaced, with characters that aren't legal in file names replaced by `_`.
+/// Write `{{` and `}}` for literal braces. EncodeOptions.collision decides what happens when the
+/// file exists. `options` may be NULL for PNG with defaults.
+/// Returns CAPTURE_OK or an error code; see capture_last_error_message() for details.
+///
+/// # Safety
+/// `path` must be a valid NUL-terminated UTF-8 string. `options` must be NULL or point to
+/// EncodeOptions initialized with capture_encode_options_default().
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_monitor_save(
+    index: size_t,
+    path: *const c_char,
+    options: *const EncodeOptions,
+) -> c_int {
+    status(unsafe { read_path(path) }.and_then(|path| {
+        let options = unsafe { EncodeOptions::read(options)? };
+        save_monitor(index, path, &options).map(drop)
+    }))
+}
+
+/// Same as capture_monitor_save(), but returns the path that was actually written, after template
+/// expansion and collision handling.
+/// The caller MUST call capture_free_string() on the returned pointer to free the memory.
+/// Returns NULL on error; see capture_last_error_message() for details.
+///
+/// # Safety
+/// Same as capture_monitor_save().
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_monitor_save_as(
+    index: size_t,
+    path: *const c_char,
+    options: *const EncodeOptions,
+) -> *mut c_char {
+    let result = unsafe { read_path(path) }.and_then(|path| {
+        let options = unsafe { EncodeOptions::read(options)? };
+        let saved = save_monitor(index, path, &options)?;
+        CString::new(saved.to_string_lossy().into_owned())
+            .map_err(|_| CaptureError::from("Saved path contains null bytes".to_string()))
+    });
+    match result {
+        Ok(saved) => saved.into_raw(),
+        Err(err) => {
+            eprintln!("{}", err);
+            set_last_error(err);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Captures a monitor and encodes it like capture_monitor_save(), but passes the compressed
+/// bytes to `write` in chunks of up to 64 KiB as they are produced instead of writing a file, so
+/// the encoded image never has to be held in memory as a whole. `write` is called on the calling
+/// thread and may return non-zero to stop early.
+/// Returns CAPTURE_OK or an error code; see capture_last_error_message() for details.
+///
+/// # Safety
+/// `write` must be valid until this function returns. `options` must be NULL or point to
+/// EncodeOptions initialized with capture_encode_options_default().
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_monitor_encode(
+    index: size_t,
+    options: *const EncodeOptions,
+    write: WriteCallback,
+    user_data: *mut c_void,
+) -> c_int {
+    let Some(callback) = write else {
+        return status(Err(CaptureError::new(
+            ErrorCode::InvalidArgument,
+            "Write callback must not be NULL",
+        )));
+    };
+    let writer = CallbackWriter {
+        callback,
+        user_data: UserData(user_data),
+    };
+    status(
+        unsafe { EncodeOptions::read(options) }
+            .and_then(|options| encode_monitor(index, &options, writer)),
+    )
+}
+
+/// Like capture_monitor_save(), but writes to an already open file descriptor (a HANDLE on
+/// Windows) instead of a path, for hosts that can't or won't let the library open files itself:
+/// sandboxed apps passing portal-provided files, pipes or sockets. Writing starts at the
+/// descriptor's current position; the descriptor is left open.
+/// Returns CAPTURE_OK or an error code; see capture_last_error_message() for details.
+///
+/// # Safety
+/// `fd` must be open and writable until this function returns. `options` must be NULL or point to
+/// EncodeOptions initialized with capture_encode_options_default().
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_monitor_save_fd(
+    index: size_t,
+    fd: CaptureFd,
+    options: *const EncodeOptions,
+) -> c_int {
+    status(unsafe { borrow_fd(fd) }.and_then(|file| {
+        let options = unsafe { EncodeOptions::read(options)? };
+        save_monitor_fd(index, &file, &options)
+    }))
+}
+
+/// Captures a window by its ID (see capture_window_id()) and writes it to an already open file
+/// descriptor like capture_monitor_save_fd().
+/// Returns CAPTURE_OK or an error code; see capture_last_error_message() for details.
+///
+/// # Safety
+/// Same as capture_monitor_save_fd().
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_window_save_fd(
+    window_id: c_uint,
+    fd: CaptureFd,
+    options: *const EncodeOptions,
+) -> c_int {
+    status(unsafe { borrow_fd(fd) }.and_then(|file| {
+        let options = unsafe { EncodeOptions::read(options)? };
+        save_window_fd(window_id, &file, &options)
+    }))
+}
+
+fn save_image(
+    image: &CapturedImage,
+    template: &str,
+    options: &EncodeOptions,
+) -> Result<PathBuf, CaptureError> {
+    usage::encoding(buffer::owner(image.data), || {
+        let image = buffer::to_rgba(image)?;
+        let path = output::expand_template(
+            template,
+            &TemplateFields {
+                monitor: "",
+                title: "",
+            },
+        )?;
+        output::write_file(&path, options, |writer| encode_to(&image, options, writer))
+    })
+}
+
+/// Saves an image the caller already has, e.g. one made with capture_image_from_rgba() or
+/// capture_compose_grid(), like capture_monitor_save() saves a capture. `{monitor}` and
+/// `{title}` expand to `_` in `path`; `{timestamp}` and `{seq}` work as usual.
+/// The image is not consumed.
+/// Returns CAPTURE_OK or an error code; see capture_last_error_message() for details.
+///
+/// # Safety
+/// `path` must be a valid NUL-terminated UTF-8 string. `options` must be NULL or point to
+/// EncodeOptions initialized with capture_encode_options_default().
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_image_save(
+    image: CapturedImage,
+    path: *const c_char,
+    options: *const EncodeOptions,
+) -> c_int {
+    status(unsafe { read_path(path) }.and_then(|path| {
+        let options = unsafe { EncodeOptions::read(options)? };
+        save_image(&image, path, &options).map(drop)
+    }))
+}
+
+/// Saves an image the caller already has like capture_image_save(), but to an already open file
+/// descriptor like capture_monitor_save_fd(). The image is not consumed.
+/// Returns CAPTURE_OK or an error code; see capture_last_error_message() for details.
+///
+/// # Safety
+/// Same as capture_monitor_save_fd().
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_image_save_fd(
+    image: CapturedImage,
+    fd: CaptureFd,
+    options: *const EncodeOptions,
+) -> c_int {
+    status(unsafe { borrow_fd(fd) }.and_then(|file| {
+        let options = unsafe { EncodeOptions::read(options)? };
+        usage::encoding(buffer::owner(image.data), || {
+            write_fd(&buffer::to_rgba(&image)?, &file, &options)
+        })
+    }))
+}
+
+/// Encodes an image the caller already has like capture_monitor_encode(), passing the compressed
+/// bytes to `write` in chunks. The image is not consumed.
+/// Returns CAPTURE_OK or an error code; see capture_last_error_message() for details.
+///
+/// # Safety
+/// `write` must be valid until this function returns. `options` must be NULL or point to
+/// EncodeOptions initialized with capture_encode_options_default().
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_image_encode(
+    image: CapturedImage,
+    options: *const EncodeOptions,
+    write: WriteCallback,
+    user_data: *mut c_void,
+) -> c_int {
+    let Some(callback) = write else {
+        return status(Err(CaptureError::new(
+            ErrorCode::InvalidArgument,
+            "Write callback must not be NULL",
+        )));
+    };
+    let writer = CallbackWriter {
+        callback,
+        user_data: UserData(user_data),
+    };
+    status(unsafe { EncodeOptions::read(options) }.and_then(|options| {
+        usage::encoding(buffer::owner(image.data), || {
+            let image = buffer::to_rgba(&image)?;
+            encode_to_callback(&image, &options, writer)
+        })
+    }))
+}
@@ -0,0 +1,105 @@
+// capture-ffi/src/enums.rs
+//! Every enum that crosses the FFI boundary is declared with `ffi_enum!`, which also exports
+//! each value as a C constant (a `c_int` symbol) and records it for capture_enums_json(),
+//! so bindings never have to hardcode the numbers.
+
+use libc::{c_char, c_int};
+use std::{ffi::CString, ptr};
+
+/// The C-visible name of an enum and its (constant name, value) pairs.
+pub(crate) struct EnumTable {
+    pub(crate) name: &'static str,
+    pub(crate) values: &'static [(&'static str, c_int)],
+}
+
+macro_rules! ffi_enum {
+    (
+        $(#[$meta:meta])*
+        pub enum $name:ident {
+            $(
+                $(#[$variant_meta:meta])*
+                $variant:ident = $value:literal => $constant:ident,
+            )+
+        }
+    ) => {
+        $(#[$meta])*
+        #[repr(C)]
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        pub enum $name {
+            $(
+                $(#[$variant_meta])*
+                $variant = $value,
+            )+
+        }
+
+        impl $name {
+            pub(crate) const TABLE: $crate::enums::EnumTable = $crate::enums::EnumTable {
+                name: stringify!($name),
+                values: &[$((stringify!($constant), $value)),+],
+            };
+
+            /// Converts a raw value received over FFI, which may be out of range.
+            #[allow(dead_code)]
+            pub(crate) fn from_raw(value: libc::c_int) -> Option<Self> {
+                match value {
+                    $($value => Some($name::$variant),)+
+                    _ => None,
+                }
+            }
+        }
+
+        $(
+            #[unsafe(no_mangle)]
+            pub static $constant: libc::c_int = $value;
+        )+
+    };
+}
+
+/// All enums exported over FFI, in the order they appear in capture_enums_json().
+const TABLES: &[EnumTable] = &[
+    crate::ErrorCode::TABLE,
+    crate::Feature::TABLE,
+    crate::stream::PixelFormat::TABLE,
+    crate::stream::CursorMode::TABLE,
+    crate::stream::ColorSpace::TABLE,
+    crate::event::EventType::TABLE,
+    crate::handle::HandleType::TABLE,
+    crate::limits::OversizePolicy::TABLE,
+    crate::encode::ImageFormat::TABLE,
+    crate::output::CollisionPolicy::TABLE,
+    crate::target::TargetKind::TABLE,
+    crate::callback::CallbackKind::TABLE,
+    crate::degrade::DegradeAction::TABLE,
+];
+
+fn enums_json() -> String {
+    let enums = TABLES
+        .iter()
+        .map(|table| {
+            let values = table
+                .values
+                .iter()
+                .map(|(constant, value)| format!("\"{}\":{}", constant, value))
+                .collect::<Vec<_>>()
+                .join(",");
+            format!("\"{}\":{{{}}}", table.name, values)
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    format!(
+        "{{\"version\":\"{}\",\"enums\":{{{}}}}}",
+        env!("CARGO_PKG_VERSION"),
+        enums
+    )
+}
+
+/// Describes every enum of the C API as JSON, so dynamic-language bindings can build their
+/// constant tables at runtime:
+/// `{"version":"0.1.0","enums":{"ErrorCode":{"CAPTURE_OK":0,...},...}}`
+/// The values are the same as the exported `CAPTURE_*` constants.
+/// The caller MUST call capture_free_string() on the returned pointer to free the memory.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_enums_json() -> *mut c_char {
+    // Constant names are plain identifiers, so the JSON never contains a NUL byte.
+    CString::new(enums_json()).map_or(ptr::null_mut(), CString::into_raw)
+}
@@ -0,0 +1,190 @@
+// capture-ffi/src/event.rs
+use crate::{CaptureError, ErrorCode, UserData, handle::CaptureHandle, set_last_error};
+use libc::{c_char, c_uint, c_void, size_t};
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    ffi::CString,
+    ptr,
+    sync::{Condvar, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Events kept for capture_events_poll() at most; older ones are dropped first.
+const QUEUE_CAPACITY: usize = 256;
+
+ffi_enum! {
+    /// Kinds of events reported through the event callback.
+    pub enum EventType {
+        /// A stream or composite recording delivered its negotiated parameters and is about to
+        /// produce frames.
+        StreamStarted = 0 => CAPTURE_EVENT_STREAM_STARTED,
+        /// A stream or composite recording stopped and will not deliver any more frames.
+        StreamStopped = 1 => CAPTURE_EVENT_STREAM_STOPPED,
+        /// A stream or composite recording failed to capture a frame; `message` says why. It
+        /// keeps running.
+        StreamError = 2 => CAPTURE_EVENT_STREAM_ERROR,
+        /// A region watch or heatmap failed to capture; `message` says why. It keeps running.
+        WatchError = 3 => CAPTURE_EVENT_WATCH_ERROR,
+        /// A stream that kept missing its frame deadlines took the next step of the degradation
+        /// ladder; `message` says what it gave up.
+        StreamDegraded = 4 => CAPTURE_EVENT_STREAM_DEGRADED,
+    }
+}
+
+/// An event delivered to the callback registered with capture_set_event_callback.
+#[repr(C)]
+pub struct CaptureEvent {
+    pub event_type: EventType,
+    /// Handle of the object the event is about (e.g. the stream returned by capture_stream_start).
+    pub source: CaptureHandle,
+    /// When the event happened, in milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+    /// Null-terminated UTF-8 details, or NULL. Only valid for the duration of the callback.
+    pub message: *const c_char,
+}
+
+pub type EventCallback =
+    Option<unsafe extern "C" fn(event: *const CaptureEvent, user_data: *mut c_void)>;
+
+type EventSink = (
+    unsafe extern "C" fn(*const CaptureEvent, *mut c_void),
+    UserData,
+);
+
+static EVENT_SINK: Mutex<Option<EventSink>> = Mutex::new(None);
+
+/// An event waiting for capture_events_poll().
+struct QueuedEvent {
+    event_type: EventType,
+    source: CaptureHandle,
+    timestamp_ms: u64,
+    message: Option<CString>,
+}
+
+static QUEUE: Mutex<VecDeque<QueuedEvent>> = Mutex::new(VecDeque::new());
+static QUEUED: Condvar = Condvar::new();
+
+thread_local! {
+    /// Messages of the events last returned by capture_events_poll() on this thread.
+    static POLLED_MESSAGES: RefCell<Vec<CString>> = const { RefCell::new(Vec::new()) };
+}
+
+pub(crate) fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+/// Reports an event to the registered callback on the calling thread, or queues it for
+/// capture_events_poll() if there is none.
+pub(crate) fn emit(event_type: EventType, source: CaptureHandle, message: Option<&str>) {
+    let message = message.and_then(|m| CString::new(m).ok());
+    let sink = *EVENT_SINK.lock().unwrap_or_else(|e| e.into_inner());
+    let Some((callback, user_data)) = sink else {
+        let mut queue = QUEUE.lock().unwrap_or_else(|e| e.into_inner());
+        if queue.len() == QUEUE_CAPACITY {
+            queue.pop_front();
+        }
+        queue.push_back(QueuedEvent {
+            event_type,
+            source,
+            timestamp_ms: now_ms(),
+            message,
+        });
+        QUEUED.notify_all();
+        return;
+    };
+
+    let event = CaptureEvent {
+        event_type,
+        source,
+        timestamp_ms: now_ms(),
+        message: message.as_ref().map_or(ptr::null(), |m| m.as_ptr()),
+    };
+    // SAFETY: the caller promised the callback stays valid until it is replaced.
+    unsafe { callback(&event, user_data.0) };
+}
+
+/// Registers the callback that receives library events (stream start/stop/errors).
+/// Pass NULL to stop receiving events; they are then queued for capture_events_poll() instead.
+/// Only one callback is active at a time.
+/// The callback may be invoked from background threads owned by the library.
+///
+/// # Safety
+/// `callback` must stay valid, and safe to call from any thread, until it is replaced.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_set_event_callback(
+    callback: EventCallback,
+    user_data: *mut c_void,
+) {
+    *EVENT_SINK.lock().unwrap_or_else(|e| e.into_inner()) =
+        callback.map(|callback| (callback, UserData(user_data)));
+}
+
+/// Moves up to `max` queued events into `out`.
+///
+/// # Safety
+/// `out` must be valid for writing `max` CaptureEvent structs. They need not be initialized.
+unsafe fn poll(out: *mut CaptureEvent, max: usize, timeout: Duration) -> usize {
+    let deadline = Instant::now() + timeout;
+    let mut queue = QUEUE.lock().unwrap_or_else(|e| e.into_inner());
+    while queue.is_empty() {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        queue = QUEUED
+            .wait_timeout(queue, deadline - now)
+            .unwrap_or_else(|e| e.into_inner())
+            .0;
+    }
+    let count = queue.len().min(max);
+    let polled: Vec<QueuedEvent> = queue.drain(..count).collect();
+    drop(queue);
+    POLLED_MESSAGES.with(|messages| {
+        let mut messages = messages.borrow_mut();
+        messages.clear();
+        for (i, event) in polled.into_iter().enumerate() {
+            let slot = CaptureEvent {
+                event_type: event.event_type,
+                source: event.source,
+                timestamp_ms: event.timestamp_ms,
+                message: event.message.as_ref().map_or(ptr::null(), |m| m.as_ptr()),
+            };
+            // Written without reading or dropping what was there: the caller's memory may be
+            // uninitialized, and not every bit pattern is a valid event type.
+            // SAFETY: `i < count <= max`, and the caller promised room for `max` events.
+            unsafe { ptr::write(out.add(i), slot) };
+            // Moving the CString doesn't move its heap buffer, so the pointer stays valid.
+            messages.extend(event.message);
+        }
+    });
+    count
+}
+
+/// Copies up to `max` pending events, oldest first, into `out_events`, waiting up to
+/// `timeout_ms` milliseconds (0 to not wait) for at least one; the polling counterpart of
+/// capture_set_event_callback() for hosts that can't take calls from library threads. Events are
+/// only queued while no event callback is set, and only the latest 256 are kept.
+/// Each event's `message` stays valid until the next call to this function on the same thread.
+/// Returns the number of events copied, 0 if none arrived in time or on error.
+///
+/// # Safety
+/// `out_events` must point to room for `max` CaptureEvent structs, or may be NULL if `max` is 0.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_events_poll(
+    out_events: *mut CaptureEvent,
+    max: size_t,
+    timeout_ms: c_uint,
+) -> size_t {
+    if out_events.is_null() && max > 0 {
+        set_last_error(CaptureError::new(
+            ErrorCode::InvalidArgument,
+            "Event array is NULL",
+        ));
+        return 0;
+    }
+    // SAFETY: the caller promised room for `max` events.
+    unsafe { poll(out_events, max, Duration::from_millis(timeout_ms as u64)) }
+}
@@ -0,0 +1,245 @@
+// capture-ffi/src/gallery.rs
+//! An opt-in, bounded history of recent one-shot captures.
+//! Entries hold their own reference on the image buffer, so the host can free its copy right
+//! away and still get the capture back later, e.g. to re-save it.
+
+use crate::{
+    CaptureError, CapturedImage, ErrorCode, buffer,
+    event::now_ms,
+    handle::{CaptureHandle, HandleType, Registry},
+    options::read_sized,
+    set_last_error,
+    target::CaptureTarget,
+};
+use image::RgbaImage;
+use libc::{c_int, c_uint, size_t};
+use std::{collections::VecDeque, mem, sync::Mutex};
+
+/// Description of an image kept in the gallery.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct GalleryEntry {
+    /// Pass to capture_gallery_get(); stale once the entry is evicted.
+    pub id: CaptureHandle,
+    /// When the capture was taken, in milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+    /// What was captured.
+    pub target: CaptureTarget,
+    pub width: c_uint,
+    pub height: c_uint,
+}
+
+/// Filters for capture_gallery_list(). Versioned like CaptureOptions: start from
+/// capture_gallery_query_default(), which matches everything.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct GalleryQuery {
+    /// Size of this struct in bytes as known by the caller.
+    pub struct_size: size_t,
+    /// Only entries taken at or after this time (ms since the Unix epoch).
+    pub since_ms: u64,
+    /// Only entries taken at or before this time; 0 means no upper bound.
+    pub until_ms: u64,
+    /// Non-zero to only list entries whose target equals `target`.
+    pub match_target: c_int,
+    pub target: CaptureTarget,
+}
+
+impl Default for GalleryQuery {
+    fn default() -> Self {
+        GalleryQuery {
+            struct_size: mem::size_of::<GalleryQuery>(),
+            since_ms: 0,
+            until_ms: 0,
+            match_target: 0,
+            target: CaptureTarget { kind: 0, id: 0 },
+        }
+    }
+}
+
+impl GalleryQuery {
+    fn matches(&self, entry: &GalleryEntry) -> bool {
+        entry.timestamp_ms >= self.since_ms
+            && (self.until_ms == 0 || entry.timestamp_ms <= self.until_ms)
+            && (self.match_target == 0 || entry.target == self.target)
+    }
+}
+
+/// The gallery's reference on an image buffer.
+struct Kept {
+    image: CapturedImage,
+}
+
+// SAFETY: the buffer is owned by the buffer registry and only read through this pointer.
+unsafe impl Send for Kept {}
+unsafe impl Sync for Kept {}
+
+impl Drop for Kept {
+    fn drop(&mut self) {
+        let _ = buffer::release(self.image.data);
+    }
+}
+
+static IMAGES: Registry<Kept> = Registry::new(HandleType::GalleryEntry);
+
+struct Gallery {
+    /// 0 while the gallery is disabled.
+    capacity: usize,
+    /// Oldest first.
+    entries: VecDeque<GalleryEntry>,
+}
+
+static GALLERY: Mutex<Gallery> = Mutex::new(Gallery {
+    capacity: 0,
+    entries: VecDeque::new(),
+});
+
+fn lock() -> std::sync::MutexGuard<'static, Gallery> {
+    GALLERY.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Drops the oldest entries until at most `capacity` are left.
+fn evict(gallery: &mut Gallery, capacity: usize) {
+    while gallery.entries.len() > capacity {
+        if let Some(entry) = gallery.entries.pop_front() {
+            let _ = IMAGES.remove(entry.id);
+        }
+    }
+}
+
+/// Keeps a reference to a capture being handed to the host, if the gallery is enabled.
+pub(crate) fn record(image: &CapturedImage, target: CaptureTarget) {
+    let mut gallery = lock();
+    if gallery.capacity == 0 || image.data.is_null() || buffer::retain(image.data).is_err() {
+        return;
+    }
+    let id = IMAGES.insert(Kept { image: *image });
+    gallery.entries.push_back(GalleryEntry {
+        id,
+        timestamp_ms: now_ms(),
+        target,
+        width: image.width,
+        height: image.height,
+    });
+    let capacity = gallery.capacity;
+    evict(&mut gallery, capacity);
+}
+
+/// Keeps a capture that isn't handed to the host as an image (e.g. one that was only saved).
+pub(crate) fn record_frame(image: RgbaImage, target: CaptureTarget) {
+    if lock().capacity == 0 {
+        return;
+    }
+    let image = CapturedImage::from_rgba(image);
+    record(&image, target);
+    if !image.data.is_null() {
+        let _ = buffer::release(image.data);
+    }
+}
+
+/// Enables the gallery, keeping the most recent `capacity` one-shot captures (from
+/// capture_monitor_image*, capture_monitor_save* and capture_monitor_encode; stream frames are
+/// never kept). Lowering the capacity evicts the oldest entries; 0 disables the gallery and
+/// drops everything in it. The gallery is disabled by default.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_gallery_set_capacity(capacity: c_uint) {
+    let mut gallery = lock();
+    gallery.capacity = capacity as usize;
+    evict(&mut gallery, capacity as usize);
+}
+
+/// Returns a GalleryQuery that matches every entry, with `struct_size` filled in.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_gallery_query_default() -> GalleryQuery {
+    GalleryQuery::default()
+}
+
+/// Lists gallery entries matching `query` (NULL matches all), newest first, writing up to `max`
+/// of them to `out`.
+/// Returns the total number of matching entries, which may be more than `max`; call with `max`
+/// 0 to size a buffer. Returns 0 and sets the last error if `query` is malformed.
+///
+/// # Safety
+/// `query` must be NULL or point to a GalleryQuery initialized with
+/// capture_gallery_query_default(). `out` must have room for `max` entries (or be NULL if `max`
+/// is 0).
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_gallery_list(
+    query: *const GalleryQuery,
+    out: *mut GalleryEntry,
+    max: size_t,
+) -> size_t {
+    let query = if query.is_null() {
+        GalleryQuery::default()
+    } else {
+        match unsafe {
+            read_sized(
+                query,
+                GalleryQuery::default(),
+                "GalleryQuery",
+                "capture_gallery_query_default",
+            )
+        } {
+            Ok(query) => query,
+            Err(err) => {
+                set_last_error(err);
+                return 0;
+            }
+        }
+    };
+    if out.is_null() && max > 0 {
+        set_last_error(CaptureError::new(
+            ErrorCode::InvalidArgument,
+            "Output array is NULL",
+        ));
+        return 0;
+    }
+
+    let gallery = lock();
+    let mut total = 0;
+    for entry in gallery.entries.iter().rev().filter(|e| query.matches(e)) {
+        if total < max {
+            // SAFETY: the caller promised room for `max` entries.
+            unsafe { out.add(total).write(*entry) };
+        }
+        total += 1;
+    }
+    total
+}
+
+/// Gets a new reference to a gallery image, usable exactly like a fresh capture.
+/// The caller MUST call capture_free_image() on the returned struct.
+/// Returns a struct with NULL data and sets CAPTURE_ERROR_INVALID_HANDLE if `id` has been evicted
+/// or isn't a gallery entry.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_gallery_get(id: CaptureHandle) -> CapturedImage {
+    let result = IMAGES.get(id).and_then(|kept| {
+        buffer::retain(kept.image.data)?;
+        Ok(kept.image)
+    });
+    match result {
+        Ok(image) => image,
+        Err(err) => {
+            set_last_error(err);
+            CapturedImage::empty()
+        }
+    }
+}
+
+/// Removes one entry from the gallery, dropping its reference on the image.
+/// Returns CAPTURE_OK, or CAPTURE_ERROR_INVALID_HANDLE if `id` isn't a live gallery entry.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_gallery_remove(id: CaptureHandle) -> c_int {
+    let mut gallery = lock();
+    match IMAGES.remove(id) {
+        Ok(_) => {
+            gallery.entries.retain(|entry| entry.id != id);
+            ErrorCode::Ok as c_int
+        }
+        Err(err) => {
+            let code = err.code as c_int;
+            set_last_error(err);
+            code
+        }
+    }
+}
@@ -0,0 +1,161 @@
+// capture-ffi/src/handle.rs
+//! Opaque handles for objects owned by the library.
+//! A handle packs the object's type, a per-slot generation counter and a slot index:
+//!
+//! ```text
+//!  63      56 55                 32 31                     0
+//! +----------+---------------------+------------------------+
+//! |   type   |     generation      |          slot          |
+//! +----------+---------------------+------------------------+
+//! ```
+//!
+//! Every call validates all three, so a handle of the wrong type, a stale handle whose object
+//! was freed, or plain garbage fails with CAPTURE_ERROR_INVALID_HANDLE instead of crashing.
+//!
+//! Only objects the library creates and frees get handles; HandleType lists them. Monitors and
+//! windows belong to the platform and are addressed by enumeration index or platform ID instead,
+//! looked up afresh on every call, so an index out of range or an unknown ID fails with an error
+//! rather than a crash. Those numbers carry no type, though: a monitor index passed where a
+//! window index is expected refers to whichever window has that index. APIs that accept either
+//! kind take a CaptureTarget, which names the kind explicitly.
+
+use crate::{CaptureError, ErrorCode};
+use std::sync::{Arc, Mutex};
+
+/// An opaque object handle as seen over FFI. 0 is never a valid handle.
+pub type CaptureHandle = u64;
+
+ffi_enum! {
+    /// The kind of object a handle refers to, stored in its top 8 bits.
+    pub enum HandleType {
+        Stream = 1 => CAPTURE_HANDLE_STREAM,
+        ImageView = 2 => CAPTURE_HANDLE_IMAGE_VIEW,
+        GalleryEntry = 3 => CAPTURE_HANDLE_GALLERY_ENTRY,
+        CancelToken = 4 => CAPTURE_HANDLE_CANCEL_TOKEN,
+        RegionWatch = 5 => CAPTURE_HANDLE_REGION_WATCH,
+        CompositeRecording = 6 => CAPTURE_HANDLE_COMPOSITE_RECORDING,
+        Heatmap = 7 => CAPTURE_HANDLE_HEATMAP,
+        DeltaEncoder = 8 => CAPTURE_HANDLE_DELTA_ENCODER,
+        DeltaDecoder = 9 => CAPTURE_HANDLE_DELTA_DECODER,
+        Archive = 10 => CAPTURE_HANDLE_ARCHIVE,
+        ArchiveReader = 11 => CAPTURE_HANDLE_ARCHIVE_READER,
+    }
+}
+
+const GENERATION_MASK: u64 = 0xff_ffff;
+
+/// The type tag of a handle, without checking that it is live.
+pub(crate) fn handle_type(handle: CaptureHandle) -> Option<HandleType> {
+    HandleType::from_raw((handle >> 56) as libc::c_int)
+}
+
+fn type_name(raw_type: u64) -> Option<&'static str> {
+    match HandleType::from_raw(raw_type as libc::c_int)? {
+        HandleType::Stream => Some("stream"),
+        HandleType::ImageView => Some("image view"),
+        HandleType::GalleryEntry => Some("gallery entry"),
+        HandleType::CancelToken => Some("cancel token"),
+        HandleType::RegionWatch => Some("region watch"),
+        HandleType::CompositeRecording => Some("composite recording"),
+        HandleType::Heatmap => Some("heatmap"),
+        HandleType::DeltaEncoder => Some("delta encoder"),
+        HandleType::DeltaDecoder => Some("delta decoder"),
+        HandleType::Archive => Some("archive"),
+        HandleType::ArchiveReader => Some("archive reader"),
+    }
+}
+
+struct Slot<T> {
+    generation: u64,
+    value: Option<Arc<T>>,
+}
+
+/// Slots of one handle type. Freed slots are reused with a bumped generation.
+pub(crate) struct Registry<T> {
+    handle_type: HandleType,
+    slots: Mutex<Vec<Slot<T>>>,
+}
+
+impl<T> Registry<T> {
+    pub(crate) const fn new(handle_type: HandleType) -> Self {
+        Registry {
+            handle_type,
+            slots: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn encode(&self, generation: u64, index: usize) -> CaptureHandle {
+        ((self.handle_type as u64) << 56) | (generation << 32) | index as u64
+    }
+
+    /// Splits a handle into its slot index and generation after checking its type tag.
+    fn decode(&self, handle: CaptureHandle) -> Result<(usize, u64), CaptureError> {
+        let raw_type = handle >> 56;
+        let expected = type_name(self.handle_type as u64).unwrap_or("object");
+        if raw_type != self.handle_type as u64 {
+            let message = match type_name(raw_type) {
+                Some(actual) => {
+                    format!("Wrong handle type: expected {}, got {}", expected, actual)
+                }
+                None => format!("Invalid {} handle: {:#x}", expected, handle),
+            };
+            return Err(CaptureError::new(ErrorCode::InvalidHandle, message));
+        }
+        Ok((
+            (handle & 0xffff_ffff) as usize,
+            (handle >> 32) & GENERATION_MASK,
+        ))
+    }
+
+    fn stale(&self, handle: CaptureHandle) -> CaptureError {
+        CaptureError::new(
+            ErrorCode::InvalidHandle,
+            format!(
+                "{:#x} is not a live {} handle; it was freed or never issued",
+                handle,
+                type_name(self.handle_type as u64).unwrap_or("object"),
+            ),
+        )
+    }
+
+    /// Stores a value and returns a new handle for it.
+    pub(crate) fn insert(&self, value: T) -> CaptureHandle {
+        let mut slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+        let value = Some(Arc::new(value));
+        if let Some(index) = slots.iter().position(|slot| slot.value.is_none()) {
+            let slot = &mut slots[index];
+            // Generation 0 is skipped so no handle is ever 0.
+            slot.generation = (slot.generation % GENERATION_MASK) + 1;
+            slot.value = value;
+            self.encode(slot.generation, index)
+        } else {
+            slots.push(Slot {
+                generation: 1,
+                value,
+            });
+            self.encode(1, slots.len() - 1)
+        }
+    }
+
+    /// Looks up a live value by handle.
+    pub(crate) fn get(&self, handle: CaptureHandle) -> Result<Arc<T>, CaptureError> {
+        let (index, generation) = self.decode(handle)?;
+        let slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+        slots
+            .get(index)
+            .filter(|slot| slot.generation == generation)
+            .and_then(|slot| slot.value.clone())
+            .ok_or_else(|| self.stale(handle))
+    }
+
+    /// Invalidates a handle, returning its value so the caller can tear it down.
+    pub(crate) fn remove(&self, handle: CaptureHandle) -> Result<Arc<T>, CaptureError> {
+        let (index, generation) = self.decode(handle)?;
+        let mut slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+        slots
+            .get_mut(index)
+            .filter(|slot| slot.generation == generation)
+            .and_then(|slot| slot.value.take())
+            .ok_or_else(|| self.stale(handle))
+    }
+}
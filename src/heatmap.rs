@@ -0,0 +1,288 @@
+// capture-ffi/src/heatmap.rs
+//! Long-running maps of how static each part of a monitor is, for kiosk operators worried about
+//! OLED burn-in and researchers studying which parts of the screen change.
+
+use crate::{
+    CaptureError, CapturedImage, ErrorCode, backend, cancel, capture_background_frame,
+    event::{self, EventType},
+    handle::{CaptureHandle, HandleType, Registry},
+    monitor_at, priority, set_last_error,
+    worker::{Task, Worker},
+};
+use image::{Rgba, RgbaImage};
+use libc::{c_int, c_uint, size_t};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Interval used when the caller passes 0, and the shortest one supported.
+const DEFAULT_INTERVAL_MS: c_uint = 1000;
+const MIN_INTERVAL_MS: c_uint = 100;
+/// Cells along the longer side of the map when the caller passes 0, and the most allowed.
+const DEFAULT_MAX_SIDE: c_uint = 256;
+const MAX_SIDE: c_uint = 1024;
+/// Largest difference of a cell's mean channel values between samples that still counts as
+/// static, to ignore dithering and capture noise.
+const TOLERANCE: u8 = 2;
+
+/// Size and progress of a heatmap, as returned by capture_heatmap_info().
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct HeatmapInfo {
+    /// Size of the map in cells; every cell averages a block of captured pixels.
+    pub width: c_uint,
+    pub height: c_uint,
+    /// Monitor pixels each cell covers along either side, e.g. 15 on a 3840-wide monitor mapped
+    /// to 256 cells.
+    pub cell_size: f64,
+    /// Captures taken so far; staticness is measured between consecutive ones.
+    pub samples: u64,
+    /// Time between captures in milliseconds.
+    pub interval_ms: c_uint,
+}
+
+/// What a heatmap has seen so far.
+struct Accumulator {
+    width: u32,
+    height: u32,
+    samples: u64,
+    /// Mean color of every cell in the latest capture.
+    previous: Vec<[u8; 3]>,
+    /// In how many pairs of consecutive captures each cell stayed the same.
+    unchanged: Vec<u32>,
+}
+
+/// Averages a frame down to a `width` x `height` grid of cells, row by row.
+fn average(image: &RgbaImage, width: u32, height: u32) -> Vec<[u8; 3]> {
+    let (image_width, image_height) = image.dimensions();
+    let mut sums = vec![[0u64; 4]; (width * height) as usize];
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let column = (x as u64 * width as u64 / image_width as u64) as usize;
+        let row = (y as u64 * height as u64 / image_height as u64) as usize;
+        let sum = &mut sums[row * width as usize + column];
+        for channel in 0..3 {
+            sum[channel] += pixel[channel] as u64;
+        }
+        sum[3] += 1;
+    }
+    sums.iter()
+        .map(|sum| {
+            let count = sum[3].max(1);
+            [0, 1, 2].map(|channel| (sum[channel] / count) as u8)
+        })
+        .collect()
+}
+
+impl Accumulator {
+    /// Adds the cells of a capture, as averaged by average().
+    fn add(&mut self, cells: Vec<[u8; 3]>) {
+        if self.samples > 0 {
+            for ((count, old), new) in self.unchanged.iter_mut().zip(&self.previous).zip(&cells) {
+                if old
+                    .iter()
+                    .zip(new)
+                    .all(|(a, b)| a.abs_diff(*b) <= TOLERANCE)
+                {
+                    *count = count.saturating_add(1);
+                }
+            }
+        }
+        self.previous = cells;
+        self.samples += 1;
+    }
+
+    /// The map as a grayscale image: white cells never changed, black ones changed every time.
+    fn render(&self) -> RgbaImage {
+        let comparisons = self.samples.saturating_sub(1).max(1) as f64;
+        RgbaImage::from_fn(self.width, self.height, |x, y| {
+            let unchanged = self.unchanged[(y * self.width + x) as usize] as f64;
+            let value = (unchanged / comparisons * 255.0).round() as u8;
+            Rgba([value, value, value, 255])
+        })
+    }
+}
+
+/// A running heatmap, created by capture_heatmap_start().
+struct Heatmap {
+    accumulator: Arc<Mutex<Accumulator>>,
+    cell_size: f64,
+    interval_ms: c_uint,
+    worker: Worker,
+}
+
+impl Task for Heatmap {
+    fn worker(&self) -> &Worker {
+        &self.worker
+    }
+}
+
+static HEATMAPS: Registry<Heatmap> = Registry::new(HandleType::Heatmap);
+
+fn start(
+    index: usize,
+    interval_ms: c_uint,
+    max_side: c_uint,
+    cancel: CaptureHandle,
+) -> Result<CaptureHandle, CaptureError> {
+    backend::require_threads()?;
+    let cancel = cancel::resolve(cancel)?;
+    if max_side > MAX_SIDE {
+        return Err(CaptureError::new(
+            ErrorCode::InvalidArgument,
+            format!(
+                "Heatmaps can be at most {} cells per side, got {}",
+                MAX_SIDE, max_side
+            ),
+        ));
+    }
+    let max_side = if max_side == 0 {
+        DEFAULT_MAX_SIDE
+    } else {
+        max_side
+    };
+    let interval_ms = match interval_ms {
+        0 => DEFAULT_INTERVAL_MS,
+        interval_ms => interval_ms.max(MIN_INTERVAL_MS),
+    };
+    let monitor = monitor_at(index)?;
+    let first = capture_background_frame(&monitor)
+        .map_err(|e| e.context(format!("Error capturing image for monitor {}", index)))?;
+
+    // The map keeps the frame's aspect ratio, and never has more cells than pixels.
+    let (width, height) = first.dimensions();
+    let scale = (max_side as f64 / width.max(height) as f64).min(1.0);
+    let cells = |side: u32| ((side as f64 * scale).round() as u32).max(1);
+    let mut accumulator = Accumulator {
+        width: cells(width),
+        height: cells(height),
+        samples: 0,
+        previous: Vec::new(),
+        unchanged: vec![0; (cells(width) * cells(height)) as usize],
+    };
+    accumulator.add(average(&first, accumulator.width, accumulator.height));
+    let (grid_width, grid_height) = (accumulator.width, accumulator.height);
+    let cell_size = monitor.width() as f64 / accumulator.width as f64;
+    let accumulator = Arc::new(Mutex::new(accumulator));
+
+    let heatmap = Heatmap {
+        accumulator: accumulator.clone(),
+        cell_size,
+        interval_ms,
+        worker: Worker::new(cancel.as_deref()),
+    };
+    let interval = Duration::from_millis(interval_ms as u64);
+    Ok(HEATMAPS.start(heatmap, move |handle, stop| {
+        while !stop.sleep(interval) {
+            priority::yield_to_interactive(interval);
+            match capture_background_frame(&monitor) {
+                Ok(image) => {
+                    // Average outside the lock, so reading the map never waits for it.
+                    let cells = average(&image, grid_width, grid_height);
+                    accumulator
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .add(cells);
+                }
+                Err(e) => {
+                    let err_msg = format!("Error capturing heatmap sample: {}", e);
+                    event::emit(EventType::WatchError, handle, Some(&err_msg));
+                }
+            }
+        }
+    }))
+}
+
+/// Starts mapping how static each part of the monitor at the specified index stays, on a
+/// background thread meant to run for hours: every `interval_ms` milliseconds (0 means 1000, at
+/// least 100) the monitor is captured, averaged down to a grid of at most `max_side` cells along
+/// its longer side (0 means 256, at most 1024), and every cell whose color didn't change since
+/// the previous capture has its count raised. Memory stays bounded by the grid size however long
+/// it runs. Capture failures are reported as CAPTURE_EVENT_WATCH_ERROR events and skipped.
+/// `cancel` is a token from capture_cancel_token_new() that also stops sampling, or 0; the map
+/// stays readable until capture_heatmap_stop().
+/// Returns a heatmap handle, or 0 if the index is invalid or the first capture fails.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_heatmap_start(
+    index: size_t,
+    interval_ms: c_uint,
+    max_side: c_uint,
+    cancel: CaptureHandle,
+) -> CaptureHandle {
+    match start(index, interval_ms, max_side, cancel) {
+        Ok(handle) => handle,
+        Err(err) => {
+            let err = err.context("Failed to start heatmap");
+            eprintln!("{}", err);
+            set_last_error(err);
+            0
+        }
+    }
+}
+
+/// Renders a heatmap so far as an image with one pixel per cell, in grayscale from black (the
+/// cell changed between every pair of captures) to white (it never changed). Scale it up to the
+/// monitor's size to overlay it on a screenshot.
+/// The caller MUST call capture_free_image() on the returned struct to free the data buffer.
+/// Returns a struct with NULL data pointer and sets CAPTURE_ERROR_INVALID_HANDLE if `heatmap`
+/// isn't a live heatmap.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_heatmap_get(heatmap: CaptureHandle) -> CapturedImage {
+    match HEATMAPS.get(heatmap) {
+        Ok(heatmap) => {
+            let image = heatmap
+                .accumulator
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .render();
+            CapturedImage::from_rgba(image)
+        }
+        Err(err) => {
+            set_last_error(err);
+            CapturedImage::empty()
+        }
+    }
+}
+
+/// Gets the size of a heatmap and how many captures it has taken.
+/// Returns a zeroed struct and sets CAPTURE_ERROR_INVALID_HANDLE if `heatmap` isn't a live
+/// heatmap.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_heatmap_info(heatmap: CaptureHandle) -> HeatmapInfo {
+    match HEATMAPS.get(heatmap) {
+        Ok(heatmap) => {
+            let accumulator = heatmap
+                .accumulator
+                .lock()
+                .unwrap_or_else(|e| e.into_inner());
+            HeatmapInfo {
+                width: accumulator.width,
+                height: accumulator.height,
+                cell_size: heatmap.cell_size,
+                samples: accumulator.samples,
+                interval_ms: heatmap.interval_ms,
+            }
+        }
+        Err(err) => {
+            set_last_error(err);
+            HeatmapInfo::default()
+        }
+    }
+}
+
+/// Stops a heatmap, waits for its thread to finish and invalidates the handle, discarding the
+/// map; get it with capture_heatmap_get() first to keep it.
+/// Returns CAPTURE_OK, CAPTURE_ERROR_INVALID_HANDLE if `heatmap` isn't a live heatmap, or
+/// CAPTURE_ERROR_INVALID_ARGUMENT, leaving it running, if called from an event callback on the
+/// heatmap's own thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_heatmap_stop(heatmap: CaptureHandle) -> c_int {
+    match HEATMAPS.stop(heatmap) {
+        Ok(()) => ErrorCode::Ok as c_int,
+        Err(err) => {
+            let code = err.code as c_int;
+            set_last_error(err);
+            code
+        }
+    }
+}
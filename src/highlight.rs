@@ -0,0 +1,93 @@
+// capture-ffi/src/highlight.rs
+//! A temporary border around a capture target, for picker UIs.
+
+use crate::{
+    CaptureError, ErrorCode,
+    cancel::CancelToken,
+    overlay::{Overlay, Rgb},
+    set_last_error,
+    target::CaptureTarget,
+};
+use libc::{c_int, c_uint};
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+/// Thickness of the highlight border in pixels.
+const BORDER_WIDTH: u32 = 4;
+
+/// Hides the highlight currently shown, if any.
+static CURRENT: Mutex<Option<Arc<CancelToken>>> = Mutex::new(None);
+
+/// Shows four overlay strips along the inside edges of the target.
+fn border(target: &CaptureTarget, color: Rgb) -> Result<Vec<Overlay>, CaptureError> {
+    let rect = target.bounds()?;
+    if rect.width == 0 || rect.height == 0 {
+        return Err(CaptureError::new(
+            ErrorCode::InvalidArgument,
+            "Target has an empty area",
+        ));
+    }
+    let (x, y, width, height) = (rect.x, rect.y, rect.width, rect.height);
+    let side = BORDER_WIDTH.min(width / 2).min(height / 2).max(1);
+    let inner_height = height.saturating_sub(2 * side).max(1);
+    Ok(vec![
+        Overlay::new(x, y, width, side, color)?,
+        Overlay::new(x, y + (height - side) as i32, width, side, color)?,
+        Overlay::new(x, y + side as i32, side, inner_height, color)?,
+        Overlay::new(
+            x + (width - side) as i32,
+            y + side as i32,
+            side,
+            inner_height,
+            color,
+        )?,
+    ])
+}
+
+fn highlight(target: CaptureTarget, color: u32, duration_ms: c_uint) -> Result<(), CaptureError> {
+    let mut current = CURRENT.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(previous) = current.take() {
+        previous.cancel();
+    }
+    if duration_ms == 0 {
+        return Ok(());
+    }
+
+    // Overlays are always opaque, so the alpha byte is dropped.
+    let overlays = border(&target, color >> 8)?;
+    let hide = Arc::new(CancelToken::default());
+    *current = Some(hide.clone());
+    thread::spawn(move || {
+        hide.sleep(Duration::from_millis(duration_ms as u64));
+        drop(overlays);
+    });
+    Ok(())
+}
+
+/// Draws a border of color `color` (0xRRGGBBAA, with the alpha ignored) along the inside edges of
+/// a monitor or window for `duration_ms` milliseconds, e.g. to confirm what a picker is about to
+/// capture. Returns as soon as the border is on screen; it disappears on its own. Showing a new
+/// highlight replaces the previous one, and a `duration_ms` of 0 just hides the current one.
+/// Targets with an empty area fail with CAPTURE_ERROR_INVALID_ARGUMENT.
+/// The border is drawn with native overlays; see CAPTURE_FEATURE_OVERLAY.
+/// Returns CAPTURE_OK or an error code; see capture_last_error_message() for details.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_highlight_target(
+    target: CaptureTarget,
+    color: u32,
+    duration_ms: c_uint,
+) -> c_int {
+    match highlight(target, color, duration_ms) {
+        Ok(()) => ErrorCode::Ok as c_int,
+        Err(err) => {
+            let err = err.context("Failed to highlight target");
+            eprintln!("{}", err);
+            let code = err.code as c_int;
+            set_last_error(err);
+            code
+        }
+    }
+}
@@ -0,0 +1,313 @@
+// capture-ffi/src/host.rs
+//! The `wasm` backend: monitors the host describes and frames it supplies on request, e.g. from
+//! `getDisplayMedia()` in a browser. Mirrors the parts of xcap's Monitor and Window API the rest
+//! of the library uses, so streams, diffing, composing and encoding work unchanged on top of it.
+
+use crate::{CaptureError, ErrorCode, UserData, set_last_error};
+use image::RgbaImage;
+use libc::{c_char, c_int, c_uint, c_void, size_t};
+use std::{convert::Infallible, ffi::CStr, fmt, ptr, sync::Mutex};
+
+/// A monitor as described by the host through capture_host_set_monitors().
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct HostMonitor {
+    /// Any ID the host likes, unique among its monitors; passed back to the frame source.
+    pub id: c_uint,
+    /// NUL-terminated UTF-8 name, or NULL for an empty one. Copied by the library.
+    pub name: *const c_char,
+    pub x: c_int,
+    pub y: c_int,
+    pub width: c_uint,
+    pub height: c_uint,
+    /// Device pixels per logical pixel, e.g. `window.devicePixelRatio`. 0 means 1. Not used
+    /// for sizing: CaptureOptions.output_scale scales `width` and `height`, which are logical.
+    pub scale_factor: f32,
+    /// Refresh rate in Hz, or 0 if unknown.
+    pub frequency: f32,
+}
+
+/// A frame filled in by the host's frame source: RGBA8 pixels owned by the host.
+#[repr(C)]
+pub struct HostFrame {
+    pub data: *const u8,
+    pub width: c_uint,
+    pub height: c_uint,
+    /// Number of bytes from the start of one row to the start of the next; at least width * 4.
+    pub stride: size_t,
+}
+
+/// Fills `frame` with the current contents of the monitor with the given ID and returns 0, or
+/// returns anything else if no frame is available.
+pub type HostFrameSource = Option<
+    unsafe extern "C" fn(
+        monitor_id: c_uint,
+        frame: *mut HostFrame,
+        user_data: *mut c_void,
+    ) -> c_int,
+>;
+
+struct FrameSource {
+    fetch: unsafe extern "C" fn(c_uint, *mut HostFrame, *mut c_void) -> c_int,
+    user_data: UserData,
+}
+
+static MONITORS: Mutex<Vec<Monitor>> = Mutex::new(Vec::new());
+/// Held for the whole of every fetch, so calls into the host never overlap and replacing the
+/// source waits for the call in flight.
+static SOURCE: Mutex<Option<FrameSource>> = Mutex::new(None);
+
+/// An error from the host backend, converted to CAPTURE_ERROR_BACKEND like xcap's errors are.
+#[derive(Debug)]
+pub(crate) struct HostError(String);
+
+impl fmt::Display for HostError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<HostError> for CaptureError {
+    fn from(err: HostError) -> Self {
+        CaptureError::new(ErrorCode::Backend, err.0)
+    }
+}
+
+fn backend_error(message: impl Into<String>) -> HostError {
+    HostError(message.into())
+}
+
+/// A monitor provided by the host.
+#[derive(Clone, Debug)]
+pub(crate) struct Monitor {
+    id: u32,
+    name: String,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    frequency: f32,
+}
+
+impl Monitor {
+    pub(crate) fn all() -> Result<Vec<Monitor>, HostError> {
+        Ok(MONITORS.lock().unwrap_or_else(|e| e.into_inner()).clone())
+    }
+
+    pub(crate) fn from_point(x: i32, y: i32) -> Result<Monitor, HostError> {
+        Monitor::all()?
+            .into_iter()
+            .find(|m| {
+                (m.x..m.x.saturating_add(m.width as i32)).contains(&x)
+                    && (m.y..m.y.saturating_add(m.height as i32)).contains(&y)
+            })
+            .ok_or_else(|| backend_error(format!("No host monitor contains ({}, {})", x, y)))
+    }
+
+    pub(crate) fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn x(&self) -> i32 {
+        self.x
+    }
+
+    pub(crate) fn y(&self) -> i32 {
+        self.y
+    }
+
+    pub(crate) fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub(crate) fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub(crate) fn frequency(&self) -> f32 {
+        self.frequency
+    }
+
+    /// Asks the host's frame source for the monitor's current frame and copies it.
+    pub(crate) fn capture_image(&self) -> Result<RgbaImage, HostError> {
+        let source = SOURCE.lock().unwrap_or_else(|e| e.into_inner());
+        let source = source
+            .as_ref()
+            .ok_or_else(|| backend_error("No host frame source set"))?;
+        let mut frame = HostFrame {
+            data: ptr::null(),
+            width: 0,
+            height: 0,
+            stride: 0,
+        };
+        // SAFETY: the caller promised the source stays valid until it is replaced.
+        let status = unsafe { (source.fetch)(self.id, &mut frame, source.user_data.0) };
+        if status != 0 {
+            return Err(backend_error(format!(
+                "Host frame source failed for monitor {} with status {}",
+                self.id, status
+            )));
+        }
+        let row = frame.width as usize * 4;
+        if frame.data.is_null() || frame.width == 0 || frame.height == 0 || frame.stride < row {
+            return Err(backend_error(format!(
+                "Host frame source returned an invalid {}x{} frame with stride {}",
+                frame.width, frame.height, frame.stride
+            )));
+        }
+        let mut pixels = Vec::with_capacity(row * frame.height as usize);
+        for y in 0..frame.height as usize {
+            // SAFETY: the host promised `stride * height` readable bytes at `data`.
+            let start = unsafe { frame.data.add(y * frame.stride) };
+            pixels.extend_from_slice(unsafe { std::slice::from_raw_parts(start, row) });
+        }
+        RgbaImage::from_raw(frame.width, frame.height, pixels)
+            .ok_or_else(|| backend_error("Host frame has an invalid size"))
+    }
+}
+
+/// Windows can't be enumerated in the host backend, so there never are any.
+#[derive(Clone, Debug)]
+pub(crate) struct Window {
+    never: Infallible,
+}
+
+impl Window {
+    pub(crate) fn all() -> Result<Vec<Window>, HostError> {
+        Ok(Vec::new())
+    }
+
+    pub(crate) fn id(&self) -> u32 {
+        match self.never {}
+    }
+
+    pub(crate) fn title(&self) -> &str {
+        match self.never {}
+    }
+
+    pub(crate) fn x(&self) -> i32 {
+        match self.never {}
+    }
+
+    pub(crate) fn y(&self) -> i32 {
+        match self.never {}
+    }
+
+    pub(crate) fn width(&self) -> u32 {
+        match self.never {}
+    }
+
+    pub(crate) fn height(&self) -> u32 {
+        match self.never {}
+    }
+
+    pub(crate) fn current_monitor(&self) -> Monitor {
+        match self.never {}
+    }
+
+    pub(crate) fn capture_image(&self) -> Result<RgbaImage, HostError> {
+        match self.never {}
+    }
+}
+
+fn set_monitors(monitors: *const HostMonitor, count: size_t) -> Result<(), CaptureError> {
+    if monitors.is_null() && count > 0 {
+        return Err(CaptureError::new(
+            ErrorCode::InvalidArgument,
+            "Monitor list is NULL",
+        ));
+    }
+    let described = if count == 0 {
+        &[][..]
+    } else {
+        // SAFETY: the caller promised `count` readable monitors.
+        unsafe { std::slice::from_raw_parts(monitors, count) }
+    };
+    let mut resolved = Vec::with_capacity(count);
+    for (i, monitor) in described.iter().enumerate() {
+        if monitor.width == 0 || monitor.height == 0 {
+            return Err(CaptureError::new(
+                ErrorCode::InvalidArgument,
+                format!("Host monitor {} has a zero size", i),
+            ));
+        }
+        if resolved.iter().any(|m: &Monitor| m.id == monitor.id) {
+            return Err(CaptureError::new(
+                ErrorCode::InvalidArgument,
+                format!("Host monitor ID {} is used twice", monitor.id),
+            ));
+        }
+        let name = if monitor.name.is_null() {
+            String::new()
+        } else {
+            // SAFETY: the caller promised a NUL-terminated string.
+            unsafe { CStr::from_ptr(monitor.name) }
+                .to_str()
+                .map_err(|_| {
+                    CaptureError::new(
+                        ErrorCode::InvalidArgument,
+                        format!("Name of host monitor {} is not valid UTF-8", i),
+                    )
+                })?
+                .to_string()
+        };
+        resolved.push(Monitor {
+            id: monitor.id,
+            name,
+            x: monitor.x,
+            y: monitor.y,
+            width: monitor.width,
+            height: monitor.height,
+            frequency: monitor.frequency.max(0.0),
+        });
+    }
+    *MONITORS.lock().unwrap_or_else(|e| e.into_inner()) = resolved;
+    Ok(())
+}
+
+/// Replaces the monitors the library reports with `count` host-described ones, in enumeration
+/// order. Call it again whenever the host's displays change; 0 monitors is allowed.
+/// Only available in builds with the `wasm` feature (see CAPTURE_FEATURE_HOST_FRAMES).
+/// Returns CAPTURE_OK or an error code; see capture_last_error_message() for details.
+///
+/// # Safety
+/// `monitors` must point to `count` valid HostMonitor structs, or may be NULL if `count` is 0.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_host_set_monitors(
+    monitors: *const HostMonitor,
+    count: size_t,
+) -> c_int {
+    match set_monitors(monitors, count) {
+        Ok(()) => ErrorCode::Ok as c_int,
+        Err(err) => {
+            let code = err.code;
+            set_last_error(err);
+            code as c_int
+        }
+    }
+}
+
+/// Sets the function every capture of a host monitor gets its pixels from, replacing any previous
+/// one; NULL removes it, after which captures fail with CAPTURE_ERROR_BACKEND.
+/// The source is called on whichever thread captures, but never by two threads at once. The
+/// frame it fills in only needs to stay valid until it is called again or replaced; the library
+/// copies the pixels as soon as it returns, and runs frame processors on the copy.
+/// Only available in builds with the `wasm` feature (see CAPTURE_FEATURE_HOST_FRAMES).
+///
+/// # Safety
+/// `fetch` must stay valid, and safe to call from any thread, until it is replaced. It must not
+/// call capture_host_set_frame_source() or capture anything itself.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_host_set_frame_source(
+    fetch: HostFrameSource,
+    user_data: *mut c_void,
+) {
+    *SOURCE.lock().unwrap_or_else(|e| e.into_inner()) = fetch.map(|fetch| FrameSource {
+        fetch,
+        user_data: UserData(user_data),
+    });
+}
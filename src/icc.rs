@@ -0,0 +1,177 @@
+// capture-ffi/src/icc.rs
+//! Just enough of ICC profiles to turn a display's pixel values into linear light: the
+//! matrix/TRC RGB profiles display calibration produces, in versions 2 and 4.
+
+/// CIE XYZ relative to D50, the profile connection space, to linear sRGB with D65 white
+/// (Bradford-adapted).
+const XYZ_D50_TO_SRGB: [[f64; 3]; 3] = [
+    [3.133_856_1, -1.616_866_7, -0.490_614_6],
+    [-0.978_768_4, 1.916_141_5, 0.033_454_0],
+    [0.071_945_3, -0.228_991_4, 1.405_242_7],
+];
+
+/// A tone reproduction curve, from a channel's encoded value to its linear light, both 0 to 1.
+enum Curve {
+    Gamma(f64),
+    Table(Vec<f64>),
+    /// ICC parametric curve of the given function type, with parameters g, a, b, c, d, e, f.
+    Parametric(u16, [f64; 7]),
+}
+
+impl Curve {
+    fn eval(&self, x: f64) -> f64 {
+        match self {
+            Curve::Gamma(gamma) => x.powf(*gamma),
+            Curve::Table(table) => {
+                let position = x.clamp(0.0, 1.0) * (table.len() - 1) as f64;
+                let i = (position as usize).min(table.len() - 2);
+                let t = position - i as f64;
+                table[i] * (1.0 - t) + table[i + 1] * t
+            }
+            Curve::Parametric(function, [g, a, b, c, d, e, f]) => {
+                let power = |x: f64| (a * x + b).max(0.0).powf(*g);
+                match function {
+                    0 => x.powf(*g),
+                    1 if x >= -b / a => power(x),
+                    1 => 0.0,
+                    2 if x >= -b / a => power(x) + c,
+                    2 => *c,
+                    3 if x >= *d => power(x),
+                    3 => c * x,
+                    _ if x >= *d => power(x) + e,
+                    _ => c * x + f,
+                }
+            }
+        }
+    }
+}
+
+/// How a display encodes color: per-channel curves to linear light, then a matrix to linear
+/// sRGB.
+pub(crate) struct Profile {
+    curves: [Curve; 3],
+    to_srgb: [[f64; 3]; 3],
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+/// An s15Fixed16Number.
+fn fixed_at(data: &[u8], offset: usize) -> Option<f64> {
+    Some(u32_at(data, offset)? as i32 as f64 / 65536.0)
+}
+
+impl Profile {
+    /// The sRGB encoding, for displays without a profile.
+    pub(crate) fn srgb() -> Self {
+        let curve = || {
+            Curve::Parametric(
+                3,
+                [
+                    2.4,
+                    1.0 / 1.055,
+                    0.055 / 1.055,
+                    1.0 / 12.92,
+                    0.04045,
+                    0.0,
+                    0.0,
+                ],
+            )
+        };
+        Profile {
+            curves: [curve(), curve(), curve()],
+            to_srgb: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+        }
+    }
+
+    /// Reads an RGB matrix/TRC profile; None for anything else, including profiles that only
+    /// describe the display with lookup tables.
+    pub(crate) fn parse(data: &[u8]) -> Option<Self> {
+        if data.get(36..40)? != b"acsp" || data.get(16..20)? != b"RGB " {
+            return None;
+        }
+        let tag_count = u32_at(data, 128)? as usize;
+        let tag = |signature: &[u8; 4]| -> Option<&[u8]> {
+            (0..tag_count.min(1024)).find_map(|i| {
+                let entry = 132 + i * 12;
+                if data.get(entry..entry + 4)? != signature {
+                    return None;
+                }
+                let offset = u32_at(data, entry + 4)? as usize;
+                let size = u32_at(data, entry + 8)? as usize;
+                data.get(offset..offset.checked_add(size)?)
+            })
+        };
+        let xyz = |signature| -> Option<[f64; 3]> {
+            let tag = tag(signature)?;
+            if tag.get(0..4)? != b"XYZ " {
+                return None;
+            }
+            Some([fixed_at(tag, 8)?, fixed_at(tag, 12)?, fixed_at(tag, 16)?])
+        };
+        let curve = |signature| -> Option<Curve> {
+            let tag = tag(signature)?;
+            match tag.get(0..4)? {
+                b"curv" => match u32_at(tag, 8)? as usize {
+                    0 => Some(Curve::Gamma(1.0)),
+                    1 => Some(Curve::Gamma(u16_at(tag, 12)? as f64 / 256.0)),
+                    count => (0..count)
+                        .map(|i| Some(u16_at(tag, 12 + i * 2)? as f64 / 65535.0))
+                        .collect::<Option<Vec<_>>>()
+                        .map(Curve::Table),
+                },
+                b"para" => {
+                    let function = u16_at(tag, 8)?;
+                    let count = [1, 3, 4, 5, 7].get(function as usize)?;
+                    let mut params = [1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+                    for (i, param) in params.iter_mut().take(*count).enumerate() {
+                        *param = fixed_at(tag, 12 + i * 4)?;
+                    }
+                    Some(Curve::Parametric(function, params))
+                }
+                _ => None,
+            }
+        };
+
+        // The primaries' XYZ are the columns of the profile's RGB to XYZ matrix.
+        let primaries = [xyz(b"rXYZ")?, xyz(b"gXYZ")?, xyz(b"bXYZ")?];
+        let mut to_srgb = [[0.0; 3]; 3];
+        for (row, out) in to_srgb.iter_mut().enumerate() {
+            for (column, value) in out.iter_mut().enumerate() {
+                *value = (0..3)
+                    .map(|k| XYZ_D50_TO_SRGB[row][k] * primaries[column][k])
+                    .sum();
+            }
+        }
+        Some(Profile {
+            curves: [curve(b"rTRC")?, curve(b"gTRC")?, curve(b"bTRC")?],
+            to_srgb,
+        })
+    }
+
+    /// Per channel, the linear light of every 8-bit value, in the display's own primaries.
+    pub(crate) fn linear_tables(&self) -> [[f64; 256]; 3] {
+        let mut tables = [[0.0; 256]; 3];
+        for (table, curve) in tables.iter_mut().zip(&self.curves) {
+            for (value, linear) in table.iter_mut().enumerate() {
+                *linear = curve.eval(value as f64 / 255.0);
+            }
+        }
+        tables
+    }
+
+    /// Converts linear light in the display's primaries to linear sRGB.
+    pub(crate) fn to_srgb(&self, rgb: [f64; 3]) -> [f64; 3] {
+        self.to_srgb
+            .map(|row| row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2])
+    }
+}
@@ -0,0 +1,186 @@
+// capture-ffi/src/latency.rs
+use crate::{
+    CaptureError,
+    cancel::{self, CancelToken},
+    handle::CaptureHandle,
+    monitor_at,
+    overlay::Overlay,
+    set_last_error,
+};
+use libc::{c_uint, size_t};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Side length of the square test patch shown in the monitor's top-left corner.
+const PATCH_SIZE: u32 = 32;
+/// How long to wait for a single pattern change to show up in a capture.
+const SAMPLE_TIMEOUT: Duration = Duration::from_secs(2);
+
+const PATCH_DARK: u32 = 0x000000;
+const PATCH_LIGHT: u32 = 0xffffff;
+
+/// Statistics of a photon-to-buffer latency measurement, in milliseconds.
+/// All timings are zero if no sample succeeded.
+#[repr(C)]
+#[derive(Default)]
+pub struct LatencyStats {
+    /// Number of samples whose pattern change was observed in a capture.
+    pub samples: c_uint,
+    /// Number of samples that gave up waiting for the change to show up.
+    pub timeouts: c_uint,
+    pub min_ms: f64,
+    pub max_ms: f64,
+    pub mean_ms: f64,
+    pub median_ms: f64,
+    pub p95_ms: f64,
+    pub stddev_ms: f64,
+}
+
+impl LatencyStats {
+    fn from_samples(mut samples: Vec<f64>, timeouts: c_uint) -> Self {
+        if samples.is_empty() {
+            return LatencyStats {
+                timeouts,
+                ..Default::default()
+            };
+        }
+        samples.sort_by(f64::total_cmp);
+        let count = samples.len() as f64;
+        let mean = samples.iter().sum::<f64>() / count;
+        let variance = samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / count;
+        // Nearest-rank percentile
+        let percentile =
+            |p: f64| samples[((p * count).ceil() as usize).clamp(1, samples.len()) - 1];
+
+        LatencyStats {
+            samples: samples.len() as c_uint,
+            timeouts,
+            min_ms: samples[0],
+            max_ms: samples[samples.len() - 1],
+            mean_ms: mean,
+            median_ms: percentile(0.5),
+            p95_ms: percentile(0.95),
+            stddev_ms: variance.sqrt(),
+        }
+    }
+}
+
+/// Checks whether the test patch in a captured RGBA frame shows the expected color.
+fn patch_matches(image: &image::RgbaImage, color: u32) -> bool {
+    let center = PATCH_SIZE / 2;
+    if image.width() <= center || image.height() <= center {
+        return false;
+    }
+    let [r, g, b, _] = image.get_pixel(center, center).0;
+    let luma = (r as u32 + g as u32 + b as u32) / 3;
+    // Generous thresholds so color management or dithering don't hide the change.
+    if color == PATCH_LIGHT {
+        luma > 200
+    } else {
+        luma < 55
+    }
+}
+
+fn measure(
+    index: usize,
+    samples: c_uint,
+    cancel: &CancelToken,
+) -> Result<LatencyStats, CaptureError> {
+    let monitor = monitor_at(index)?;
+    let mut overlay = Overlay::new(monitor.x(), monitor.y(), PATCH_SIZE, PATCH_SIZE, PATCH_DARK)?;
+
+    // Make sure the initial color is on screen before the first sample starts.
+    let settle = Instant::now();
+    while !patch_matches(&monitor.capture_image()?, PATCH_DARK) {
+        cancel.check()?;
+        if settle.elapsed() > SAMPLE_TIMEOUT {
+            return Err(
+                "Test pattern never became visible in captures; is the overlay covered?"
+                    .to_string()
+                    .into(),
+            );
+        }
+    }
+
+    // Cheap xorshift jitter so samples don't lock onto the display's refresh phase.
+    let mut seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0x9e37_79b9, |d| d.subsec_nanos())
+        | 1;
+    let mut color = PATCH_DARK;
+    let mut latencies = Vec::with_capacity(samples as usize);
+    let mut timeouts = 0;
+
+    for _ in 0..samples {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        if cancel.sleep(Duration::from_millis((seed % 17) as u64)) {
+            break;
+        }
+
+        color = if color == PATCH_DARK {
+            PATCH_LIGHT
+        } else {
+            PATCH_DARK
+        };
+        overlay.set_color(color)?;
+        let start = Instant::now();
+
+        loop {
+            let image = monitor.capture_image()?;
+            let elapsed = start.elapsed();
+            if patch_matches(&image, color) {
+                latencies.push(elapsed.as_secs_f64() * 1000.0);
+                break;
+            }
+            if elapsed > SAMPLE_TIMEOUT {
+                timeouts += 1;
+                break;
+            }
+            if cancel.is_cancelled() {
+                break;
+            }
+        }
+    }
+
+    Ok(LatencyStats::from_samples(latencies, timeouts))
+}
+
+/// Measures photon-to-buffer latency of the monitor at the specified index.
+/// A small patch in the monitor's top-left corner is toggled between black and white
+/// `samples` times; each sample is the time from the display server accepting the change
+/// to a capture containing it being available.
+/// Blocks for at least a few frames per sample.
+/// Returns a struct with `samples == 0` and sets the last error if the measurement could not run.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_measure_latency(index: size_t, samples: c_uint) -> LatencyStats {
+    capture_measure_latency_ex(index, samples, 0)
+}
+
+/// Same as capture_measure_latency(), but stops early when `cancel` (a token from
+/// capture_cancel_token_new(), or 0 for none) is cancelled. A cancelled measurement returns the
+/// statistics of the samples completed so far and sets CAPTURE_ERROR_CANCELLED.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_measure_latency_ex(
+    index: size_t,
+    samples: c_uint,
+    cancel: CaptureHandle,
+) -> LatencyStats {
+    let result = cancel::resolve(cancel).and_then(|parent| {
+        let cancel = CancelToken::child_of(parent.as_deref());
+        let stats = measure(index, samples, &cancel)?;
+        if let Err(err) = cancel.check() {
+            set_last_error(err);
+        }
+        Ok(stats)
+    });
+    match result {
+        Ok(stats) => stats,
+        Err(err) => {
+            let err = err.context(format!("Latency measurement failed for monitor {}", index));
+            eprintln!("{}", err);
+            set_last_error(err);
+            LatencyStats::default()
+        }
+    }
+}
@@ -1,46 +1,319 @@
 // capture-ffi/src/lib.rs
-use libc::{c_char, c_uint, size_t};
-use std::{cell::RefCell, ffi::CString, ptr, slice};
-use xcap::{Monitor, image::EncodableLayout}; // Added image::Image
+use backend::{Monitor, Window};
+use image::RgbaImage;
+use libc::{c_char, c_int, c_uint, c_void, size_t};
+use std::{
+    cell::{Cell, RefCell},
+    ffi::CString,
+    fmt, ptr,
+    sync::Arc,
+};
+use usage::Usage;
+
+#[macro_use]
+mod enums;
+mod archive;
+mod backend;
+mod buffer;
+mod callback;
+mod cancel;
+mod compose;
+mod composite;
+mod config;
+mod countdown;
+mod degrade;
+mod delta;
+mod dpi;
+mod encode;
+mod event;
+mod gallery;
+mod handle;
+mod heatmap;
+mod highlight;
+#[cfg(feature = "wasm")]
+mod host;
+mod icc;
+mod latency;
+mod limits;
+mod options;
+mod out;
+mod output;
+mod overlay;
+mod placement;
+mod poll;
+mod priority;
+mod processor;
+mod sample;
+mod snap;
+mod stream;
+mod target;
+mod usage;
+mod view;
+mod watch;
+mod worker;
 
 // --- Data Structures for FFI ---
 
 /// Represents image data returned via FFI.
 /// The caller is responsible for calling capture_free_image to release the data buffer.
 #[repr(C)]
+#[derive(Clone, Copy)]
 pub struct CapturedImage {
     /// Pointer to the raw RGBA pixel data.
     pub data: *mut u8,
-    /// Length of the data buffer (width * height * 4).
+    /// Length of the data buffer (stride * height).
     pub len: size_t,
     /// Width of the image in pixels.
     pub width: c_uint,
     /// Height of the image in pixels.
     pub height: c_uint,
+    /// Number of bytes from the start of one row to the start of the next: width * 4 unless
+    /// a row alignment was requested through CaptureOptions.
+    pub stride: size_t,
+}
+
+impl CapturedImage {
+    /// The struct returned in place of an image when something went wrong.
+    pub(crate) fn empty() -> Self {
+        CapturedImage {
+            data: ptr::null_mut(),
+            len: 0,
+            width: 0,
+            height: 0,
+            stride: 0,
+        }
+    }
+
+    /// Hands the pixel buffer of a captured frame over to the caller without copying it.
+    pub(crate) fn from_rgba(image: RgbaImage) -> Self {
+        Self::from_rgba_aligned(image, 0)
+    }
+
+    /// Hands a captured frame over to the caller with rows aligned to `row_alignment` bytes,
+    /// copying only if the frame's own buffer doesn't already have that layout.
+    pub(crate) fn from_rgba_aligned(image: RgbaImage, row_alignment: usize) -> Self {
+        Self::register(image, row_alignment, None)
+    }
+
+    /// Hands a frame of a background capture over to the caller, with its buffer counted against
+    /// the capture's resource usage until it is freed.
+    pub(crate) fn from_rgba_owned(image: RgbaImage, owner: &Arc<Usage>) -> Self {
+        Self::register(image, 0, Some(owner.clone()))
+    }
+
+    fn register(image: RgbaImage, row_alignment: usize, owner: Option<Arc<Usage>>) -> Self {
+        let width = image.width();
+        let height = image.height();
+
+        // The buffer stays alive until the C side calls capture_free_image
+        match buffer::register_rgba(image, row_alignment, owner) {
+            Some((data, len, stride)) => CapturedImage {
+                data,
+                len,
+                width,
+                height,
+                stride,
+            },
+            None => CapturedImage::empty(),
+        }
+    }
+}
+
+/// A rectangle in pixels. Depending on the function, relative to an image or in global
+/// screen coordinates.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct CaptureRect {
+    pub x: c_int,
+    pub y: c_int,
+    pub width: c_uint,
+    pub height: c_uint,
 }
 
+/// A caller-provided context pointer that is only ever handed back to the caller's callbacks.
+#[derive(Clone, Copy)]
+pub(crate) struct UserData(pub(crate) *mut c_void);
+
+// SAFETY: the library never dereferences user data; keeping it usable from the thread
+// a callback fires on is the caller's responsibility.
+unsafe impl Send for UserData {}
+unsafe impl Sync for UserData {}
+
 // --- Helper for Error Handling (Optional but Recommended) ---
+
+ffi_enum! {
+    /// Category of the last error, as returned by capture_last_error_code().
+    pub enum ErrorCode {
+        /// No error has been reported on this thread yet.
+        Ok = 0 => CAPTURE_OK,
+        /// Anything not covered by a more specific code.
+        Failed = 1 => CAPTURE_ERROR_FAILED,
+        /// A NULL or out-of-range argument.
+        InvalidArgument = 2 => CAPTURE_ERROR_INVALID_ARGUMENT,
+        /// A monitor index that doesn't exist (anymore).
+        InvalidIndex = 3 => CAPTURE_ERROR_INVALID_INDEX,
+        /// The platform capture backend reported an error.
+        Backend = 4 => CAPTURE_ERROR_BACKEND,
+        /// The operation isn't available on this platform or build.
+        Unsupported = 5 => CAPTURE_ERROR_UNSUPPORTED,
+        /// A handle of the wrong type, already freed, or never issued by this library.
+        InvalidHandle = 6 => CAPTURE_ERROR_INVALID_HANDLE,
+        /// A capture is larger than the limit set with capture_set_max_pixels().
+        TooLarge = 7 => CAPTURE_ERROR_TOO_LARGE,
+        /// Reading or writing a file or other output failed.
+        Io = 8 => CAPTURE_ERROR_IO,
+        /// The output file exists and the collision policy says not to replace it.
+        AlreadyExists = 9 => CAPTURE_ERROR_ALREADY_EXISTS,
+        /// The operation was stopped through its cancellation token.
+        Cancelled = 10 => CAPTURE_ERROR_CANCELLED,
+        /// Nothing arrived within the time the caller was willing to wait.
+        Timeout = 11 => CAPTURE_ERROR_TIMEOUT,
+        /// A reader got to the end of its input.
+        EndOfData = 12 => CAPTURE_ERROR_END_OF_DATA,
+    }
+}
+
+/// An error code plus the message reported through capture_last_error_message().
+#[derive(Debug)]
+pub(crate) struct CaptureError {
+    pub(crate) code: ErrorCode,
+    pub(crate) message: String,
+}
+
+impl CaptureError {
+    pub(crate) fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        CaptureError {
+            code,
+            message: message.into(),
+        }
+    }
+
+    /// Prefixes the message, keeping the code.
+    pub(crate) fn context(self, context: impl fmt::Display) -> Self {
+        CaptureError {
+            code: self.code,
+            message: format!("{}: {}", context, self.message),
+        }
+    }
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<String> for CaptureError {
+    fn from(message: String) -> Self {
+        CaptureError::new(ErrorCode::Failed, message)
+    }
+}
+
+impl From<std::io::Error> for CaptureError {
+    fn from(err: std::io::Error) -> Self {
+        CaptureError::new(ErrorCode::Io, err.to_string())
+    }
+}
+
+#[cfg(feature = "native")]
+impl From<xcap::XCapError> for CaptureError {
+    fn from(err: xcap::XCapError) -> Self {
+        CaptureError::new(ErrorCode::Backend, err.to_string())
+    }
+}
+
 // Store the last error message
 thread_local! {
-    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+    static LAST_ERROR: RefCell<Option<(ErrorCode, CString)>> = const { RefCell::new(None) };
+    /// How many errors were reported on this thread, so a caller can tell whether a call it made
+    /// reported one even when it's the same as the last.
+    static ERRORS_REPORTED: Cell<u64> = const { Cell::new(0) };
 }
 
-fn set_last_error(err: String) {
+fn set_last_error(err: impl Into<CaptureError>) {
+    let err = err.into();
+    ERRORS_REPORTED.with(|count| count.set(count.get() + 1));
     LAST_ERROR.with(|cell| {
-        *cell.borrow_mut() = Some(
-            CString::new(err)
+        *cell.borrow_mut() = Some((
+            err.code,
+            CString::new(err.message)
                 .unwrap_or_else(|_| CString::new("Failed to create error message").unwrap()),
-        );
+        ));
     });
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn capture_last_error_message() -> *const c_char {
-    LAST_ERROR.with(|cell| cell.borrow().as_ref().map_or(ptr::null(), |s| s.as_ptr()))
+    LAST_ERROR.with(|cell| {
+        cell.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |(_, s)| s.as_ptr())
+    })
+}
+
+/// Gets the ErrorCode (CAPTURE_ERROR_*) of the error reported by capture_last_error_message().
+/// Returns CAPTURE_OK if no error has been reported on this thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_last_error_code() -> c_int {
+    LAST_ERROR.with(|cell| {
+        cell.borrow()
+            .as_ref()
+            .map_or(ErrorCode::Ok, |(code, _)| *code) as c_int
+    })
+}
+
+// --- Feature Detection ---
+
+ffi_enum! {
+    /// Optional capabilities that depend on the platform or build.
+    pub enum Feature {
+        /// Capturing monitors through xcap. Off in `wasm` builds, which capture what the host
+        /// supplies instead (see CAPTURE_FEATURE_HOST_FRAMES).
+        MonitorCapture = 0 => CAPTURE_FEATURE_MONITOR_CAPTURE,
+        /// capture_stream_start() and friends, which need a capture backend and background
+        /// threads.
+        Streams = 1 => CAPTURE_FEATURE_STREAMS,
+        /// Native overlay windows, needed by capture_measure_latency() and
+        /// capture_highlight_target().
+        Overlay = 2 => CAPTURE_FEATURE_OVERLAY,
+        /// Moving and resizing windows for capture_window_image_ex().
+        WindowPlacement = 3 => CAPTURE_FEATURE_WINDOW_PLACEMENT,
+        /// Monitors and frames supplied by the host through capture_host_set_monitors() and
+        /// capture_host_set_frame_source(), in builds with the `wasm` feature.
+        HostFrames = 4 => CAPTURE_FEATURE_HOST_FRAMES,
+    }
+}
+
+/// Checks whether a Feature (CAPTURE_FEATURE_*) is available in this build on this platform.
+/// Unknown values, e.g. from a newer header, report false. Processing-only builds report none of
+/// them: everything that doesn't capture (encoding, composing, views, the image functions) is
+/// always available.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_supports(feature: c_int) -> bool {
+    match Feature::from_raw(feature) {
+        Some(Feature::MonitorCapture) => cfg!(feature = "native"),
+        Some(Feature::Streams) => backend::CAPTURE && backend::THREADS,
+        Some(Feature::Overlay) => overlay::SUPPORTED,
+        Some(Feature::WindowPlacement) => placement::SUPPORTED,
+        Some(Feature::HostFrames) => cfg!(feature = "wasm"),
+        None => false,
+    }
 }
 
 // --- Monitor Functions ---
 
+/// Looks up the monitor at the specified index.
+fn monitor_at(index: usize) -> Result<Monitor, CaptureError> {
+    let monitors =
+        Monitor::all().map_err(|e| CaptureError::from(e).context("Error fetching monitors"))?;
+    monitors.into_iter().nth(index).ok_or_else(|| {
+        CaptureError::new(
+            ErrorCode::InvalidIndex,
+            format!("Monitor index out of bounds: {}", index),
+        )
+    })
+}
+
 /// Gets the number of connected monitors.
 /// Returns 0 if there's an error fetching the monitors.
 #[unsafe(no_mangle)]
@@ -48,9 +321,9 @@ pub extern "C" fn capture_monitor_count() -> size_t {
     match Monitor::all() {
         Ok(monitors) => monitors.len(),
         Err(e) => {
-            let err_msg = format!("Error fetching monitors: {}", e);
-            eprintln!("{}", err_msg);
-            set_last_error(err_msg);
+            let err = CaptureError::from(e).context("Error fetching monitors");
+            eprintln!("{}", err);
+            set_last_error(err);
             0
         }
     }
@@ -69,7 +342,7 @@ pub extern "C" fn capture_monitor_name(index: size_t) -> *mut c_char {
                 match CString::new(monitor.name()) {
                     Ok(c_string) => c_string.into_raw(),
                     Err(_) => {
-                        let err_msg = format!("Monitor name contains null bytes");
+                        let err_msg = "Monitor name contains null bytes".to_string();
                         set_last_error(err_msg);
                         ptr::null_mut() // Name contained null bytes
                     }
@@ -77,14 +350,13 @@ pub extern "C" fn capture_monitor_name(index: size_t) -> *mut c_char {
             } else {
                 // Index out of bounds
                 let err_msg = format!("Monitor index out of bounds: {}", index);
-                set_last_error(err_msg);
+                set_last_error(CaptureError::new(ErrorCode::InvalidIndex, err_msg));
                 ptr::null_mut()
             }
         }
         Err(e) => {
             // Error fetching monitors
-            let err_msg = format!("Error fetching monitors: {}", e);
-            set_last_error(err_msg);
+            set_last_error(CaptureError::from(e).context("Error fetching monitors"));
             ptr::null_mut()
         }
     }
@@ -101,14 +373,13 @@ pub extern "C" fn capture_monitor_id(index: size_t) -> c_uint {
                 m.id()
             } else {
                 let err_msg = format!("Monitor index out of bounds: {}", index);
-                set_last_error(err_msg);
+                set_last_error(CaptureError::new(ErrorCode::InvalidIndex, err_msg));
                 0
             }
         }
         Err(e) => {
             // Error fetching monitors
-            let err_msg = format!("Error fetching monitors: {}", e);
-            set_last_error(err_msg);
+            set_last_error(CaptureError::from(e).context("Error fetching monitors"));
             0
         }
     }
@@ -124,13 +395,12 @@ pub extern "C" fn capture_monitor_width(index: size_t) -> c_uint {
                 m.width()
             } else {
                 let err_msg = format!("Monitor index out of bounds: {}", index);
-                set_last_error(err_msg);
+                set_last_error(CaptureError::new(ErrorCode::InvalidIndex, err_msg));
                 0
             }
         }
         Err(e) => {
-            let err_msg = format!("Error fetching monitors: {}", e);
-            set_last_error(err_msg);
+            set_last_error(CaptureError::from(e).context("Error fetching monitors"));
             0
         }
     }
@@ -146,84 +416,241 @@ pub extern "C" fn capture_monitor_height(index: size_t) -> c_uint {
                 m.height()
             } else {
                 let err_msg = format!("Monitor index out of bounds: {}", index);
-                set_last_error(err_msg);
+                set_last_error(CaptureError::new(ErrorCode::InvalidIndex, err_msg));
                 0
             }
         }
         Err(e) => {
-            let err_msg = format!("Error fetching monitors: {}", e);
-            set_last_error(err_msg);
+            set_last_error(CaptureError::from(e).context("Error fetching monitors"));
+            0
+        }
+    }
+}
+
+// --- Window Functions ---
+
+/// Looks up the window at the specified index.
+fn window_at(index: usize) -> Result<Window, CaptureError> {
+    let windows =
+        Window::all().map_err(|e| CaptureError::from(e).context("Error fetching windows"))?;
+    windows.into_iter().nth(index).ok_or_else(|| {
+        CaptureError::new(
+            ErrorCode::InvalidIndex,
+            format!("Window index out of bounds: {}", index),
+        )
+    })
+}
+
+/// Gets the number of open windows, in stacking order from the top.
+/// Returns 0 if there's an error fetching the windows.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_window_count() -> size_t {
+    match Window::all() {
+        Ok(windows) => windows.len(),
+        Err(e) => {
+            set_last_error(CaptureError::from(e).context("Error fetching windows"));
             0
         }
     }
 }
 
+/// Gets the platform-specific ID of the window at the specified index, for use in a
+/// CaptureTarget. Window indices change as windows open and close; IDs don't.
+/// Returns 0 if the index is out of bounds or an error occurs.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_window_id(index: size_t) -> c_uint {
+    match window_at(index) {
+        Ok(window) => window.id(),
+        Err(err) => {
+            set_last_error(err);
+            0
+        }
+    }
+}
+
+/// Gets the title of the window at the specified index.
+/// The caller MUST call capture_free_string() on the returned pointer to free the memory.
+/// Returns NULL if the index is out of bounds or an error occurs.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_window_title(index: size_t) -> *mut c_char {
+    let title = window_at(index).and_then(|window| {
+        CString::new(window.title())
+            .map_err(|_| CaptureError::from("Window title contains null bytes".to_string()))
+    });
+    match title {
+        Ok(title) => title.into_raw(),
+        Err(err) => {
+            set_last_error(err);
+            ptr::null_mut()
+        }
+    }
+}
+
 // --- Capture Functions ---
 
+/// Captures a frame of a monitor with the process-wide size limit and frame processors applied.
+/// Every capture delivered to callers goes through here or through capture_frame().
+fn capture_background_frame(monitor: &Monitor) -> Result<RgbaImage, CaptureError> {
+    limits::check_nominal(monitor.width(), monitor.height())?;
+    let image = monitor.capture_image()?;
+    usage::converting(|| {
+        let mut image = limits::apply(image)?;
+        processor::run(&mut image)?;
+        Ok(image)
+    })
+}
+
+/// Captures a frame for a one-shot request, ahead of any running streams.
+fn capture_frame(monitor: &Monitor) -> Result<RgbaImage, CaptureError> {
+    let _lane = priority::interactive();
+    capture_background_frame(monitor)
+}
+
+/// Captures a window with the process-wide size limit applied, like capture_background_frame().
+fn capture_background_window_frame(window: &Window) -> Result<RgbaImage, CaptureError> {
+    limits::check_nominal(window.width(), window.height())?;
+    let image = window.capture_image()?;
+    usage::converting(|| {
+        let mut image = limits::apply(image)?;
+        processor::run(&mut image)?;
+        Ok(image)
+    })
+}
+
+/// Captures a window for a one-shot request, like capture_frame().
+fn capture_window_frame(window: &Window) -> Result<RgbaImage, CaptureError> {
+    let _lane = priority::interactive();
+    capture_background_window_frame(window)
+}
+
 /// Captures an image of the monitor at the specified index.
 /// Returns a CapturedImage struct containing the image data.
 /// The caller MUST call capture_free_image() on the returned struct to free the data buffer.
 /// Returns a struct with NULL data pointer and zero dimensions if an error occurs or index is invalid.
 #[unsafe(no_mangle)]
 pub extern "C" fn capture_monitor_image(index: size_t) -> CapturedImage {
-    let empty_image = CapturedImage {
-        data: ptr::null_mut(),
-        len: 0,
-        width: 0,
-        height: 0,
-    };
+    let empty_image = CapturedImage::empty();
 
     match Monitor::all() {
         Ok(monitors) => {
             if let Some(monitor) = monitors.get(index) {
-                match monitor.capture_image() {
+                match capture_frame(monitor) {
                     Ok(image) => {
-                        let width = image.width();
-                        let height = image.height();
-
-                        let buffer_data = image.as_bytes().to_vec();
-
-                        let mut buffer = buffer_data.into_boxed_slice();
-                        let data = buffer.as_mut_ptr();
-                        let len = buffer.len();
-
-                        // Prevent Rust from freeing the memory now; C side will call capture_free_image
-                        std::mem::forget(buffer);
-
-                        CapturedImage {
-                            data,
-                            len,
-                            width,
-                            height,
-                        }
+                        let image = CapturedImage::from_rgba(image);
+                        gallery::record(&image, target::CaptureTarget::monitor(monitor));
+                        image
                     }
                     Err(e) => {
-                        let err_msg = format!("Error capturing image for monitor {}: {}", index, e);
-                        eprintln!("{}", err_msg);
-                        set_last_error(err_msg);
+                        let err = e.context(format!("Error capturing image for monitor {}", index));
+                        eprintln!("{}", err);
+                        set_last_error(err);
                         empty_image
                     }
                 }
             } else {
                 let err_msg = format!("Invalid monitor index: {}", index);
                 eprintln!("{}", err_msg);
-                set_last_error(err_msg);
+                set_last_error(CaptureError::new(ErrorCode::InvalidIndex, err_msg));
                 empty_image
             }
         }
         Err(e) => {
-            let err_msg = format!("Error fetching monitors: {}", e);
-            eprintln!("{}", err_msg);
-            set_last_error(err_msg);
+            let err = CaptureError::from(e).context("Error fetching monitors");
+            eprintln!("{}", err);
+            set_last_error(err);
             empty_image
         }
     }
 }
 
+/// Captures an image of the monitor at the specified index, like capture_monitor_image(), with
+/// the given options (NULL for defaults; see capture_options_default()).
+/// The caller MUST call capture_free_image() on the returned struct to free the data buffer.
+/// Returns a struct with NULL data pointer if an error occurs, the index is invalid or the
+/// options are malformed.
+///
+/// # Safety
+/// `options` must be NULL or point to a CaptureOptions initialized with capture_options_default().
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_monitor_image_ex(
+    index: size_t,
+    options: *const options::CaptureOptions,
+) -> CapturedImage {
+    let result = unsafe { options::CaptureOptions::read(options) }.and_then(|options| {
+        let monitor = monitor_at(index)?;
+        let image = capture_frame(&monitor)
+            .map_err(|e| e.context(format!("Error capturing image for monitor {}", index)))?;
+        let image = dpi::normalize(
+            image,
+            (monitor.width(), monitor.height()),
+            options.output_scale,
+        )?;
+        let image = CapturedImage::from_rgba_aligned(image, options.row_alignment);
+        gallery::record(&image, target::CaptureTarget::monitor(&monitor));
+        Ok(image)
+    });
+    match result {
+        Ok(image) => image,
+        Err(err) => {
+            eprintln!("{}", err);
+            set_last_error(err);
+            CapturedImage::empty()
+        }
+    }
+}
+
+/// Captures the window with the given ID (see capture_window_id()), with the given options (NULL
+/// for defaults; see capture_options_default()).
+/// With a non-NULL `frame`, the window is first moved and resized so its content area covers
+/// exactly that rectangle in screen coordinates, then put back where it was after the capture,
+/// so the same window gives identically framed screenshots across runs and machines. Fails
+/// instead of capturing if the window manager won't apply that exact frame, e.g. because of the
+/// application's minimum size, and with CAPTURE_ERROR_UNSUPPORTED where windows can't be moved
+/// (see CAPTURE_FEATURE_WINDOW_PLACEMENT).
+/// The caller MUST call capture_free_image() on the returned struct to free the data buffer.
+/// Returns a struct with NULL data pointer if an error occurs.
+///
+/// # Safety
+/// `options` must be NULL or point to a CaptureOptions initialized with capture_options_default().
+/// `frame` must be NULL or point to a valid CaptureRect.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_window_image_ex(
+    window_id: c_uint,
+    options: *const options::CaptureOptions,
+    frame: *const CaptureRect,
+) -> CapturedImage {
+    let frame = unsafe { frame.as_ref() }.copied();
+    let result = unsafe { options::CaptureOptions::read(options) }.and_then(|options| {
+        let target = target::CaptureTarget::window(window_id);
+        let _placement = frame
+            .map(|frame| placement::Placement::apply(window_id, frame))
+            .transpose()?;
+        let image = target
+            .capture()
+            .map_err(|e| e.context(format!("Error capturing window {}", window_id)))?;
+        let bounds = target.bounds()?;
+        let image = dpi::normalize(image, (bounds.width, bounds.height), options.output_scale)?;
+        let image = CapturedImage::from_rgba_aligned(image, options.row_alignment);
+        gallery::record(&image, target);
+        Ok(image)
+    });
+    match result {
+        Ok(image) => image,
+        Err(err) => {
+            eprintln!("{}", err);
+            set_last_error(err);
+            CapturedImage::empty()
+        }
+    }
+}
+
 // --- Memory Management Functions ---
 
 /// Frees a C string allocated by Rust (e.g., returned by capture_monitor_name).
 /// Call this with the pointer received from Rust functions that return *mut c_char.
+///
+/// # Safety
+/// `ptr` must be NULL or a pointer returned by this library that has not been freed yet.
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn capture_free_string(ptr: *mut c_char) {
     if !ptr.is_null() {
@@ -232,14 +659,69 @@ pub unsafe extern "C" fn capture_free_string(ptr: *mut c_char) {
     }
 }
 
-/// Frees the image data buffer allocated by Rust (contained within CapturedImage).
-/// Call this with the struct received from capture_monitor_image.
+/// Releases one reference to the image data buffer allocated by Rust (contained within
+/// CapturedImage); the buffer is freed together with its last reference.
+/// Call this with the struct received from capture_monitor_image, a stream frame callback or
+/// capture_image_clone_ref.
+/// Freeing a buffer that is already gone sets CAPTURE_ERROR_INVALID_HANDLE instead of crashing.
+///
+/// # Safety
+/// `image.data` must not be used after the reference passed here was the last one.
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn capture_free_image(image: CapturedImage) {
-    if !image.data.is_null() {
-        // Reconstruct the Vec<u8> or Box<[u8]> from the raw parts and let it drop.
-        // We know len is the original length. Capacity might differ, but from_raw_parts handles this.
-        let slice = unsafe { slice::from_raw_parts_mut(image.data, image.len) };
-        let _ = unsafe { Box::from_raw(slice) }; // Takes ownership and drops when scope ends.
+    if image.data.is_null() {
+        return;
+    }
+    if let Err(err) = buffer::release(image.data) {
+        eprintln!("{}", err);
+        set_last_error(err);
+    }
+}
+
+/// Copies caller-provided RGBA8 pixels into a library-owned image, so frames captured elsewhere
+/// (another process, a browser, a file) can be passed to the functions that take a CapturedImage:
+/// views, composing, encoding and saving. `stride` is the number of bytes from the start of one
+/// row to the start of the next; 0 means width * 4. Frame processors don't run on it.
+/// The caller MUST call capture_free_image() on the returned struct to free the data buffer.
+/// Returns a struct with NULL data pointer if a dimension is 0 or the stride is too small.
+///
+/// # Safety
+/// `data` must point to at least `stride * (height - 1) + width * 4` readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_image_from_rgba(
+    data: *const u8,
+    width: c_uint,
+    height: c_uint,
+    stride: size_t,
+) -> CapturedImage {
+    match unsafe { buffer::copy_rgba(data, width, height, stride) } {
+        Ok(image) => CapturedImage::from_rgba(image),
+        Err(err) => {
+            set_last_error(err);
+            CapturedImage::empty()
+        }
+    }
+}
+
+/// Adds a reference to a captured image's buffer and returns the same image, so it can be handed
+/// to another consumer (e.g. an encoder thread) without copying.
+/// Every reference, including the original one, MUST be released with capture_free_image().
+/// The buffer is shared: treat it as read-only while more than one reference exists.
+/// Returns a struct with NULL data pointer if `image` is empty or was already freed.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_image_clone_ref(image: CapturedImage) -> CapturedImage {
+    if image.data.is_null() {
+        set_last_error(CaptureError::new(
+            ErrorCode::InvalidArgument,
+            "Image has no data",
+        ));
+        return CapturedImage::empty();
+    }
+    match buffer::retain(image.data) {
+        Ok(_) => image,
+        Err(err) => {
+            set_last_error(err);
+            CapturedImage::empty()
+        }
     }
 }
@@ -0,0 +1,106 @@
+// capture-ffi/src/limits.rs
+//! A process-wide cap on the size of captured frames, so a huge virtual desktop can't exhaust
+//! the address space of a 32-bit or memory-constrained host.
+
+use crate::{CaptureError, ErrorCode, set_last_error};
+use image::{RgbaImage, imageops};
+use libc::c_int;
+use std::sync::atomic::{AtomicI32, AtomicU64, Ordering};
+
+ffi_enum! {
+    /// What happens to a capture larger than the limit set with capture_set_max_pixels().
+    pub enum OversizePolicy {
+        /// Fail with CAPTURE_ERROR_TOO_LARGE.
+        Reject = 0 => CAPTURE_OVERSIZE_REJECT,
+        /// Scale the frame down, keeping its aspect ratio, until it fits.
+        Downscale = 1 => CAPTURE_OVERSIZE_DOWNSCALE,
+    }
+}
+
+/// 0 means unlimited.
+static MAX_PIXELS: AtomicU64 = AtomicU64::new(0);
+static POLICY: AtomicI32 = AtomicI32::new(OversizePolicy::Reject as i32);
+
+fn limit() -> Option<(u64, OversizePolicy)> {
+    let max_pixels = MAX_PIXELS.load(Ordering::Relaxed);
+    let policy = OversizePolicy::from_raw(POLICY.load(Ordering::Relaxed))?;
+    (max_pixels > 0).then_some((max_pixels, policy))
+}
+
+fn too_large(width: u32, height: u32, max_pixels: u64) -> CaptureError {
+    CaptureError::new(
+        ErrorCode::TooLarge,
+        format!(
+            "{}x{} capture exceeds the limit of {} pixels",
+            width, height, max_pixels
+        ),
+    )
+}
+
+/// Rejects a capture up front when even its nominal size is over the limit, so the backend never
+/// allocates the frame. The backend may still deliver more pixels than this (HiDPI), which
+/// `apply` catches afterwards.
+pub(crate) fn check_nominal(width: u32, height: u32) -> Result<(), CaptureError> {
+    match limit() {
+        Some((max_pixels, OversizePolicy::Reject)) if width as u64 * height as u64 > max_pixels => {
+            Err(too_large(width, height, max_pixels))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Enforces the limit on a captured frame.
+pub(crate) fn apply(image: RgbaImage) -> Result<RgbaImage, CaptureError> {
+    let (width, height) = image.dimensions();
+    let pixels = width as u64 * height as u64;
+    let Some((max_pixels, policy)) = limit().filter(|(max_pixels, _)| pixels > *max_pixels) else {
+        return Ok(image);
+    };
+
+    match policy {
+        OversizePolicy::Reject => Err(too_large(width, height, max_pixels)),
+        OversizePolicy::Downscale => {
+            let scale = (max_pixels as f64 / pixels as f64).sqrt();
+            // Round down so the result never ends up a pixel over the limit.
+            let new_width = ((width as f64 * scale) as u32).max(1);
+            let new_height = ((height as f64 * scale) as u32).max(1);
+            // The clamp to one pixel can put a very thin frame back over the limit.
+            if new_width as u64 * new_height as u64 > max_pixels {
+                return Err(too_large(width, height, max_pixels));
+            }
+            Ok(imageops::resize(
+                &image,
+                new_width,
+                new_height,
+                imageops::FilterType::Triangle,
+            ))
+        }
+    }
+}
+
+/// Sets the limit for all later captures; 0 removes it.
+pub(crate) fn set(max_pixels: u64, policy: OversizePolicy) {
+    POLICY.store(policy as i32, Ordering::Relaxed);
+    MAX_PIXELS.store(max_pixels, Ordering::Relaxed);
+}
+
+/// Limits every capture (one-shot and streams) to `max_pixels` pixels; 0 removes the limit.
+/// `policy` is an OversizePolicy (CAPTURE_OVERSIZE_*) saying whether larger captures fail with
+/// CAPTURE_ERROR_TOO_LARGE or are scaled down to fit; frames so thin that they'd be over the limit
+/// even one pixel high (or wide) fail either way. With CAPTURE_OVERSIZE_REJECT, captures whose
+/// monitor is already too large by its reported size fail before any pixels are read.
+/// Returns CAPTURE_OK, or CAPTURE_ERROR_INVALID_ARGUMENT for an unknown policy.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_set_max_pixels(max_pixels: u64, policy: c_int) -> c_int {
+    let Some(oversize) = OversizePolicy::from_raw(policy) else {
+        let err = CaptureError::new(
+            ErrorCode::InvalidArgument,
+            format!("Unknown oversize policy: {}", policy),
+        );
+        let code = err.code as c_int;
+        set_last_error(err);
+        return code;
+    };
+    set(max_pixels, oversize);
+    ErrorCode::Ok as c_int
+}
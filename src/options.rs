@@ -0,0 +1,224 @@
+// capture-ffi/src/options.rs
+use crate::{CaptureError, ErrorCode, config, dpi, encode::ImageFormat, output::CollisionPolicy};
+use libc::{c_int, c_uint, size_t};
+use std::{mem, ptr};
+
+/// Largest supported row alignment (one page).
+const MAX_ROW_ALIGNMENT: size_t = 4096;
+
+/// JPEG quality used when EncodeOptions.quality is 0.
+pub(crate) const DEFAULT_JPEG_QUALITY: u8 = 90;
+
+/// Options for the `*_ex` capture functions.
+/// Always start from capture_options_default(): `struct_size` lets newer libraries accept structs
+/// from callers built against older, shorter versions of it. Fields only ever get appended, and
+/// all of them are plain integers so any bit pattern is safe to read.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct CaptureOptions {
+    /// Size of this struct in bytes as known by the caller.
+    pub struct_size: size_t,
+    /// Alignment in bytes of the data pointer and of every row start in captured images, with
+    /// rows padded to a multiple of it (see CapturedImage.stride). 0 or 1 means tightly packed
+    /// rows; otherwise a power of two up to 4096, e.g. 64 for SIMD or GPU uploads.
+    pub row_alignment: size_t,
+    /// Size of captured images in percent of the target's logical size, whatever the scale factor
+    /// of the monitor it is on: 100 always gives 1x logical pixels, 200 gives 2x. 0 (the default)
+    /// keeps the monitor's native pixels. At most 800.
+    pub output_scale: c_uint,
+}
+
+impl Default for CaptureOptions {
+    fn default() -> Self {
+        CaptureOptions {
+            struct_size: mem::size_of::<CaptureOptions>(),
+            row_alignment: 0,
+            output_scale: 0,
+        }
+    }
+}
+
+/// Copies a caller-provided options struct over `defaults`, keeping the defaults for trailing
+/// fields the caller doesn't know about.
+///
+/// # Safety
+/// `T` must be `#[repr(C)]`, start with a `struct_size: size_t` field and contain only plain
+/// integers. `options` must point to at least `(*options).struct_size` readable bytes.
+pub(crate) unsafe fn read_sized<T: Copy>(
+    options: *const T,
+    mut defaults: T,
+    name: &str,
+    default_fn: &str,
+) -> Result<T, CaptureError> {
+    let struct_size = unsafe { ptr::read_unaligned(options as *const size_t) };
+    if struct_size < mem::size_of::<size_t>() {
+        return Err(CaptureError::new(
+            ErrorCode::InvalidArgument,
+            format!(
+                "{}.struct_size is {}; initialize options with {}()",
+                name, struct_size, default_fn
+            ),
+        ));
+    }
+    let known = struct_size.min(mem::size_of::<T>());
+    unsafe {
+        ptr::copy_nonoverlapping(
+            options as *const u8,
+            &mut defaults as *mut T as *mut u8,
+            known,
+        )
+    };
+    // Report our own size from here on.
+    unsafe { ptr::write_unaligned(&mut defaults as *mut T as *mut size_t, mem::size_of::<T>()) };
+    Ok(defaults)
+}
+
+impl CaptureOptions {
+    /// Reads caller-provided options, filling fields the caller doesn't know about with defaults.
+    /// NULL means all defaults.
+    ///
+    /// # Safety
+    /// `options` must be NULL or point to at least `(*options).struct_size` readable bytes.
+    pub(crate) unsafe fn read(options: *const CaptureOptions) -> Result<Self, CaptureError> {
+        if options.is_null() {
+            return Ok(CaptureOptions::default());
+        }
+        let resolved = unsafe {
+            read_sized(
+                options,
+                CaptureOptions::default(),
+                "CaptureOptions",
+                "capture_options_default",
+            )?
+        };
+        resolved.validate()?;
+        Ok(resolved)
+    }
+
+    fn validate(&self) -> Result<(), CaptureError> {
+        let alignment = self.row_alignment;
+        if alignment > 1 && (!alignment.is_power_of_two() || alignment > MAX_ROW_ALIGNMENT) {
+            return Err(CaptureError::new(
+                ErrorCode::InvalidArgument,
+                format!(
+                    "Row alignment must be a power of two up to {}, got {}",
+                    MAX_ROW_ALIGNMENT, alignment
+                ),
+            ));
+        }
+        if self.output_scale > dpi::MAX_OUTPUT_SCALE {
+            return Err(CaptureError::new(
+                ErrorCode::InvalidArgument,
+                format!(
+                    "Output scale must be at most {}%, got {}%",
+                    dpi::MAX_OUTPUT_SCALE,
+                    self.output_scale
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Returns CaptureOptions with every field at its default value and `struct_size` filled in.
+/// Initialize options with this before changing individual fields.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_options_default() -> CaptureOptions {
+    CaptureOptions::default()
+}
+
+/// Options for the functions that save or encode captures. Versioned like CaptureOptions: always
+/// start from capture_encode_options_default().
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct EncodeOptions {
+    /// Size of this struct in bytes as known by the caller.
+    pub struct_size: size_t,
+    /// An ImageFormat (CAPTURE_FORMAT_*). Defaults to PNG, or the format set by
+    /// capture_load_config().
+    pub format: c_int,
+    /// JPEG quality from 1 to 100; 0 means the default of 90. Ignored for PNG. Defaults to 0, or
+    /// the quality set by capture_load_config().
+    pub quality: c_int,
+    /// Non-zero (the default) writes files under a temporary name next to the target and renames
+    /// them into place once complete, so readers never see a partial file. Set to 0 on
+    /// filesystems without atomic rename.
+    pub atomic_write: c_int,
+    /// Non-zero flushes files to disk before returning. Off by default.
+    pub fsync: c_int,
+    /// A CollisionPolicy (CAPTURE_COLLISION_*) for when the output file already exists.
+    /// Defaults to overwriting it.
+    pub collision: c_int,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        EncodeOptions {
+            struct_size: mem::size_of::<EncodeOptions>(),
+            format: config::image_format().unwrap_or(ImageFormat::Png) as c_int,
+            quality: config::quality().map_or(0, c_int::from),
+            atomic_write: 1,
+            fsync: 0,
+            collision: CollisionPolicy::Overwrite as c_int,
+        }
+    }
+}
+
+impl EncodeOptions {
+    /// Reads caller-provided options like CaptureOptions::read().
+    ///
+    /// # Safety
+    /// `options` must be NULL or point to at least `(*options).struct_size` readable bytes.
+    pub(crate) unsafe fn read(options: *const EncodeOptions) -> Result<Self, CaptureError> {
+        if options.is_null() {
+            return Ok(EncodeOptions::default());
+        }
+        let resolved = unsafe {
+            read_sized(
+                options,
+                EncodeOptions::default(),
+                "EncodeOptions",
+                "capture_encode_options_default",
+            )?
+        };
+        resolved.image_format()?;
+        resolved.jpeg_quality()?;
+        resolved.collision_policy()?;
+        Ok(resolved)
+    }
+
+    pub(crate) fn image_format(&self) -> Result<ImageFormat, CaptureError> {
+        ImageFormat::from_raw(self.format).ok_or_else(|| {
+            CaptureError::new(
+                ErrorCode::InvalidArgument,
+                format!("Unknown image format: {}", self.format),
+            )
+        })
+    }
+
+    pub(crate) fn collision_policy(&self) -> Result<CollisionPolicy, CaptureError> {
+        CollisionPolicy::from_raw(self.collision).ok_or_else(|| {
+            CaptureError::new(
+                ErrorCode::InvalidArgument,
+                format!("Unknown collision policy: {}", self.collision),
+            )
+        })
+    }
+
+    pub(crate) fn jpeg_quality(&self) -> Result<u8, CaptureError> {
+        match self.quality {
+            0 => Ok(DEFAULT_JPEG_QUALITY),
+            1..=100 => Ok(self.quality as u8),
+            quality => Err(CaptureError::new(
+                ErrorCode::InvalidArgument,
+                format!("JPEG quality must be between 1 and 100, got {}", quality),
+            )),
+        }
+    }
+}
+
+/// Returns EncodeOptions with every field at its default value and `struct_size` filled in.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_encode_options_default() -> EncodeOptions {
+    EncodeOptions::default()
+}
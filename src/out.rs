@@ -0,0 +1,176 @@
+// capture-ffi/src/out.rs
+//! Out-pointer variants of every function that returns a struct by value, for FFI runtimes that
+//! can't receive structs as return values (Bun, Python ctypes on some platforms, Node ffi-napi).
+//! Each `<name>_out()` takes the same arguments plus a pointer the result is written to, and
+//! returns CAPTURE_OK, or the ErrorCode of the error the call reported.
+//! They are C-level only: the Deno bindings take structs by value and don't use them.
+
+use crate::{
+    CaptureError, CaptureRect, CapturedImage, ERRORS_REPORTED, ErrorCode,
+    archive::{
+        ArchiveEstimate, ArchiveOptions, ArchiveStats, capture_archive_estimate,
+        capture_archive_options_default, capture_archive_reader_next, capture_archive_stats,
+    },
+    capture_image_clone_ref, capture_image_from_rgba, capture_last_error_code,
+    capture_monitor_image, capture_monitor_image_ex, capture_window_image_ex,
+    compose::capture_compose_grid,
+    composite::{
+        CompositeLayer, CompositeOptions, capture_composite_layer_default,
+        capture_composite_options_default,
+    },
+    countdown::capture_delayed,
+    delta::capture_delta_decode,
+    gallery::{GalleryQuery, capture_gallery_get, capture_gallery_query_default},
+    handle::CaptureHandle,
+    heatmap::{HeatmapInfo, capture_heatmap_get, capture_heatmap_info},
+    latency::{LatencyStats, capture_measure_latency, capture_measure_latency_ex},
+    options::{
+        CaptureOptions, EncodeOptions, capture_encode_options_default, capture_options_default,
+    },
+    poll::capture_stream_poll_frame,
+    sample::{PatchSample, capture_sample_patch},
+    set_last_error,
+    stream::{StreamInfo, capture_stream_info},
+    target::CaptureTarget,
+    usage::{StreamResourceStats, capture_stream_resource_stats},
+    view::{CapturedImageView, capture_image_view, capture_image_view_to_image},
+};
+use libc::{c_int, c_uint, size_t};
+use std::cell::Cell;
+
+/// Runs `call` and writes what it returns to `out`, even if it failed, so the caller always gets
+/// the function's usual empty value. Nothing runs if `out` is NULL, so no image can leak.
+///
+/// # Safety
+/// `out` must be NULL or valid for writing a `T`.
+unsafe fn write_out<T>(out: *mut T, call: impl FnOnce() -> T) -> c_int {
+    if out.is_null() {
+        let err = CaptureError::new(ErrorCode::InvalidArgument, "Out pointer is NULL");
+        let code = err.code;
+        set_last_error(err);
+        return code as c_int;
+    }
+    let reported = ERRORS_REPORTED.with(Cell::get);
+    let value = call();
+    unsafe { out.write_unaligned(value) };
+    if ERRORS_REPORTED.with(Cell::get) == reported {
+        ErrorCode::Ok as c_int
+    } else {
+        capture_last_error_code()
+    }
+}
+
+macro_rules! out_variants {
+    ($(
+        $(#[$meta:meta])*
+        $name:ident => $inner:ident($($arg:ident: $ty:ty),*) -> $ret:ty;
+    )+) => {$(
+        #[doc = concat!(
+            "Same as ", stringify!($inner), "(), but writes the result to `out` and returns ",
+            "CAPTURE_OK or an error code."
+        )]
+        $(#[$meta])*
+        ///
+        /// # Safety
+        #[doc = concat!(
+            "`out` must be NULL or valid for writing a ", stringify!($ret), ". The arguments ",
+            "have the same requirements as for ", stringify!($inner), "()."
+        )]
+        #[unsafe(no_mangle)]
+        pub unsafe extern "C" fn $name($($arg: $ty,)* out: *mut $ret) -> c_int {
+            unsafe { write_out(out, || $inner($($arg),*)) }
+        }
+    )+};
+}
+
+out_variants! {
+    capture_monitor_image_out => capture_monitor_image(index: size_t) -> CapturedImage;
+    capture_monitor_image_ex_out => capture_monitor_image_ex(
+        index: size_t,
+        options: *const CaptureOptions
+    ) -> CapturedImage;
+    capture_window_image_ex_out => capture_window_image_ex(
+        window_id: c_uint,
+        options: *const CaptureOptions,
+        frame: *const CaptureRect
+    ) -> CapturedImage;
+    capture_delayed_out => capture_delayed(
+        target: CaptureTarget,
+        delay_secs: c_uint,
+        show_countdown: bool,
+        cancel: CaptureHandle
+    ) -> CapturedImage;
+    capture_image_from_rgba_out => capture_image_from_rgba(
+        data: *const u8,
+        width: c_uint,
+        height: c_uint,
+        stride: size_t
+    ) -> CapturedImage;
+    capture_image_clone_ref_out => capture_image_clone_ref(image: CapturedImage) -> CapturedImage;
+    capture_compose_grid_out => capture_compose_grid(
+        images: *const CapturedImage,
+        count: size_t,
+        columns: c_uint,
+        padding: c_uint,
+        bg_color: u32
+    ) -> CapturedImage;
+    capture_archive_reader_next_out => capture_archive_reader_next(
+        reader: CaptureHandle,
+        timestamp_ms: *mut u64
+    ) -> CapturedImage;
+    capture_archive_stats_out => capture_archive_stats(archive: CaptureHandle) -> ArchiveStats;
+    capture_archive_estimate_out => capture_archive_estimate(
+        first: CapturedImage,
+        second: CapturedImage,
+        options: *const ArchiveOptions,
+        fps: c_uint
+    ) -> ArchiveEstimate;
+    capture_delta_decode_out => capture_delta_decode(
+        decoder: CaptureHandle,
+        data: *const u8,
+        len: size_t
+    ) -> CapturedImage;
+    capture_gallery_get_out => capture_gallery_get(id: CaptureHandle) -> CapturedImage;
+    capture_heatmap_get_out => capture_heatmap_get(heatmap: CaptureHandle) -> CapturedImage;
+    capture_heatmap_info_out => capture_heatmap_info(heatmap: CaptureHandle) -> HeatmapInfo;
+    capture_image_view_out => capture_image_view(
+        image: CapturedImage,
+        rect: CaptureRect
+    ) -> CapturedImageView;
+    capture_image_view_to_image_out => capture_image_view_to_image(
+        view: CaptureHandle
+    ) -> CapturedImage;
+    capture_stream_info_out => capture_stream_info(stream: CaptureHandle) -> StreamInfo;
+    capture_stream_poll_frame_out => capture_stream_poll_frame(
+        handle: CaptureHandle,
+        timeout_ms: c_uint
+    ) -> CapturedImage;
+    capture_stream_resource_stats_out => capture_stream_resource_stats(
+        handle: CaptureHandle
+    ) -> StreamResourceStats;
+    capture_measure_latency_out => capture_measure_latency(
+        index: size_t,
+        samples: c_uint
+    ) -> LatencyStats;
+    /// A cancelled measurement writes the samples completed so far and returns
+    /// CAPTURE_ERROR_CANCELLED.
+    capture_measure_latency_ex_out => capture_measure_latency_ex(
+        index: size_t,
+        samples: c_uint,
+        cancel: CaptureHandle
+    ) -> LatencyStats;
+    capture_sample_patch_out => capture_sample_patch(
+        index: size_t,
+        rect: CaptureRect,
+        samples: c_uint,
+        interval_ms: c_uint,
+        cancel: CaptureHandle
+    ) -> PatchSample;
+    capture_options_default_out => capture_options_default() -> CaptureOptions;
+    capture_encode_options_default_out => capture_encode_options_default() -> EncodeOptions;
+    capture_gallery_query_default_out => capture_gallery_query_default() -> GalleryQuery;
+    capture_archive_options_default_out => capture_archive_options_default() -> ArchiveOptions;
+    capture_composite_layer_default_out => capture_composite_layer_default() -> CompositeLayer;
+    capture_composite_options_default_out => capture_composite_options_default()
+        -> CompositeOptions;
+}
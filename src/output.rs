@@ -0,0 +1,327 @@
+// capture-ffi/src/output.rs
+//! Writing capture output to files: filename templates, collision handling and atomic writes.
+
+use crate::{CaptureError, ErrorCode, config, event::now_ms, options::EncodeOptions};
+use std::{
+    fmt::Write as _,
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+ffi_enum! {
+    /// What a save does when its output file already exists.
+    pub enum CollisionPolicy {
+        /// Replace the existing file.
+        Overwrite = 0 => CAPTURE_COLLISION_OVERWRITE,
+        /// Append -1, -2, ... to the file stem until the name is free.
+        Increment = 1 => CAPTURE_COLLISION_INCREMENT,
+        /// Fail with CAPTURE_ERROR_ALREADY_EXISTS.
+        Error = 2 => CAPTURE_COLLISION_ERROR,
+    }
+}
+
+/// Highest suffix tried under CollisionPolicy::Increment.
+const MAX_INCREMENT: u32 = 9999;
+/// Longest substituted template value, in characters.
+const MAX_FIELD_LEN: usize = 128;
+
+/// Counter behind the `{seq}` placeholder, shared by all saves in the process.
+static SEQUENCE: AtomicU64 = AtomicU64::new(1);
+
+/// Values for the placeholders of an output path template.
+pub(crate) struct TemplateFields<'a> {
+    /// `{monitor}`: name of the captured monitor (or of the one the window is on).
+    pub(crate) monitor: &'a str,
+    /// `{title}`: title of the captured window; the monitor name for monitor captures.
+    pub(crate) title: &'a str,
+}
+
+/// Formats a Unix time in milliseconds as ISO 8601 basic format in UTC, `20240131T235959Z`,
+/// which sorts chronologically and is a legal file name everywhere.
+fn timestamp(ms: u64) -> String {
+    let secs = ms / 1000;
+    let (days, rem) = ((secs / 86400) as i64, secs % 86400);
+    // Civil-from-days, from Howard Hinnant's date algorithms.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60
+    )
+}
+
+/// Makes a substituted value safe as (part of) a single path component on every platform:
+/// no separators, no characters Windows rejects, no trailing dots or spaces, no `..`, and no
+/// reserved device names like `CON`.
+fn sanitize(value: &str) -> String {
+    let mut clean: String = value
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .take(MAX_FIELD_LEN)
+        .collect();
+    clean.truncate(clean.trim_end_matches(['.', ' ']).len());
+    if clean.is_empty() {
+        return "_".to_string();
+    }
+    let stem = clean
+        .split('.')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_uppercase();
+    let reserved = matches!(stem.as_str(), "CON" | "PRN" | "AUX" | "NUL")
+        || (stem.len() == 4
+            && (stem.starts_with("COM") || stem.starts_with("LPT"))
+            && stem.as_bytes()[3].is_ascii_digit());
+    if reserved {
+        clean.insert(0, '_');
+    }
+    clean
+}
+
+/// Expands `{monitor}`, `{timestamp}`, `{seq}` and `{title}` in an output path. Substituted
+/// values are sanitized; the rest of the template is used as is. `{{` and `}}` stand for literal
+/// braces. Relative results are resolved against the configured output directory, if any.
+pub(crate) fn expand_template(
+    template: &str,
+    fields: &TemplateFields,
+) -> Result<PathBuf, CaptureError> {
+    let invalid = |message: String| CaptureError::new(ErrorCode::InvalidArgument, message);
+    let mut path = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find(['{', '}']) {
+        path.push_str(&rest[..start]);
+        let brace = rest.as_bytes()[start];
+        rest = &rest[start + 1..];
+        if rest.as_bytes().first() == Some(&brace) {
+            path.push(brace as char);
+            rest = &rest[1..];
+            continue;
+        }
+        if brace == b'}' {
+            return Err(invalid(format!(
+                "Unmatched '}}' in output path template {:?}; write '}}}}' for a literal brace",
+                template
+            )));
+        }
+        let Some(end) = rest.find('}') else {
+            return Err(invalid(format!(
+                "Unterminated placeholder in output path template {:?}",
+                template
+            )));
+        };
+        match &rest[..end] {
+            "monitor" => path.push_str(&sanitize(fields.monitor)),
+            "title" => path.push_str(&sanitize(fields.title)),
+            "timestamp" => path.push_str(&timestamp(now_ms())),
+            "seq" => {
+                let _ = write!(path, "{:04}", SEQUENCE.fetch_add(1, Ordering::Relaxed));
+            }
+            name => {
+                return Err(invalid(format!(
+                    "Unknown placeholder {{{}}} in output path template; \
+                     expected monitor, timestamp, seq or title",
+                    name
+                )));
+            }
+        }
+        rest = &rest[end + 1..];
+    }
+    path.push_str(rest);
+    Ok(config::output_path(PathBuf::from(path)))
+}
+
+/// `path` with `-n` appended to its file stem.
+fn numbered(path: &Path, n: u32) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, n, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, n),
+    };
+    path.with_file_name(name)
+}
+
+/// The names to try for `path` under `policy`, in order.
+fn candidates(path: &Path, policy: CollisionPolicy) -> impl Iterator<Item = PathBuf> + '_ {
+    let increments = match policy {
+        CollisionPolicy::Increment => MAX_INCREMENT,
+        _ => 0,
+    };
+    std::iter::once(path.to_path_buf()).chain((1..=increments).map(|n| numbered(path, n)))
+}
+
+fn collision_error(path: &Path, policy: CollisionPolicy) -> CaptureError {
+    let message = match policy {
+        CollisionPolicy::Increment => format!(
+            "{} and its numbered variants up to -{} already exist",
+            path.display(),
+            MAX_INCREMENT
+        ),
+        _ => format!("{} already exists", path.display()),
+    };
+    CaptureError::new(ErrorCode::AlreadyExists, message)
+}
+
+/// Moves a finished temporary file to `path` unless something already exists there.
+fn rename_no_clobber(temp: &Path, path: &Path) -> io::Result<()> {
+    // A hard link fails atomically if the target exists; not every filesystem has them.
+    match fs::hard_link(temp, path) {
+        Ok(()) => {
+            let _ = fs::remove_file(temp);
+            Ok(())
+        }
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Err(e),
+        Err(_) if fs::symlink_metadata(path).is_ok() => {
+            Err(io::Error::from(io::ErrorKind::AlreadyExists))
+        }
+        Err(_) => fs::rename(temp, path),
+    }
+}
+
+/// Distinguishes temporary files of concurrent saves within this process.
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Creates a temporary file next to `path` that nothing else is using.
+fn create_temp(path: &Path) -> io::Result<(PathBuf, File)> {
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    loop {
+        // A leading dot keeps the partial file out of most directory watchers' globs.
+        let temp = dir.join(format!(
+            ".{}.{}-{}.tmp",
+            name,
+            process::id(),
+            TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        match OpenOptions::new().write(true).create_new(true).open(&temp) {
+            Ok(file) => return Ok((temp, file)),
+            // Left over from a crashed process with a recycled pid.
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Makes a completed rename durable by syncing the directory entry.
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => File::open(dir)?.sync_all(),
+        _ => File::open(".")?.sync_all(),
+    }
+}
+
+// Windows has no directory handles to sync; the rename itself is journaled.
+#[cfg(not(unix))]
+fn sync_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+fn write_into(
+    file: File,
+    options: &EncodeOptions,
+    write: impl FnOnce(&mut dyn Write) -> Result<(), CaptureError>,
+) -> Result<(), CaptureError> {
+    let mut writer = BufWriter::new(file);
+    write(&mut writer)?;
+    let file = writer.into_inner().map_err(|e| e.into_error())?;
+    if options.fsync != 0 {
+        file.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Writes whatever `write` produces to `path`, handling an existing file there according to
+/// `options.collision`, and returns the path actually written.
+/// With `options.atomic_write` set the file only appears once it is complete; on failure nothing
+/// is left behind either way.
+pub(crate) fn write_file(
+    path: &Path,
+    options: &EncodeOptions,
+    write: impl FnOnce(&mut dyn Write) -> Result<(), CaptureError>,
+) -> Result<PathBuf, CaptureError> {
+    let policy = options.collision_policy()?;
+    let display = path.display();
+    if options.atomic_write == 0 {
+        let (target, file) = create_target(path, policy)?;
+        return match write_into(file, options, write) {
+            Ok(()) => Ok(target),
+            Err(e) => {
+                let _ = fs::remove_file(&target);
+                Err(e.context(format!("Error saving {}", target.display())))
+            }
+        };
+    }
+
+    let (temp, file) = create_temp(path).map_err(|e| {
+        CaptureError::from(e).context(format!("Error creating a temporary file for {}", display))
+    })?;
+    let result = write_into(file, options, write)
+        .and_then(|()| place(&temp, path, policy))
+        .and_then(|target| match options.fsync {
+            0 => Ok(target),
+            _ => Ok(sync_dir(&target).map(|()| target)?),
+        });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result.map_err(|e| e.context(format!("Error saving {}", display)))
+}
+
+/// Creates the output file for a non-atomic write.
+pub(crate) fn create_target(
+    path: &Path,
+    policy: CollisionPolicy,
+) -> Result<(PathBuf, File), CaptureError> {
+    let creating =
+        |e: io::Error| CaptureError::from(e).context(format!("Error creating {}", path.display()));
+    if policy == CollisionPolicy::Overwrite {
+        return Ok((path.to_path_buf(), File::create(path).map_err(creating)?));
+    }
+    for candidate in candidates(path, policy) {
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&candidate)
+        {
+            Ok(file) => return Ok((candidate, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(creating(e)),
+        }
+    }
+    Err(collision_error(path, policy))
+}
+
+/// Moves a finished temporary file to its final name.
+fn place(temp: &Path, path: &Path, policy: CollisionPolicy) -> Result<PathBuf, CaptureError> {
+    if policy == CollisionPolicy::Overwrite {
+        fs::rename(temp, path)?;
+        return Ok(path.to_path_buf());
+    }
+    for candidate in candidates(path, policy) {
+        match rename_no_clobber(temp, &candidate) {
+            Ok(()) => return Ok(candidate),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Err(collision_error(path, policy))
+}
@@ -0,0 +1,274 @@
+// capture-ffi/src/overlay.rs
+//! Borderless, always-on-top rectangles drawn through the platform's window system.
+//! xcap only reads the screen, so anything the library needs to show on it goes through here.
+
+use crate::{CaptureError, CaptureRect, ErrorCode};
+
+/// Whether this platform and build have an overlay implementation.
+pub(crate) const SUPPORTED: bool = cfg!(all(target_os = "linux", feature = "native"));
+
+/// A solid color as 0xRRGGBB.
+pub(crate) type Rgb = u32;
+
+/// A solid-colored overlay rectangle in global screen coordinates.
+/// The overlay is removed from the screen when dropped.
+pub(crate) struct Overlay {
+    inner: platform::Overlay,
+}
+
+impl Overlay {
+    /// Creates and shows an overlay covering the given rectangle.
+    pub(crate) fn new(
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        color: Rgb,
+    ) -> Result<Self, CaptureError> {
+        if width == 0 || height == 0 {
+            return Err(CaptureError::new(
+                ErrorCode::InvalidArgument,
+                "Overlay size must be non-zero",
+            ));
+        }
+        Ok(Overlay {
+            inner: platform::Overlay::new(x, y, width, height, color)?,
+        })
+    }
+
+    /// Repaints the whole overlay with a new color.
+    /// Returns once the window system has processed the change.
+    pub(crate) fn set_color(&mut self, color: Rgb) -> Result<(), CaptureError> {
+        self.inner.set_color(color)
+    }
+
+    /// Repaints the overlay with its background color and fills `rects`, given relative to the
+    /// overlay's top-left corner, with `color`.
+    /// Returns once the window system has processed the change.
+    pub(crate) fn draw_rects(
+        &mut self,
+        rects: &[CaptureRect],
+        color: Rgb,
+    ) -> Result<(), CaptureError> {
+        self.inner.draw_rects(rects, color)
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "native"))]
+mod platform {
+    use super::Rgb;
+    use crate::{CaptureError, CaptureRect, ErrorCode};
+    use std::collections::HashMap;
+    use xcb::{Connection, x};
+
+    fn backend_error(context: &str, err: impl std::fmt::Display) -> CaptureError {
+        CaptureError::new(ErrorCode::Backend, format!("{}: {}", context, err))
+    }
+
+    pub(crate) struct Overlay {
+        conn: Connection,
+        window: x::Window,
+        colormap: x::Colormap,
+        /// Colormap entries allocated so far, by color. Callers switch between a few colors, so
+        /// each is allocated once and all are freed with the overlay.
+        pixels: HashMap<Rgb, u32>,
+        width: u16,
+        height: u16,
+    }
+
+    impl Overlay {
+        pub(crate) fn new(
+            x: i32,
+            y: i32,
+            width: u32,
+            height: u32,
+            color: Rgb,
+        ) -> Result<Self, CaptureError> {
+            let (conn, screen_num) = Connection::connect(None)
+                .map_err(|e| backend_error("Failed to connect to the X server", e))?;
+            let screen = conn
+                .get_setup()
+                .roots()
+                .nth(screen_num as usize)
+                .ok_or_else(|| {
+                    CaptureError::new(ErrorCode::Backend, "X server reported no screens")
+                })?
+                .to_owned();
+
+            let width = u16::try_from(width).unwrap_or(u16::MAX);
+            let height = u16::try_from(height).unwrap_or(u16::MAX);
+            let window: x::Window = conn.generate_id();
+            let mut overlay = Overlay {
+                conn,
+                window,
+                colormap: screen.default_colormap(),
+                pixels: HashMap::new(),
+                width,
+                height,
+            };
+            let pixel = overlay.pixel(color)?;
+
+            // Override-redirect keeps the window manager from decorating, moving or
+            // focusing the overlay, and keeps it above normal windows.
+            overlay
+                .conn
+                .send_and_check_request(&x::CreateWindow {
+                    depth: x::COPY_FROM_PARENT as u8,
+                    wid: window,
+                    parent: screen.root(),
+                    x: x.clamp(i16::MIN as i32, i16::MAX as i32) as i16,
+                    y: y.clamp(i16::MIN as i32, i16::MAX as i32) as i16,
+                    width,
+                    height,
+                    border_width: 0,
+                    class: x::WindowClass::InputOutput,
+                    visual: screen.root_visual(),
+                    value_list: &[x::Cw::BackPixel(pixel), x::Cw::OverrideRedirect(true)],
+                })
+                .map_err(|e| backend_error("Failed to create overlay window", e))?;
+            overlay.conn.send_request(&x::MapWindow { window });
+            overlay.sync()?;
+
+            Ok(overlay)
+        }
+
+        fn pixel(&mut self, color: Rgb) -> Result<u32, CaptureError> {
+            if let Some(&pixel) = self.pixels.get(&color) {
+                return Ok(pixel);
+            }
+            let pixel = self.alloc_color(color)?;
+            self.pixels.insert(color, pixel);
+            Ok(pixel)
+        }
+
+        fn alloc_color(&self, color: Rgb) -> Result<u32, CaptureError> {
+            // Scale 8-bit channels to the 16-bit range X expects.
+            let channel = |shift: u32| (((color >> shift) & 0xff) as u16) * 257;
+            let cookie = self.conn.send_request(&x::AllocColor {
+                cmap: self.colormap,
+                red: channel(16),
+                green: channel(8),
+                blue: channel(0),
+            });
+            self.conn
+                .wait_for_reply(cookie)
+                .map(|reply| reply.pixel())
+                .map_err(|e| backend_error("Failed to allocate overlay color", e))
+        }
+
+        pub(crate) fn set_color(&mut self, color: Rgb) -> Result<(), CaptureError> {
+            let pixel = self.pixel(color)?;
+            self.conn.send_request(&x::ChangeWindowAttributes {
+                window: self.window,
+                value_list: &[x::Cw::BackPixel(pixel)],
+            });
+            self.conn.send_request(&x::ClearArea {
+                exposures: false,
+                window: self.window,
+                x: 0,
+                y: 0,
+                width: self.width,
+                height: self.height,
+            });
+            self.sync()
+        }
+
+        pub(crate) fn draw_rects(
+            &mut self,
+            rects: &[CaptureRect],
+            color: Rgb,
+        ) -> Result<(), CaptureError> {
+            let pixel = self.pixel(color)?;
+            let rectangles: Vec<x::Rectangle> = rects
+                .iter()
+                .map(|r| x::Rectangle {
+                    x: r.x.clamp(i16::MIN as i32, i16::MAX as i32) as i16,
+                    y: r.y.clamp(i16::MIN as i32, i16::MAX as i32) as i16,
+                    width: u16::try_from(r.width).unwrap_or(u16::MAX),
+                    height: u16::try_from(r.height).unwrap_or(u16::MAX),
+                })
+                .collect();
+            let gc: x::Gcontext = self.conn.generate_id();
+            let drawable = x::Drawable::Window(self.window);
+            self.conn.send_request(&x::ClearArea {
+                exposures: false,
+                window: self.window,
+                x: 0,
+                y: 0,
+                width: self.width,
+                height: self.height,
+            });
+            self.conn.send_request(&x::CreateGc {
+                cid: gc,
+                drawable,
+                value_list: &[x::Gc::Foreground(pixel)],
+            });
+            self.conn.send_request(&x::PolyFillRectangle {
+                drawable,
+                gc,
+                rectangles: &rectangles,
+            });
+            self.conn.send_request(&x::FreeGc { gc });
+            self.sync()
+        }
+
+        fn sync(&self) -> Result<(), CaptureError> {
+            // A round trip guarantees every earlier request has been handled.
+            let cookie = self.conn.send_request(&x::GetInputFocus {});
+            self.conn
+                .wait_for_reply(cookie)
+                .map(|_| ())
+                .map_err(|e| backend_error("X server round trip failed", e))
+        }
+    }
+
+    impl Drop for Overlay {
+        fn drop(&mut self) {
+            self.conn.send_request(&x::DestroyWindow {
+                window: self.window,
+            });
+            let pixels: Vec<u32> = self.pixels.values().copied().collect();
+            self.conn.send_request(&x::FreeColors {
+                cmap: self.colormap,
+                plane_mask: 0,
+                pixels: &pixels,
+            });
+            let _ = self.conn.flush();
+        }
+    }
+}
+
+#[cfg(not(all(target_os = "linux", feature = "native")))]
+mod platform {
+    use super::Rgb;
+    use crate::{CaptureError, CaptureRect, ErrorCode};
+
+    pub(crate) struct Overlay;
+
+    impl Overlay {
+        pub(crate) fn new(
+            _x: i32,
+            _y: i32,
+            _width: u32,
+            _height: u32,
+            _color: Rgb,
+        ) -> Result<Self, CaptureError> {
+            Err(CaptureError::new(
+                ErrorCode::Unsupported,
+                "Native overlays are not supported on this platform yet",
+            ))
+        }
+
+        pub(crate) fn set_color(&mut self, _color: Rgb) -> Result<(), CaptureError> {
+            Ok(())
+        }
+
+        pub(crate) fn draw_rects(
+            &mut self,
+            _rects: &[CaptureRect],
+            _color: Rgb,
+        ) -> Result<(), CaptureError> {
+            Ok(())
+        }
+    }
+}
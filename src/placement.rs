@@ -0,0 +1,235 @@
+// capture-ffi/src/placement.rs
+//! Moving and resizing windows to an exact frame for the duration of a capture, so the same
+//! window produces pixel-identical screenshots on every run and machine.
+
+use crate::{CaptureError, CaptureRect, ErrorCode};
+use std::time::Duration;
+
+/// Whether this platform and build can move and resize other applications' windows.
+pub(crate) const SUPPORTED: bool = cfg!(all(target_os = "linux", feature = "native"));
+
+/// Extra time for the application to repaint at its new size before it is captured.
+const REPAINT_DELAY: Duration = Duration::from_millis(150);
+
+/// A window held at a requested frame. Its original frame is restored when dropped.
+pub(crate) struct Placement {
+    inner: platform::Placement,
+}
+
+impl Placement {
+    /// Moves and resizes the window with the given platform ID so its content area covers
+    /// `frame` in global screen coordinates, and returns once the window is there and has had a
+    /// moment to repaint. Fails if the window manager won't give the window that exact size.
+    pub(crate) fn apply(window_id: u32, frame: CaptureRect) -> Result<Self, CaptureError> {
+        if frame.width == 0 || frame.height == 0 {
+            return Err(CaptureError::new(
+                ErrorCode::InvalidArgument,
+                "Window frame size must be non-zero",
+            ));
+        }
+        // Wrap first so a window left half-moved by a failure is still put back.
+        let mut placement = Placement {
+            inner: platform::Placement::new(window_id)?,
+        };
+        placement.inner.move_to(frame)?;
+        std::thread::sleep(REPAINT_DELAY);
+        Ok(placement)
+    }
+}
+
+impl Drop for Placement {
+    fn drop(&mut self) {
+        self.inner.restore();
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "native"))]
+mod platform {
+    use crate::{CaptureError, CaptureRect, ErrorCode};
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+    use xcb::{Connection, XidNew, x};
+
+    /// How long the window manager gets to apply a new geometry before the capture fails.
+    const APPLY_TIMEOUT: Duration = Duration::from_secs(2);
+    /// How often the window's geometry is checked while waiting.
+    const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+    fn backend_error(context: &str, err: impl std::fmt::Display) -> CaptureError {
+        CaptureError::new(ErrorCode::Backend, format!("{}: {}", context, err))
+    }
+
+    pub(crate) struct Placement {
+        conn: Connection,
+        window: x::Window,
+        root: x::Window,
+        original: CaptureRect,
+        /// Where the window manager puts the content area relative to the position requested
+        /// for it, usually the size of the title bar and left border.
+        offset: (i32, i32),
+    }
+
+    impl Placement {
+        pub(crate) fn new(window_id: u32) -> Result<Self, CaptureError> {
+            let (conn, screen_num) = Connection::connect(None)
+                .map_err(|e| backend_error("Failed to connect to the X server", e))?;
+            let root = conn
+                .get_setup()
+                .roots()
+                .nth(screen_num as usize)
+                .ok_or_else(|| {
+                    CaptureError::new(ErrorCode::Backend, "X server reported no screens")
+                })?
+                .root();
+            // SAFETY: the ID is only used in requests, which the server validates.
+            let window = unsafe { x::Window::new(window_id) };
+            let mut placement = Placement {
+                conn,
+                window,
+                root,
+                original: CaptureRect {
+                    x: 0,
+                    y: 0,
+                    width: 0,
+                    height: 0,
+                },
+                offset: (0, 0),
+            };
+            placement.original = placement.geometry().map_err(|_| {
+                CaptureError::new(
+                    ErrorCode::InvalidArgument,
+                    format!("No window with ID {}", window_id),
+                )
+            })?;
+            Ok(placement)
+        }
+
+        /// The window's content area in root coordinates.
+        fn geometry(&self) -> Result<CaptureRect, CaptureError> {
+            let geometry = self.conn.send_request(&x::GetGeometry {
+                drawable: x::Drawable::Window(self.window),
+            });
+            let origin = self.conn.send_request(&x::TranslateCoordinates {
+                src_window: self.window,
+                dst_window: self.root,
+                src_x: 0,
+                src_y: 0,
+            });
+            let geometry = self
+                .conn
+                .wait_for_reply(geometry)
+                .map_err(|e| backend_error("Failed to query window geometry", e))?;
+            let origin = self
+                .conn
+                .wait_for_reply(origin)
+                .map_err(|e| backend_error("Failed to query window position", e))?;
+            Ok(CaptureRect {
+                x: origin.dst_x() as i32,
+                y: origin.dst_y() as i32,
+                width: geometry.width() as u32,
+                height: geometry.height() as u32,
+            })
+        }
+
+        fn configure(&self, x: i32, y: i32, width: u32, height: u32) {
+            self.conn.send_request(&x::ConfigureWindow {
+                window: self.window,
+                value_list: &[
+                    x::ConfigWindow::X(x),
+                    x::ConfigWindow::Y(y),
+                    x::ConfigWindow::Width(width),
+                    x::ConfigWindow::Height(height),
+                ],
+            });
+            let _ = self.conn.flush();
+        }
+
+        /// Polls the window's geometry until `done` accepts it or the timeout runs out.
+        fn wait_for(
+            &self,
+            done: impl Fn(&CaptureRect) -> bool,
+        ) -> Result<CaptureRect, CaptureError> {
+            let deadline = Instant::now() + APPLY_TIMEOUT;
+            loop {
+                let current = self.geometry()?;
+                if done(&current) || Instant::now() >= deadline {
+                    return Ok(current);
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+
+        pub(crate) fn move_to(&mut self, frame: CaptureRect) -> Result<(), CaptureError> {
+            let size = |r: &CaptureRect| r.width == frame.width && r.height == frame.height;
+            self.configure(frame.x, frame.y, frame.width, frame.height);
+            let original = (self.original.x, self.original.y);
+            let actual = self.wait_for(|r| {
+                size(r) && ((r.x, r.y) == (frame.x, frame.y) || (r.x, r.y) != original)
+            })?;
+
+            // Window managers position the frame, not the content, at the requested point;
+            // ask again with their offset taken out.
+            self.offset = (actual.x - frame.x, actual.y - frame.y);
+            if self.offset != (0, 0) {
+                self.configure(
+                    frame.x - self.offset.0,
+                    frame.y - self.offset.1,
+                    frame.width,
+                    frame.height,
+                );
+            }
+            let actual = self.wait_for(|r| size(r) && (r.x, r.y) == (frame.x, frame.y))?;
+            if !size(&actual) || (actual.x, actual.y) != (frame.x, frame.y) {
+                return Err(CaptureError::new(
+                    ErrorCode::Failed,
+                    format!(
+                        "Window manager placed the window at {}x{}+{}+{} instead of {}x{}+{}+{}",
+                        actual.width,
+                        actual.height,
+                        actual.x,
+                        actual.y,
+                        frame.width,
+                        frame.height,
+                        frame.x,
+                        frame.y
+                    ),
+                ));
+            }
+            Ok(())
+        }
+
+        pub(crate) fn restore(&mut self) {
+            let original = self.original;
+            self.configure(
+                original.x - self.offset.0,
+                original.y - self.offset.1,
+                original.width,
+                original.height,
+            );
+        }
+    }
+}
+
+#[cfg(not(all(target_os = "linux", feature = "native")))]
+mod platform {
+    use crate::{CaptureError, CaptureRect, ErrorCode};
+
+    pub(crate) struct Placement;
+
+    impl Placement {
+        pub(crate) fn new(_window_id: u32) -> Result<Self, CaptureError> {
+            Err(CaptureError::new(
+                ErrorCode::Unsupported,
+                "Moving and resizing windows is not supported on this platform yet",
+            ))
+        }
+
+        pub(crate) fn move_to(&mut self, _frame: CaptureRect) -> Result<(), CaptureError> {
+            Ok(())
+        }
+
+        pub(crate) fn restore(&mut self) {}
+    }
+}
@@ -0,0 +1,199 @@
+// capture-ffi/src/poll.rs
+//! Frames of streams, region watches and composite recordings started without a callback, kept
+//! for the caller to collect with capture_stream_poll_frame() from a thread of its choosing.
+
+use crate::{
+    CaptureError, CapturedImage, ErrorCode, UserData, composite,
+    handle::{self, CaptureHandle, HandleType},
+    set_last_error, stream,
+    stream::FrameCallback,
+    usage::{self, Usage},
+    watch,
+};
+use image::RgbaImage;
+use libc::{c_uint, c_void};
+use std::{
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+#[derive(Default)]
+struct Slot {
+    frame: Option<RgbaImage>,
+    closed: bool,
+}
+
+/// Holds the latest frame nobody has polled yet; a newer frame replaces it, so a slow poller
+/// skips frames instead of piling them up.
+#[derive(Default)]
+pub(crate) struct Mailbox {
+    slot: Mutex<Slot>,
+    ready: Condvar,
+}
+
+impl Mailbox {
+    /// Counts the waiting frame against `usage` until it is polled or replaced.
+    fn put(&self, image: RgbaImage, usage: &Usage) {
+        usage.hold(image.as_raw().len());
+        let replaced = self
+            .slot
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .frame
+            .replace(image);
+        if let Some(replaced) = replaced {
+            usage.release(replaced.as_raw().len());
+        }
+        self.ready.notify_all();
+    }
+
+    /// Wakes pollers once no more frames will come.
+    fn close(&self) {
+        self.slot.lock().unwrap_or_else(|e| e.into_inner()).closed = true;
+        self.ready.notify_all();
+    }
+
+    fn take(&self, timeout: Duration, usage: &Usage) -> Result<RgbaImage, CaptureError> {
+        let deadline = Instant::now() + timeout;
+        let mut slot = self.slot.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            if let Some(frame) = slot.frame.take() {
+                usage.release(frame.as_raw().len());
+                return Ok(frame);
+            }
+            if slot.closed {
+                return Err(CaptureError::new(
+                    ErrorCode::Cancelled,
+                    "The capture stopped and will deliver no more frames",
+                ));
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(CaptureError::new(
+                    ErrorCode::Timeout,
+                    format!("No new frame within {} ms", timeout.as_millis()),
+                ));
+            }
+            slot = self
+                .ready
+                .wait_timeout(slot, deadline - now)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Target {
+    /// The caller's callback, run on the capture's thread.
+    Callback(unsafe extern "C" fn(CapturedImage, *mut c_void), UserData),
+    /// Kept for capture_stream_poll_frame().
+    Mailbox(Arc<Mailbox>),
+}
+
+/// Where a background capture delivers its frames, and what they cost it.
+#[derive(Clone)]
+pub(crate) struct FrameSink {
+    target: Target,
+    usage: Arc<Usage>,
+}
+
+impl FrameSink {
+    /// Delivers to `callback`, or to a new mailbox if it is NULL.
+    pub(crate) fn new(callback: FrameCallback, user_data: UserData) -> Self {
+        let target = match callback {
+            Some(callback) => Target::Callback(callback, user_data),
+            None => Target::Mailbox(Arc::default()),
+        };
+        FrameSink {
+            target,
+            usage: Arc::default(),
+        }
+    }
+
+    pub(crate) fn usage(&self) -> &Arc<Usage> {
+        &self.usage
+    }
+
+    /// Attributes the calling thread's conversion work to this capture until dropped.
+    pub(crate) fn enter(&self) -> usage::Scope {
+        usage::enter(&self.usage)
+    }
+
+    /// The mailbox to poll, if frames aren't going to a callback.
+    fn mailbox(&self) -> Result<&Mailbox, CaptureError> {
+        match &self.target {
+            Target::Mailbox(mailbox) => Ok(mailbox),
+            Target::Callback(..) => Err(CaptureError::new(
+                ErrorCode::InvalidArgument,
+                "Frames go to the callback this was started with; start it with a NULL \
+                 callback to poll them",
+            )),
+        }
+    }
+
+    pub(crate) fn deliver(&self, image: RgbaImage) {
+        self.usage.delivered();
+        match &self.target {
+            Target::Callback(callback, user_data) => {
+                let frame =
+                    usage::converting(|| CapturedImage::from_rgba_owned(image, &self.usage));
+                // SAFETY: the caller promised the callback stays valid while the capture runs.
+                unsafe { callback(frame, user_data.0) }
+            }
+            Target::Mailbox(mailbox) => mailbox.put(image, &self.usage),
+        }
+    }
+
+    /// Tells pollers the capture has stopped.
+    pub(crate) fn close(&self) {
+        if let Target::Mailbox(mailbox) = &self.target {
+            mailbox.close();
+        }
+    }
+}
+
+/// The frame sink of a stream, region watch or composite recording.
+pub(crate) fn sink(handle: CaptureHandle) -> Result<FrameSink, CaptureError> {
+    match handle::handle_type(handle) {
+        Some(HandleType::Stream) => stream::frame_sink(handle),
+        Some(HandleType::RegionWatch) => watch::frame_sink(handle),
+        Some(HandleType::CompositeRecording) => composite::frame_sink(handle),
+        _ => Err(CaptureError::new(
+            ErrorCode::InvalidHandle,
+            format!(
+                "{:#x} is not a stream, region watch or composite recording handle",
+                handle
+            ),
+        )),
+    }
+}
+
+/// Waits up to `timeout_ms` milliseconds (0 to not wait) for the next frame of a stream, region
+/// watch or composite recording that was started with a NULL callback, and returns it; the frame
+/// callback APIs deliver on library threads, this lets single-threaded hosts drive captures from
+/// their own loop instead. Only the latest frame is kept between polls, so frames that arrive
+/// faster than they are polled are skipped.
+/// The caller MUST call capture_free_image() on the returned struct to free the data buffer.
+/// Returns a struct with NULL data pointer and sets CAPTURE_ERROR_TIMEOUT if no frame arrived in
+/// time, CAPTURE_ERROR_CANCELLED once the capture has stopped, or another error code if `handle`
+/// can't be polled.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_stream_poll_frame(
+    handle: CaptureHandle,
+    timeout_ms: c_uint,
+) -> CapturedImage {
+    let result = sink(handle).and_then(|sink| {
+        let image = sink
+            .mailbox()?
+            .take(Duration::from_millis(timeout_ms as u64), &sink.usage)?;
+        Ok(CapturedImage::from_rgba_owned(image, &sink.usage))
+    });
+    match result {
+        Ok(image) => image,
+        Err(err) => {
+            set_last_error(err);
+            CapturedImage::empty()
+        }
+    }
+}
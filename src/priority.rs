@@ -0,0 +1,49 @@
+// capture-ffi/src/priority.rs
+//! Lets interactive one-shot captures go ahead of background streams.
+//! Backends serialize captures internally, so a stream capturing at full rate can make a
+//! screenshot wait behind several of its frames. Streams check in here before every frame and
+//! skip their turn while an interactive capture is in flight.
+
+use std::{
+    sync::{Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Number of interactive captures currently running.
+static PENDING: Mutex<usize> = Mutex::new(0);
+static IDLE: Condvar = Condvar::new();
+
+/// Marks an interactive capture as in flight until dropped.
+pub(crate) struct Interactive(());
+
+pub(crate) fn interactive() -> Interactive {
+    *PENDING.lock().unwrap_or_else(|e| e.into_inner()) += 1;
+    Interactive(())
+}
+
+impl Drop for Interactive {
+    fn drop(&mut self) {
+        let mut pending = PENDING.lock().unwrap_or_else(|e| e.into_inner());
+        *pending -= 1;
+        if *pending == 0 {
+            IDLE.notify_all();
+        }
+    }
+}
+
+/// Called by background work before it touches the backend: waits while interactive captures
+/// are running, but no longer than `max_wait` so a stream is delayed by at most about one tick.
+pub(crate) fn yield_to_interactive(max_wait: Duration) {
+    let deadline = Instant::now() + max_wait;
+    let mut pending = PENDING.lock().unwrap_or_else(|e| e.into_inner());
+    while *pending > 0 {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        pending = IDLE
+            .wait_timeout(pending, deadline - now)
+            .unwrap_or_else(|e| e.into_inner())
+            .0;
+    }
+}
@@ -0,0 +1,121 @@
+// capture-ffi/src/processor.rs
+//! Native plugins that see and may modify every captured frame before the library delivers,
+//! encodes or saves it: redaction, watermarks, analytics.
+
+use crate::{CaptureError, ErrorCode, UserData, set_last_error};
+use image::RgbaImage;
+use libc::{c_int, c_uint, c_void, size_t};
+use std::sync::{
+    RwLock,
+    atomic::{AtomicU32, Ordering},
+};
+
+/// A frame handed to a frame processor: RGBA8 pixels the processor may modify in place.
+/// The pixels belong to the library and are only valid during the call; never pass `data` to
+/// capture_free_image().
+#[repr(C)]
+pub struct ProcessorFrame {
+    pub data: *mut u8,
+    pub width: c_uint,
+    pub height: c_uint,
+    /// Number of bytes from the start of one row to the start of the next.
+    pub stride: size_t,
+}
+
+/// Returns 0 to let the frame through, or anything else to drop it; the capture then fails, so
+/// a failing redaction plugin never leaks what it should have hidden.
+pub type FrameProcessor =
+    Option<unsafe extern "C" fn(frame: *mut ProcessorFrame, user_data: *mut c_void) -> c_int>;
+
+struct Registered {
+    id: c_uint,
+    process: unsafe extern "C" fn(*mut ProcessorFrame, *mut c_void) -> c_int,
+    user_data: UserData,
+}
+
+/// In registration order. Frames hold the read lock while processors run, so unregistering
+/// waits for calls in flight.
+static PROCESSORS: RwLock<Vec<Registered>> = RwLock::new(Vec::new());
+static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+/// Runs every registered processor over a captured frame, in registration order.
+pub(crate) fn run(image: &mut RgbaImage) -> Result<(), CaptureError> {
+    let processors = PROCESSORS.read().unwrap_or_else(|e| e.into_inner());
+    let (width, height) = image.dimensions();
+    for processor in processors.iter() {
+        let mut frame = ProcessorFrame {
+            data: image.as_mut_ptr(),
+            width,
+            height,
+            stride: width as size_t * 4,
+        };
+        // SAFETY: the caller promised the processor stays valid until it is unregistered.
+        let status = unsafe { (processor.process)(&mut frame, processor.user_data.0) };
+        if status != 0 {
+            return Err(CaptureError::new(
+                ErrorCode::Failed,
+                format!(
+                    "Frame processor {} rejected the frame with status {}",
+                    processor.id, status
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Registers a function that runs on every captured frame (one-shot captures, stream and
+/// composite frames, region watches, saves and encodes) before the library uses it, and may
+/// modify its pixels in place. Processors run in registration order on the capturing thread,
+/// which may be any thread, and can reject a frame by returning non-zero.
+/// Returns an ID for capture_unregister_frame_processor(), or 0 if `process` is NULL.
+///
+/// # Safety
+/// `process` must stay valid, and safe to call from any thread, until it is unregistered. It
+/// must not call capture_register_frame_processor() or capture_unregister_frame_processor().
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_register_frame_processor(
+    process: FrameProcessor,
+    user_data: *mut c_void,
+) -> c_uint {
+    let Some(process) = process else {
+        set_last_error(CaptureError::new(
+            ErrorCode::InvalidArgument,
+            "Frame processor must not be NULL",
+        ));
+        return 0;
+    };
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    PROCESSORS
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(Registered {
+            id,
+            process,
+            user_data: UserData(user_data),
+        });
+    id
+}
+
+/// Removes a frame processor. Waits for calls to it already in progress, so its user data may
+/// be freed once this returns.
+/// Returns CAPTURE_OK, or CAPTURE_ERROR_INVALID_ARGUMENT if no processor has this ID.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_unregister_frame_processor(id: c_uint) -> c_int {
+    let mut processors = PROCESSORS.write().unwrap_or_else(|e| e.into_inner());
+    match processors.iter().position(|p| p.id == id) {
+        Some(index) => {
+            processors.remove(index);
+            ErrorCode::Ok as c_int
+        }
+        None => {
+            let err = CaptureError::new(
+                ErrorCode::InvalidArgument,
+                format!("No frame processor with ID {}", id),
+            );
+            let code = err.code as c_int;
+            set_last_error(err);
+            code
+        }
+    }
+}
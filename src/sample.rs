@@ -0,0 +1,240 @@
+// capture-ffi/src/sample.rs
+//! Color readings of a screen patch averaged over pixels and time, for display calibration and
+//! ambient-light tools that need more precision than a single 8-bit sRGB pixel gives.
+
+use crate::{
+    CaptureError, CaptureRect, ErrorCode,
+    backend::Monitor,
+    cancel::{self, CancelToken},
+    handle::CaptureHandle,
+    icc::Profile,
+    monitor_at, set_last_error,
+};
+use image::RgbaImage;
+use libc::{c_uint, size_t};
+use std::time::Duration;
+
+/// Reading of a patch averaged over its pixels and over time.
+/// All values are zero if no sample could be taken.
+#[repr(C)]
+#[derive(Default)]
+pub struct PatchSample {
+    /// Number of captures averaged.
+    pub samples: c_uint,
+    /// Number of captured pixels the patch covered in each.
+    pub pixels: c_uint,
+    /// Mean linear-light red, green and blue in sRGB primaries with D65 white, where 1 is the
+    /// display's white. Colors outside the sRGB gamut fall outside 0 to 1.
+    pub r: f64,
+    pub g: f64,
+    pub b: f64,
+    /// Mean relative luminance (CIE Y), 1 for the display's white.
+    pub luminance: f64,
+    /// Standard deviation of the luminance between samples, to tell a steady patch from a
+    /// flickering or animated one.
+    pub luminance_stddev: f64,
+    /// Whether the monitor's ICC profile was applied. Without one, or when the backend's profiles
+    /// can't be matched to the monitor, pixels are taken to be sRGB.
+    pub profile_applied: bool,
+}
+
+/// The part of a frame `rect` covers, with `rect` relative to the monitor's top-left corner in
+/// the units of its reported size; HiDPI backends may capture more pixels than that.
+fn patch_pixels(
+    monitor: &Monitor,
+    image: &RgbaImage,
+    rect: CaptureRect,
+) -> Result<(u32, u32, u32, u32), CaptureError> {
+    let outside = || {
+        CaptureError::new(
+            ErrorCode::InvalidArgument,
+            format!(
+                "Rectangle {:?} is empty or not inside the {}x{} monitor",
+                rect,
+                monitor.width(),
+                monitor.height()
+            ),
+        )
+    };
+    let (x, y) = (u32::try_from(rect.x), u32::try_from(rect.y));
+    let (Ok(x), Ok(y)) = (x, y) else {
+        return Err(outside());
+    };
+    if rect.width == 0
+        || rect.height == 0
+        || x as u64 + rect.width as u64 > monitor.width() as u64
+        || y as u64 + rect.height as u64 > monitor.height() as u64
+    {
+        return Err(outside());
+    }
+    let scale_x = image.width() as f64 / monitor.width().max(1) as f64;
+    let scale_y = image.height() as f64 / monitor.height().max(1) as f64;
+    let left = ((x as f64 * scale_x) as u32).min(image.width() - 1);
+    let top = ((y as f64 * scale_y) as u32).min(image.height() - 1);
+    let width = ((rect.width as f64 * scale_x).round() as u32).clamp(1, image.width() - left);
+    let height = ((rect.height as f64 * scale_y).round() as u32).clamp(1, image.height() - top);
+    Ok((left, top, width, height))
+}
+
+fn sample(
+    index: usize,
+    rect: CaptureRect,
+    samples: c_uint,
+    interval: Duration,
+    cancel: &CancelToken,
+) -> Result<PatchSample, CaptureError> {
+    let monitor = monitor_at(index)?;
+    let profile = platform::monitor_profile(&monitor).and_then(|icc| Profile::parse(&icc));
+    let profile_applied = profile.is_some();
+    let profile = profile.unwrap_or_else(Profile::srgb);
+    let tables = profile.linear_tables();
+
+    let mut readings = Vec::with_capacity(samples.max(1) as usize);
+    let mut pixels = 0;
+    for i in 0..samples.max(1) {
+        if i > 0 && cancel.sleep(interval) {
+            break;
+        }
+        // Frame processors and size limits are skipped: they would change the reading.
+        let image = monitor.capture_image()?;
+        let (left, top, width, height) = patch_pixels(&monitor, &image, rect)?;
+        let mut sum = [0.0; 3];
+        for y in top..top + height {
+            for x in left..left + width {
+                let [r, g, b, _] = image.get_pixel(x, y).0;
+                sum[0] += tables[0][r as usize];
+                sum[1] += tables[1][g as usize];
+                sum[2] += tables[2][b as usize];
+            }
+        }
+        pixels = width * height;
+        // The conversion to sRGB is linear, so it can be applied to the mean.
+        readings.push(profile.to_srgb(sum.map(|channel| channel / pixels as f64)));
+    }
+
+    let count = readings.len() as f64;
+    let luminance = |[r, g, b]: [f64; 3]| 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let mean = |channel: usize| readings.iter().map(|rgb| rgb[channel]).sum::<f64>() / count;
+    let (r, g, b) = (mean(0), mean(1), mean(2));
+    let mean_luminance = luminance([r, g, b]);
+    let variance = readings
+        .iter()
+        .map(|rgb| (luminance(*rgb) - mean_luminance).powi(2))
+        .sum::<f64>()
+        / count;
+    Ok(PatchSample {
+        samples: readings.len() as c_uint,
+        pixels,
+        r,
+        g,
+        b,
+        luminance: mean_luminance,
+        luminance_stddev: variance.sqrt(),
+        profile_applied,
+    })
+}
+
+#[cfg(all(target_os = "linux", feature = "native"))]
+mod platform {
+    use crate::backend::Monitor;
+    use xcb::{Connection, XidNew, randr, x};
+
+    /// The monitor's profile as published by color management daemons on X11 (ICC Profiles in
+    /// X Specification 0.4): the `_ICC_PROFILE` property of its RandR output, which is what xcap
+    /// reports as the monitor's id. The root window's `_ICC_PROFILE` is only used with a single
+    /// monitor, and its numbered `_ICC_PROFILE_<n>` variants not at all: they follow Xinerama
+    /// screen order, which nothing ties to the order of monitors here.
+    pub(crate) fn monitor_profile(monitor: &Monitor) -> Option<Vec<u8>> {
+        let (conn, screen_num) = Connection::connect(None).ok()?;
+        let root = conn.get_setup().roots().nth(screen_num as usize)?.root();
+        let atom = conn
+            .wait_for_reply(conn.send_request(&x::InternAtom {
+                only_if_exists: true,
+                name: b"_ICC_PROFILE",
+            }))
+            .ok()?
+            .atom();
+        if atom == x::ATOM_NONE {
+            return None;
+        }
+
+        // SAFETY: xcap takes monitor ids from the RandR output XIDs.
+        let output: randr::Output = unsafe { XidNew::new(monitor.id()) };
+        let reply = conn.wait_for_reply(conn.send_request(&randr::GetOutputProperty {
+            output,
+            property: atom,
+            r#type: x::ATOM_ANY,
+            long_offset: 0,
+            long_length: u32::MAX / 4,
+            delete: false,
+            pending: false,
+        }));
+        if let Some(reply) = reply.ok().filter(|reply| reply.format() == 8) {
+            return Some(reply.data::<u8>().to_vec());
+        }
+
+        if Monitor::all().ok()?.len() != 1 {
+            return None;
+        }
+        let reply = conn
+            .wait_for_reply(conn.send_request(&x::GetProperty {
+                delete: false,
+                window: root,
+                property: atom,
+                r#type: x::ATOM_ANY,
+                long_offset: 0,
+                long_length: u32::MAX / 4,
+            }))
+            .ok()?;
+        (reply.format() == 8).then(|| reply.value::<u8>().to_vec())
+    }
+}
+
+#[cfg(not(all(target_os = "linux", feature = "native")))]
+mod platform {
+    use crate::backend::Monitor;
+
+    /// Other backends don't expose the display's profile.
+    pub(crate) fn monitor_profile(_monitor: &Monitor) -> Option<Vec<u8>> {
+        None
+    }
+}
+
+/// Measures the color of a patch of the monitor at the specified index, for display calibration
+/// and ambient-light tools: captures it `samples` times (0 means 1), `interval_ms` milliseconds
+/// apart, converts every pixel to linear light with the monitor's ICC profile where the backend
+/// publishes one (X11 color management) and sRGB otherwise, and averages over pixels and
+/// samples, which resolves finer steps than 8 bits on dithered or noisy content.
+/// `rect` is relative to the monitor's top-left corner, in the units of its reported size.
+/// Blocks for about `samples * interval_ms` milliseconds, or until `cancel` (a token from
+/// capture_cancel_token_new(), or 0 for none) is cancelled, which returns the reading of the
+/// samples taken so far and sets CAPTURE_ERROR_CANCELLED.
+/// Returns a struct with `samples == 0` and sets the last error if a capture fails or `rect`
+/// isn't inside the monitor.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_sample_patch(
+    index: size_t,
+    rect: CaptureRect,
+    samples: c_uint,
+    interval_ms: c_uint,
+    cancel: CaptureHandle,
+) -> PatchSample {
+    let result = cancel::resolve(cancel).and_then(|parent| {
+        let cancel = CancelToken::child_of(parent.as_deref());
+        let interval = Duration::from_millis(interval_ms as u64);
+        let sample = sample(index, rect, samples, interval, &cancel)?;
+        if let Err(err) = cancel.check() {
+            set_last_error(err);
+        }
+        Ok(sample)
+    });
+    match result {
+        Ok(sample) => sample,
+        Err(err) => {
+            let err = err.context(format!("Patch sampling failed for monitor {}", index));
+            eprintln!("{}", err);
+            set_last_error(err);
+            PatchSample::default()
+        }
+    }
+}
@@ -0,0 +1,232 @@
+// capture-ffi/src/snap.rs
+//! capture_snap(): a whole screenshot described by one JSON spec, for scripting hosts that
+//! would rather make a single call than drive the lower-level functions.
+
+use crate::{
+    CaptureError, CaptureRect, ErrorCode, compose, config, dpi,
+    encode::{ImageFormat, encode_to},
+    gallery, monitor_at,
+    options::EncodeOptions,
+    output::{self, TemplateFields},
+    set_last_error,
+    stream::{self, CursorMode},
+    target::{CaptureTarget, Source},
+};
+use base64::Engine;
+use libc::{c_char, c_int};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::ffi::{CStr, CString};
+
+/// What to capture: exactly one of the fields.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+struct SnapTarget {
+    monitor_index: Option<usize>,
+    monitor_id: Option<u32>,
+    window_id: Option<u32>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SnapRegion {
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum SnapFormat {
+    Png,
+    Jpeg,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+struct SnapSpec {
+    #[serde(default)]
+    target: SnapTarget,
+    region: Option<SnapRegion>,
+    format: Option<SnapFormat>,
+    quality: Option<c_int>,
+    scale: Option<f64>,
+    cursor: Option<bool>,
+    path: Option<String>,
+}
+
+fn invalid(message: impl Into<String>) -> CaptureError {
+    CaptureError::new(ErrorCode::InvalidArgument, message)
+}
+
+impl SnapTarget {
+    fn resolve(&self) -> Result<CaptureTarget, CaptureError> {
+        match (self.monitor_index, self.monitor_id, self.window_id) {
+            (None, None, None) => Ok(CaptureTarget::monitor(&monitor_at(0)?)),
+            (Some(index), None, None) => Ok(CaptureTarget::monitor(&monitor_at(index)?)),
+            (None, Some(id), None) => Ok(CaptureTarget::monitor_id(id)),
+            (None, None, Some(id)) => Ok(CaptureTarget::window(id)),
+            _ => Err(invalid(
+                "target must have only one of monitorIndex, monitorId and windowId",
+            )),
+        }
+    }
+}
+
+fn snap(spec: &str) -> Result<Value, CaptureError> {
+    let spec: SnapSpec =
+        serde_json::from_str(spec).map_err(|e| invalid(format!("Invalid snap spec: {}", e)))?;
+    // A default from the config is best-effort; only an explicit request can't go unmet.
+    let cursor = spec.cursor.unwrap_or_else(config::cursor);
+    if cursor && spec.cursor.is_some() && stream::backend_cursor_mode() != CursorMode::Os {
+        return Err(CaptureError::new(
+            ErrorCode::Unsupported,
+            "This backend can't include the cursor in captures",
+        ));
+    }
+    let output_scale = match spec.scale {
+        None => 0,
+        Some(scale) if scale > 0.0 && scale * 100.0 <= dpi::MAX_OUTPUT_SCALE as f64 => {
+            (scale * 100.0).round() as _
+        }
+        Some(scale) => {
+            return Err(invalid(format!(
+                "scale must be above 0 and at most {}, got {}",
+                dpi::MAX_OUTPUT_SCALE / 100,
+                scale
+            )));
+        }
+    };
+    let defaults = EncodeOptions::default();
+    let format = match spec.format {
+        Some(SnapFormat::Png) => ImageFormat::Png,
+        Some(SnapFormat::Jpeg) => ImageFormat::Jpeg,
+        None => defaults.image_format()?,
+    };
+    let options = EncodeOptions {
+        format: format as c_int,
+        quality: spec.quality.unwrap_or(defaults.quality),
+        ..defaults
+    };
+    options.jpeg_quality()?;
+
+    let target = spec.target.resolve()?;
+    let source = target.source()?;
+    let mut image = source.capture()?;
+    let bounds = target.bounds()?;
+    let mut logical = (bounds.width, bounds.height);
+    if let Some(region) = &spec.region {
+        let rect = CaptureRect {
+            x: region.x,
+            y: region.y,
+            width: region.width,
+            height: region.height,
+        };
+        // The region covers the same share of the target's logical size as of the frame.
+        let share = |part: u32, logical: u32, whole: u32| {
+            (part as u64 * logical as u64 / whole.max(1) as u64) as u32
+        };
+        logical = (
+            share(rect.width, logical.0, image.width()),
+            share(rect.height, logical.1, image.height()),
+        );
+        image = compose::crop(&image, rect)
+            .filter(|_| rect.width > 0 && rect.height > 0)
+            .ok_or_else(|| invalid("region is empty or outside the target"))?;
+    }
+    image = dpi::normalize(image, logical, output_scale)?;
+
+    let mut result = json!({
+        "ok": true,
+        "width": image.width(),
+        "height": image.height(),
+        "format": match format {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpeg",
+        },
+        "target": match source {
+            Source::Monitor(_) => json!({ "monitorId": target.id }),
+            Source::Window(_) => json!({ "windowId": target.id }),
+        },
+    });
+    match &spec.path {
+        Some(template) => {
+            let (monitor, title) = match &source {
+                Source::Monitor(monitor) => {
+                    (monitor.name().to_string(), monitor.name().to_string())
+                }
+                Source::Window(window) => (
+                    window.current_monitor().name().to_string(),
+                    window.title().to_string(),
+                ),
+            };
+            let path = output::expand_template(
+                template,
+                &TemplateFields {
+                    monitor: &monitor,
+                    title: &title,
+                },
+            )?;
+            let saved = output::write_file(&path, &options, |writer| {
+                encode_to(&image, &options, writer)
+            })?;
+            result["path"] = json!(saved.to_string_lossy());
+        }
+        None => {
+            let mut encoded = Vec::new();
+            encode_to(&image, &options, &mut encoded)?;
+            result["base64"] = json!(base64::engine::general_purpose::STANDARD.encode(&encoded));
+        }
+    }
+    gallery::record_frame(image, target);
+    Ok(result)
+}
+
+/// Takes a screenshot described by a JSON spec in one call and returns a JSON result.
+/// The spec is an object with these optional fields:
+/// - `target`: `{"monitorIndex": n}`, `{"monitorId": id}` or `{"windowId": id}`; defaults to
+///   the first monitor.
+/// - `region`: `{"x", "y", "width", "height"}` to keep, in the target's captured pixels.
+/// - `format`: `"png"` or `"jpeg"`, with `quality` from 1 to 100; defaults to PNG or to what
+///   capture_load_config() set.
+/// - `scale`: output size relative to the target's logical size, as CaptureOptions.output_scale.
+/// - `cursor`: true to include the cursor, which fails with CAPTURE_ERROR_UNSUPPORTED on
+///   backends that can't. Defaults to the loaded config's, which is only applied where the
+///   backend supports it.
+/// - `path`: an output path template as for capture_monitor_save(). Without one, the encoded
+///   image is returned in the result's `base64` field instead.
+///
+/// The result is `{"ok": true, "width", "height", "format", "target", "path" or "base64"}`, or
+/// `{"ok": false, "code", "message"}` on error, which is also set as the last error.
+/// The caller MUST call capture_free_string() on the returned pointer to free the memory.
+/// Returns NULL only if `spec_json` is NULL or not UTF-8.
+///
+/// # Safety
+/// `spec_json` must be NULL or a valid NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_snap(spec_json: *const c_char) -> *mut c_char {
+    if spec_json.is_null() {
+        set_last_error(invalid("Snap spec is NULL"));
+        return std::ptr::null_mut();
+    }
+    let Ok(spec) = unsafe { CStr::from_ptr(spec_json) }.to_str() else {
+        set_last_error(invalid("Snap spec is not valid UTF-8"));
+        return std::ptr::null_mut();
+    };
+    let result = snap(spec).unwrap_or_else(|err| {
+        let err = err.context("Snap failed");
+        let result = json!({
+            "ok": false,
+            "code": err.code as c_int,
+            "message": &err.message,
+        });
+        eprintln!("{}", err);
+        set_last_error(err);
+        result
+    });
+    // Serialized JSON escapes control characters, so it never contains a NUL byte.
+    CString::new(result.to_string())
+        .map(CString::into_raw)
+        .unwrap_or(std::ptr::null_mut())
+}
@@ -0,0 +1,360 @@
+// capture-ffi/src/stream.rs
+use crate::{
+    CaptureError, CapturedImage, ErrorCode, UserData, backend, cancel, capture_background_frame,
+    degrade::{self, Ladder, Step},
+    event::{self, EventType},
+    handle::{CaptureHandle, HandleType, Registry},
+    monitor_at,
+    poll::FrameSink,
+    priority, set_last_error, usage,
+    worker::{Task, Worker},
+};
+use image::imageops;
+use libc::{c_int, c_uint, c_void, size_t};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Frame rate used when neither the caller nor the monitor says otherwise.
+const DEFAULT_FPS: c_uint = 30;
+
+ffi_enum! {
+    /// Layout of the pixels in delivered frames.
+    pub enum PixelFormat {
+        /// 8 bits per channel, R G B A byte order, rows tightly packed.
+        Rgba8 = 0 => CAPTURE_PIXEL_FORMAT_RGBA8,
+    }
+}
+
+ffi_enum! {
+    /// Whether the mouse cursor is part of delivered frames, and who drew it.
+    /// Not every value is produced by every backend; the full set is part of the C API.
+    #[allow(dead_code)]
+    pub enum CursorMode {
+        /// Frames never contain the cursor.
+        Hidden = 0 => CAPTURE_CURSOR_HIDDEN,
+        /// The OS composites the cursor into frames.
+        Os = 1 => CAPTURE_CURSOR_OS,
+        /// This library draws the cursor into frames.
+        Library = 2 => CAPTURE_CURSOR_LIBRARY,
+        /// The backend doesn't tell (e.g. Wayland portals, where it depends on the compositor).
+        Unknown = 3 => CAPTURE_CURSOR_UNKNOWN,
+    }
+}
+
+ffi_enum! {
+    /// How the pixel values of delivered frames should be interpreted.
+    pub enum ColorSpace {
+        /// Values are sRGB encoded.
+        Srgb = 0 => CAPTURE_COLOR_SPACE_SRGB,
+        /// Values are in the display's own color space, as configured in the OS.
+        DisplayNative = 1 => CAPTURE_COLOR_SPACE_DISPLAY_NATIVE,
+    }
+}
+
+/// Parameters a stream actually runs with, as opposed to what was requested.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct StreamInfo {
+    /// Width of delivered frames in pixels.
+    pub width: c_uint,
+    /// Height of delivered frames in pixels.
+    pub height: c_uint,
+    pub pixel_format: PixelFormat,
+    /// Upper bound on delivered frames per second.
+    pub max_fps: c_uint,
+    pub cursor: CursorMode,
+    pub color_space: ColorSpace,
+}
+
+impl StreamInfo {
+    fn empty() -> Self {
+        StreamInfo {
+            width: 0,
+            height: 0,
+            pixel_format: PixelFormat::Rgba8,
+            max_fps: 0,
+            cursor: CursorMode::Unknown,
+            color_space: ColorSpace::Srgb,
+        }
+    }
+}
+
+/// Whether xcap is going through a Wayland portal rather than X11 (same check xcap uses).
+#[cfg(target_os = "linux")]
+fn is_wayland() -> bool {
+    let var = |name| std::env::var(name).unwrap_or_default().to_lowercase();
+    var("XDG_SESSION_TYPE") == "wayland" || var("WAYLAND_DISPLAY").contains("wayland")
+}
+
+pub(crate) fn backend_cursor_mode() -> CursorMode {
+    // Host-supplied frames may or may not show it, e.g. getDisplayMedia()'s `cursor` constraint.
+    if cfg!(feature = "wasm") {
+        return CursorMode::Unknown;
+    }
+    #[cfg(target_os = "linux")]
+    if is_wayland() {
+        return CursorMode::Unknown;
+    }
+    // X11 GetImage, GDI BitBlt and CGDisplayCreateImage all leave the cursor out.
+    CursorMode::Hidden
+}
+
+fn backend_color_space() -> ColorSpace {
+    // CoreGraphics hands back images tagged with the display's profile; the others hand
+    // back framebuffer contents, which desktop compositors treat as sRGB.
+    if cfg!(target_os = "macos") {
+        ColorSpace::DisplayNative
+    } else {
+        ColorSpace::Srgb
+    }
+}
+
+/// Size of one side of a frame scaled to `scale` percent.
+fn scaled(side: c_uint, scale: c_uint) -> c_uint {
+    ((side as u64 * scale as u64 / 100) as c_uint).max(1)
+}
+
+/// Applies a step of the degradation ladder to a stream's parameters and its `scale` in percent
+/// of the backend's `full_size` frames, and describes the change for the event reporting it;
+/// None if it wouldn't change anything.
+fn degrade(
+    step: Step,
+    info: &mut StreamInfo,
+    scale: &mut c_uint,
+    full_size: (c_uint, c_uint),
+) -> Option<String> {
+    match step {
+        Step::ReduceFps(fps) if fps < info.max_fps => {
+            info.max_fps = fps;
+            Some(format!("reduced the frame rate to {} fps", fps))
+        }
+        Step::Downscale(percent) if *scale > 1 => {
+            *scale = scaled(*scale, percent);
+            info.width = scaled(full_size.0, *scale);
+            info.height = scaled(full_size.1, *scale);
+            Some(format!(
+                "scaled frames down to {}x{}",
+                info.width, info.height
+            ))
+        }
+        _ => None,
+    }
+}
+
+pub type FrameCallback = Option<unsafe extern "C" fn(frame: CapturedImage, user_data: *mut c_void)>;
+
+/// A running capture stream, created by capture_stream_start.
+pub(crate) struct CaptureStream {
+    /// Updated by the stream's thread as it degrades.
+    info: Arc<Mutex<StreamInfo>>,
+    frames: FrameSink,
+    worker: Worker,
+}
+
+impl Task for CaptureStream {
+    fn worker(&self) -> &Worker {
+        &self.worker
+    }
+}
+
+static STREAMS: Registry<CaptureStream> = Registry::new(HandleType::Stream);
+
+fn start(
+    index: usize,
+    max_fps: c_uint,
+    on_frame: FrameCallback,
+    user_data: UserData,
+    cancel: CaptureHandle,
+) -> Result<CaptureHandle, CaptureError> {
+    backend::require_threads()?;
+    let cancel = cancel::resolve(cancel)?;
+    let frames = FrameSink::new(on_frame, user_data);
+    let monitor = monitor_at(index)?;
+
+    // The first frame tells us what the backend really delivers (e.g. HiDPI sizes).
+    let first = {
+        let _usage = frames.enter();
+        capture_background_frame(&monitor)
+    }
+    .map_err(|e| e.context(format!("Error capturing image for monitor {}", index)))?;
+
+    // Capturing faster than the display refreshes only yields duplicate frames.
+    let refresh_rate = monitor.frequency().round() as c_uint;
+    let mut fps = if max_fps == 0 { DEFAULT_FPS } else { max_fps };
+    if refresh_rate > 0 {
+        fps = fps.min(refresh_rate);
+    }
+
+    let full_size = first.dimensions();
+    let info = StreamInfo {
+        width: first.width(),
+        height: first.height(),
+        pixel_format: PixelFormat::Rgba8,
+        max_fps: fps,
+        cursor: backend_cursor_mode(),
+        color_space: backend_color_space(),
+    };
+
+    let info = Arc::new(Mutex::new(info));
+    let stream = CaptureStream {
+        info: info.clone(),
+        frames: frames.clone(),
+        // Stopped by capture_stream_stop() or by the caller's token, whichever comes first.
+        worker: Worker::new(cancel.as_deref()),
+    };
+    let mut interval = Duration::from_secs_f64(1.0 / fps as f64);
+    Ok(STREAMS.start(stream, move |handle, stop| {
+        event::emit(EventType::StreamStarted, handle, None);
+        let _usage = frames.enter();
+        let mut frame = Some(first);
+        let mut next_tick = Instant::now();
+        let mut ladder = Ladder::current();
+        // Percent of the backend's frame size delivered, lowered by the ladder.
+        let mut scale: c_uint = 100;
+
+        while !stop.is_cancelled() {
+            let image = match frame.take() {
+                Some(image) => Ok(image),
+                None => {
+                    // Skip (at most) a tick while a one-shot capture is waiting for the backend.
+                    priority::yield_to_interactive(interval);
+                    capture_background_frame(&monitor)
+                }
+            };
+            let image = image.map(|image| match scale {
+                100 => image,
+                scale => usage::converting(|| {
+                    let (width, height) =
+                        (scaled(image.width(), scale), scaled(image.height(), scale));
+                    imageops::resize(&image, width, height, imageops::FilterType::Triangle)
+                }),
+            });
+            match image {
+                Ok(image) => frames.deliver(image),
+                Err(e) => {
+                    let err_msg = format!("Error capturing image for monitor {}: {}", index, e);
+                    event::emit(EventType::StreamError, handle, Some(&err_msg));
+                }
+            }
+
+            // Keep a steady cadence; if capturing is slower than the cap, don't try to catch up.
+            next_tick += interval;
+            let now = Instant::now();
+            let missed = next_tick <= now;
+            if missed {
+                next_tick = now;
+            }
+            if ladder.tick(missed) {
+                let mut degraded = None;
+                let mut info = info.lock().unwrap_or_else(|e| e.into_inner());
+                // Skip steps that wouldn't shed anything, so every degradation counts.
+                while let Some(step) = ladder.next_step() {
+                    if let Some(change) = degrade(step, &mut info, &mut scale, full_size) {
+                        interval = Duration::from_secs_f64(1.0 / info.max_fps as f64);
+                        degraded = Some(format!(
+                            "Missed {} frame deadlines in a row; {}",
+                            degrade::MISSES_PER_STEP,
+                            change
+                        ));
+                        break;
+                    }
+                }
+                // Released first: the event callback may call capture_stream_info().
+                drop(info);
+                if let Some(message) = degraded {
+                    event::emit(EventType::StreamDegraded, handle, Some(&message));
+                }
+            }
+            if !missed {
+                stop.sleep(next_tick - now);
+            }
+        }
+
+        frames.close();
+        event::emit(EventType::StreamStopped, handle, None);
+    }))
+}
+
+/// Starts capturing the monitor at the specified index continuously on a background thread.
+/// `max_fps` caps the frame rate (0 picks a default); the cap actually used, together with the
+/// other negotiated parameters, is available from capture_stream_info().
+/// Every frame is passed to `on_frame` on the stream's thread; the callback owns the frame and
+/// MUST call capture_free_image() on it. With a NULL `on_frame`, frames are instead kept for
+/// capture_stream_poll_frame().
+/// Returns a stream handle, or 0 if the index is invalid or the first capture fails.
+///
+/// # Safety
+/// `on_frame` must stay valid, and safe to call from another thread, until capture_stream_stop()
+/// returns.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_stream_start(
+    index: size_t,
+    max_fps: c_uint,
+    on_frame: FrameCallback,
+    user_data: *mut c_void,
+) -> CaptureHandle {
+    unsafe { capture_stream_start_ex(index, max_fps, on_frame, user_data, 0) }
+}
+
+/// Same as capture_stream_start(), but the stream also stops when `cancel` (a token from
+/// capture_cancel_token_new(), or 0 for none) is cancelled. A cancelled stream delivers no more
+/// frames and reports CAPTURE_EVENT_STREAM_STOPPED; its handle stays valid until
+/// capture_stream_stop() is called on it.
+///
+/// # Safety
+/// Same as capture_stream_start().
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_stream_start_ex(
+    index: size_t,
+    max_fps: c_uint,
+    on_frame: FrameCallback,
+    user_data: *mut c_void,
+    cancel: CaptureHandle,
+) -> CaptureHandle {
+    match start(index, max_fps, on_frame, UserData(user_data), cancel) {
+        Ok(handle) => handle,
+        Err(err) => {
+            let err = err.context("Failed to start stream");
+            eprintln!("{}", err);
+            set_last_error(err);
+            0
+        }
+    }
+}
+
+/// Where a stream delivers its frames.
+pub(crate) fn frame_sink(stream: CaptureHandle) -> Result<FrameSink, CaptureError> {
+    Ok(STREAMS.get(stream)?.frames.clone())
+}
+
+/// Gets the parameters the stream actually runs with.
+/// Returns a zeroed struct and sets CAPTURE_ERROR_INVALID_HANDLE if `stream` isn't a live stream.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_stream_info(stream: CaptureHandle) -> StreamInfo {
+    match STREAMS.get(stream) {
+        Ok(stream) => *stream.info.lock().unwrap_or_else(|e| e.into_inner()),
+        Err(err) => {
+            set_last_error(err);
+            StreamInfo::empty()
+        }
+    }
+}
+
+/// Stops a stream, waits for its thread to deliver the last frame and invalidates the handle.
+/// No frame callbacks run after this returns.
+/// Returns CAPTURE_OK, CAPTURE_ERROR_INVALID_HANDLE if `stream` isn't a live stream, or
+/// CAPTURE_ERROR_INVALID_ARGUMENT, leaving the stream running, if called from the stream's own
+/// callbacks; cancel the stream's token from there instead.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_stream_stop(stream: CaptureHandle) -> c_int {
+    match STREAMS.stop(stream) {
+        Ok(()) => ErrorCode::Ok as c_int,
+        Err(err) => {
+            let code = err.code as c_int;
+            set_last_error(err);
+            code
+        }
+    }
+}
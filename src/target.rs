@@ -0,0 +1,141 @@
+// capture-ffi/src/target.rs
+use crate::{
+    CaptureError, CaptureRect, ErrorCode,
+    backend::{Monitor, Window},
+    capture_background_frame, capture_background_window_frame, capture_frame, capture_window_frame,
+};
+use image::RgbaImage;
+use libc::{c_int, c_uint};
+
+ffi_enum! {
+    /// The kind of thing a CaptureTarget refers to.
+    pub enum TargetKind {
+        Monitor = 0 => CAPTURE_TARGET_MONITOR,
+        Window = 1 => CAPTURE_TARGET_WINDOW,
+    }
+}
+
+/// Identifies what a capture was taken of, independent of enumeration order.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CaptureTarget {
+    /// A TargetKind (CAPTURE_TARGET_*).
+    pub kind: c_int,
+    /// The platform ID of the monitor or window (see capture_monitor_id() and
+    /// capture_window_id()).
+    pub id: c_uint,
+}
+
+impl CaptureTarget {
+    pub(crate) fn monitor(monitor: &Monitor) -> Self {
+        CaptureTarget {
+            kind: TargetKind::Monitor as c_int,
+            id: monitor.id(),
+        }
+    }
+
+    pub(crate) fn monitor_id(id: c_uint) -> Self {
+        CaptureTarget {
+            kind: TargetKind::Monitor as c_int,
+            id,
+        }
+    }
+
+    pub(crate) fn window(id: c_uint) -> Self {
+        CaptureTarget {
+            kind: TargetKind::Window as c_int,
+            id,
+        }
+    }
+
+    fn not_found(&self, what: &str) -> CaptureError {
+        CaptureError::new(
+            ErrorCode::InvalidArgument,
+            format!("No {} with ID {}", what, self.id),
+        )
+    }
+
+    fn kind(&self) -> Result<TargetKind, CaptureError> {
+        TargetKind::from_raw(self.kind).ok_or_else(|| {
+            CaptureError::new(
+                ErrorCode::InvalidArgument,
+                format!("Unknown target kind: {}", self.kind),
+            )
+        })
+    }
+
+    fn find_monitor(&self) -> Result<Monitor, CaptureError> {
+        Monitor::all()
+            .map_err(|e| CaptureError::from(e).context("Error fetching monitors"))?
+            .into_iter()
+            .find(|m| m.id() == self.id)
+            .ok_or_else(|| self.not_found("monitor"))
+    }
+
+    fn find_window(&self) -> Result<Window, CaptureError> {
+        Window::all()
+            .map_err(|e| CaptureError::from(e).context("Error fetching windows"))?
+            .into_iter()
+            .find(|w| w.id() == self.id)
+            .ok_or_else(|| self.not_found("window"))
+    }
+
+    /// Looks up where the target currently is on the desktop.
+    pub(crate) fn bounds(&self) -> Result<CaptureRect, CaptureError> {
+        let (x, y, width, height) = match self.kind()? {
+            TargetKind::Monitor => {
+                let monitor = self.find_monitor()?;
+                (monitor.x(), monitor.y(), monitor.width(), monitor.height())
+            }
+            TargetKind::Window => {
+                let window = self.find_window()?;
+                (window.x(), window.y(), window.width(), window.height())
+            }
+        };
+        Ok(CaptureRect {
+            x,
+            y,
+            width,
+            height,
+        })
+    }
+
+    /// Looks the target up once for repeated captures.
+    pub(crate) fn source(&self) -> Result<Source, CaptureError> {
+        Ok(match self.kind()? {
+            TargetKind::Monitor => Source::Monitor(self.find_monitor()?),
+            TargetKind::Window => Source::Window(self.find_window()?),
+        })
+    }
+
+    /// Captures the target as a one-shot capture.
+    pub(crate) fn capture(&self) -> Result<RgbaImage, CaptureError> {
+        self.source()?
+            .capture()
+            .map_err(|e| e.context("Error capturing target"))
+    }
+}
+
+/// The monitor or window a CaptureTarget refers to.
+pub(crate) enum Source {
+    Monitor(Monitor),
+    Window(Window),
+}
+
+impl Source {
+    /// Captures the source for a one-shot request.
+    pub(crate) fn capture(&self) -> Result<RgbaImage, CaptureError> {
+        match self {
+            Source::Monitor(monitor) => capture_frame(monitor),
+            Source::Window(window) => capture_window_frame(window),
+        }
+    }
+
+    /// Captures the source for a recurring background task, behind one-shot requests.
+    pub(crate) fn capture_background(&self) -> Result<RgbaImage, CaptureError> {
+        match self {
+            Source::Monitor(monitor) => capture_background_frame(monitor),
+            Source::Window(window) => capture_background_window_frame(window),
+        }
+    }
+}
@@ -0,0 +1,176 @@
+// capture-ffi/src/usage.rs
+//! Resource accounting for streams, region watches and composite recordings, so applications
+//! running several at once can decide for themselves which to slow down or stop.
+
+use crate::{handle::CaptureHandle, poll, set_last_error};
+use std::{
+    cell::{Cell, RefCell},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+/// What a background capture has cost so far, as returned by capture_stream_resource_stats().
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct StreamResourceStats {
+    /// Bytes of pixel buffers the capture delivered that haven't been freed yet, plus the frame
+    /// waiting for capture_stream_poll_frame(), if any. Buffers shared with
+    /// capture_image_clone_ref() count once.
+    pub held_bytes: u64,
+    /// Number of the frames counted in `held_bytes`.
+    pub held_frames: u64,
+    /// Frames delivered since the capture started.
+    pub frames_delivered: u64,
+    /// CPU time in microseconds spent on frames after the backend captured them: size limits,
+    /// frame processors, cropping, change detection, compositing and handing them over.
+    pub conversion_cpu_us: u64,
+    /// CPU time in microseconds spent encoding and saving the capture's frames with
+    /// capture_image_encode(), capture_image_save() or capture_delta_encode(), on whichever thread
+    /// called them.
+    pub encode_cpu_us: u64,
+}
+
+impl StreamResourceStats {
+    fn empty() -> Self {
+        StreamResourceStats {
+            held_bytes: 0,
+            held_frames: 0,
+            frames_delivered: 0,
+            conversion_cpu_us: 0,
+            encode_cpu_us: 0,
+        }
+    }
+}
+
+/// Counters shared by a background capture and the buffers it hands out, which can outlive it.
+#[derive(Default)]
+pub(crate) struct Usage {
+    held_bytes: AtomicU64,
+    held_frames: AtomicU64,
+    frames_delivered: AtomicU64,
+    conversion_ns: AtomicU64,
+    encode_ns: AtomicU64,
+}
+
+impl Usage {
+    /// Counts a frame buffer of `bytes` as held until release() is called with the same size.
+    pub(crate) fn hold(&self, bytes: usize) {
+        self.held_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.held_frames.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn release(&self, bytes: usize) {
+        self.held_bytes.fetch_sub(bytes as u64, Ordering::Relaxed);
+        self.held_frames.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn delivered(&self) {
+        self.frames_delivered.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn stats(&self) -> StreamResourceStats {
+        let us = |ns: &AtomicU64| ns.load(Ordering::Relaxed) / 1000;
+        StreamResourceStats {
+            held_bytes: self.held_bytes.load(Ordering::Relaxed),
+            held_frames: self.held_frames.load(Ordering::Relaxed),
+            frames_delivered: self.frames_delivered.load(Ordering::Relaxed),
+            conversion_cpu_us: us(&self.conversion_ns),
+            encode_cpu_us: us(&self.encode_ns),
+        }
+    }
+}
+
+/// CPU time the calling thread has used. Where the OS has no per-thread clock, wall time since
+/// the first call stands in for it.
+#[cfg(unix)]
+fn thread_cpu_time() -> Duration {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: `time` is valid for writing.
+    if unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut time) } != 0 {
+        return Duration::ZERO;
+    }
+    Duration::new(time.tv_sec as u64, time.tv_nsec as u32)
+}
+
+#[cfg(not(unix))]
+fn thread_cpu_time() -> Duration {
+    static START: std::sync::LazyLock<std::time::Instant> =
+        std::sync::LazyLock::new(std::time::Instant::now);
+    START.elapsed()
+}
+
+/// Runs `f` and adds the CPU time it took to `counter`.
+fn measure<T>(counter: &AtomicU64, f: impl FnOnce() -> T) -> T {
+    let start = thread_cpu_time();
+    let result = f();
+    let spent = thread_cpu_time().saturating_sub(start);
+    counter.fetch_add(spent.as_nanos() as u64, Ordering::Relaxed);
+    result
+}
+
+thread_local! {
+    /// The capture the current thread works for, set by enter().
+    static CURRENT: RefCell<Option<Arc<Usage>>> = const { RefCell::new(None) };
+    /// Whether converting() is already measuring, so nested calls aren't counted twice.
+    static CONVERTING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Attributes the calling thread's conversion work to a capture until dropped.
+pub(crate) struct Scope {
+    previous: Option<Arc<Usage>>,
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+pub(crate) fn enter(usage: &Arc<Usage>) -> Scope {
+    let previous = CURRENT.with(|current| current.borrow_mut().replace(usage.clone()));
+    Scope { previous }
+}
+
+/// Runs `f`, counting its CPU time as conversion work of the capture the thread works for, if
+/// any; one-shot captures aren't counted anywhere.
+pub(crate) fn converting<T>(f: impl FnOnce() -> T) -> T {
+    let usage = CURRENT.with(|current| current.borrow().clone());
+    let Some(usage) = usage.filter(|_| !CONVERTING.get()) else {
+        return f();
+    };
+    CONVERTING.set(true);
+    let result = measure(&usage.conversion_ns, f);
+    CONVERTING.set(false);
+    result
+}
+
+/// Runs `f`, counting its CPU time as encoding work of `owner`, the capture the encoded frame
+/// came from, if any.
+pub(crate) fn encoding<T>(owner: Option<Arc<Usage>>, f: impl FnOnce() -> T) -> T {
+    match owner {
+        Some(usage) => measure(&usage.encode_ns, f),
+        None => f(),
+    }
+}
+
+/// Gets what a stream, region watch or composite recording has cost so far. `handle` must still
+/// be running or stopped by cancellation; frames it delivered stay accounted for until freed,
+/// but can't be queried once the handle is stopped.
+/// Returns a zeroed struct and sets CAPTURE_ERROR_INVALID_HANDLE if `handle` isn't one of those.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_stream_resource_stats(handle: CaptureHandle) -> StreamResourceStats {
+    match poll::sink(handle) {
+        Ok(sink) => sink.usage().stats(),
+        Err(err) => {
+            set_last_error(err);
+            StreamResourceStats::empty()
+        }
+    }
+}
@@ -0,0 +1,255 @@
+// capture-ffi/src/view.rs
+use crate::{
+    CaptureError, CaptureRect, CapturedImage, ErrorCode, buffer,
+    handle::{CaptureHandle, HandleType, Registry},
+    set_last_error,
+};
+use image::RgbaImage;
+use libc::{c_int, c_uint, size_t};
+use std::{ptr, sync::Mutex};
+
+const BYTES_PER_PIXEL: usize = 4;
+
+/// A rectangular window into a CapturedImage, returned by capture_image_view().
+/// Until capture_image_view_make_mut() is called, `data` points into the parent image's buffer
+/// and MUST NOT be written to.
+#[repr(C)]
+pub struct CapturedImageView {
+    /// Pointer to the view's top-left pixel.
+    pub data: *const u8,
+    /// Number of bytes from the start of one row to the start of the next.
+    pub stride: size_t,
+    /// Width of the view in pixels.
+    pub width: c_uint,
+    /// Height of the view in pixels.
+    pub height: c_uint,
+    /// Identifies the view in the other capture_image_view_* functions.
+    pub handle: CaptureHandle,
+}
+
+impl CapturedImageView {
+    fn empty() -> Self {
+        CapturedImageView {
+            data: ptr::null(),
+            stride: 0,
+            width: 0,
+            height: 0,
+            handle: 0,
+        }
+    }
+}
+
+enum ViewData {
+    /// Borrowing pixels from a parent buffer we hold a reference to.
+    Shared {
+        parent: usize,
+        offset: usize,
+        stride: usize,
+    },
+    /// A private, tightly packed copy made when the view was made mutable.
+    Owned(Box<[u8]>),
+}
+
+struct ViewState {
+    data: ViewData,
+    width: usize,
+    height: usize,
+}
+
+impl ViewState {
+    fn data_ptr(&mut self) -> *mut u8 {
+        match &mut self.data {
+            ViewData::Shared { parent, offset, .. } => (*parent + *offset) as *mut u8,
+            ViewData::Owned(bytes) => bytes.as_mut_ptr(),
+        }
+    }
+
+    fn stride(&self) -> usize {
+        match &self.data {
+            ViewData::Shared { stride, .. } => *stride,
+            ViewData::Owned(_) => self.width * BYTES_PER_PIXEL,
+        }
+    }
+
+    /// Copies the view's pixels into a tightly packed buffer.
+    fn packed_copy(&mut self) -> Vec<u8> {
+        let row_len = self.width * BYTES_PER_PIXEL;
+        let stride = self.stride();
+        let base = self.data_ptr();
+        let mut packed = Vec::with_capacity(row_len * self.height);
+        for row in 0..self.height {
+            // SAFETY: the rectangle was checked against the parent buffer's length when the view
+            // was created, and the buffer stays alive while the view holds its reference.
+            let row = unsafe { std::slice::from_raw_parts(base.add(row * stride), row_len) };
+            packed.extend_from_slice(row);
+        }
+        packed
+    }
+
+    fn ffi_view(&mut self, handle: CaptureHandle) -> CapturedImageView {
+        CapturedImageView {
+            data: self.data_ptr(),
+            stride: self.stride(),
+            width: self.width as c_uint,
+            height: self.height as c_uint,
+            handle,
+        }
+    }
+}
+
+impl Drop for ViewState {
+    fn drop(&mut self) {
+        if let ViewData::Shared { parent, .. } = self.data {
+            let _ = buffer::release(parent as *const u8);
+        }
+    }
+}
+
+struct ImageView {
+    state: Mutex<ViewState>,
+}
+
+static VIEWS: Registry<ImageView> = Registry::new(HandleType::ImageView);
+
+fn create(image: &CapturedImage, rect: CaptureRect) -> Result<CapturedImageView, CaptureError> {
+    if image.data.is_null() {
+        return Err(CaptureError::new(
+            ErrorCode::InvalidArgument,
+            "Image has no data",
+        ));
+    }
+    let inside = rect.x >= 0
+        && rect.y >= 0
+        && rect.width > 0
+        && rect.height > 0
+        && rect.x as u64 + rect.width as u64 <= image.width as u64
+        && rect.y as u64 + rect.height as u64 <= image.height as u64;
+    if !inside {
+        return Err(CaptureError::new(
+            ErrorCode::InvalidArgument,
+            format!(
+                "Rectangle {:?} is empty or not inside the {}x{} image",
+                rect, image.width, image.height
+            ),
+        ));
+    }
+
+    let stride = image.stride;
+    if stride < image.width as usize * BYTES_PER_PIXEL {
+        return Err(CaptureError::new(
+            ErrorCode::InvalidArgument,
+            format!("Image stride {} is too small for its width", stride),
+        ));
+    }
+    let len = buffer::retain(image.data)?;
+    let offset = rect.y as usize * stride + rect.x as usize * BYTES_PER_PIXEL;
+    let end = offset + (rect.height as usize - 1) * stride + rect.width as usize * BYTES_PER_PIXEL;
+    if end > len {
+        let _ = buffer::release(image.data);
+        return Err(CaptureError::new(
+            ErrorCode::InvalidArgument,
+            "Image dimensions don't match its buffer",
+        ));
+    }
+
+    let mut state = ViewState {
+        data: ViewData::Shared {
+            parent: image.data as usize,
+            offset,
+            stride,
+        },
+        width: rect.width as usize,
+        height: rect.height as usize,
+    };
+    let view = state.ffi_view(0);
+    let handle = VIEWS.insert(ImageView {
+        state: Mutex::new(state),
+    });
+    Ok(CapturedImageView { handle, ..view })
+}
+
+/// Creates a view of a rectangle inside a captured image without copying any pixels.
+/// The view keeps the parent's buffer alive, so the parent may be freed first.
+/// The caller MUST call capture_free_image_view() on the returned view's handle.
+/// Returns a view with NULL data and handle 0 if the image is empty or the rectangle doesn't fit.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_image_view(image: CapturedImage, rect: CaptureRect) -> CapturedImageView {
+    match create(&image, rect) {
+        Ok(view) => view,
+        Err(err) => {
+            set_last_error(err);
+            CapturedImageView::empty()
+        }
+    }
+}
+
+/// Makes a view writable, copying its pixels out of the parent buffer the first time, and updates
+/// `view.data` and `view.stride` to the private copy. Later calls are free.
+/// Returns CAPTURE_OK, or an error code if `view` is NULL or its handle isn't a live view.
+///
+/// # Safety
+/// `view` must be NULL or point to a valid CapturedImageView.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_image_view_make_mut(view: *mut CapturedImageView) -> c_int {
+    let Some(view) = (unsafe { view.as_mut() }) else {
+        set_last_error(CaptureError::new(
+            ErrorCode::InvalidArgument,
+            "View pointer is NULL",
+        ));
+        return ErrorCode::InvalidArgument as c_int;
+    };
+    let shared = match VIEWS.get(view.handle) {
+        Ok(shared) => shared,
+        Err(err) => {
+            let code = err.code as c_int;
+            set_last_error(err);
+            return code;
+        }
+    };
+
+    let mut state = shared.state.lock().unwrap_or_else(|e| e.into_inner());
+    if let ViewData::Shared { .. } = state.data {
+        let owned = ViewData::Owned(state.packed_copy().into_boxed_slice());
+        // Swap in the copy, then drop our reference on the parent.
+        if let ViewData::Shared { parent, .. } = std::mem::replace(&mut state.data, owned) {
+            let _ = buffer::release(parent as *const u8);
+        }
+    }
+    *view = state.ffi_view(view.handle);
+    ErrorCode::Ok as c_int
+}
+
+/// Copies a view into a new, independent CapturedImage with tightly packed rows.
+/// The caller MUST call capture_free_image() on the returned struct to free the data buffer.
+/// Returns a struct with NULL data pointer if `view` isn't a live view.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_image_view_to_image(view: CaptureHandle) -> CapturedImage {
+    match VIEWS.get(view) {
+        Ok(view) => {
+            let mut state = view.state.lock().unwrap_or_else(|e| e.into_inner());
+            let (width, height) = (state.width as u32, state.height as u32);
+            match RgbaImage::from_raw(width, height, state.packed_copy()) {
+                Some(image) => CapturedImage::from_rgba(image),
+                None => CapturedImage::empty(),
+            }
+        }
+        Err(err) => {
+            set_last_error(err);
+            CapturedImage::empty()
+        }
+    }
+}
+
+/// Frees a view and drops its reference on the parent image's buffer.
+/// Returns CAPTURE_OK, or CAPTURE_ERROR_INVALID_HANDLE if `view` isn't a live view.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_free_image_view(view: CaptureHandle) -> c_int {
+    match VIEWS.remove(view) {
+        Ok(_) => ErrorCode::Ok as c_int,
+        Err(err) => {
+            let code = err.code as c_int;
+            set_last_error(err);
+            code
+        }
+    }
+}
@@ -0,0 +1,207 @@
+// capture-ffi/src/watch.rs
+//! Watching a screen region for changes without streaming whole frames to the host.
+
+use crate::{
+    CaptureError, CaptureRect, ErrorCode, UserData,
+    backend::{self, Monitor},
+    cancel, capture_background_frame,
+    event::{self, EventType},
+    handle::{CaptureHandle, HandleType, Registry},
+    poll::FrameSink,
+    priority, set_last_error,
+    stream::FrameCallback,
+    usage,
+    worker::{Task, Worker},
+};
+use image::{RgbaImage, imageops};
+use libc::{c_int, c_uint, c_void};
+use std::time::Duration;
+
+/// Shortest supported polling interval.
+const MIN_INTERVAL_MS: c_uint = 10;
+
+/// A running region watch, created by capture_region_watch().
+struct RegionWatch {
+    frames: FrameSink,
+    worker: Worker,
+}
+
+impl Task for RegionWatch {
+    fn worker(&self) -> &Worker {
+        &self.worker
+    }
+}
+
+static WATCHES: Registry<RegionWatch> = Registry::new(HandleType::RegionWatch);
+
+/// The region in the monitor's captured pixels. Backends may capture HiDPI monitors at more
+/// pixels than their logical size, so the rectangle is scaled to the frame.
+fn crop(monitor: &Monitor, image: &RgbaImage, rect: CaptureRect) -> RgbaImage {
+    let scale_x = image.width() as f64 / monitor.width().max(1) as f64;
+    let scale_y = image.height() as f64 / monitor.height().max(1) as f64;
+    let x = ((rect.x - monitor.x()) as f64 * scale_x) as u32;
+    let y = ((rect.y - monitor.y()) as f64 * scale_y) as u32;
+    let width = ((rect.width as f64 * scale_x).round() as u32).max(1);
+    let height = ((rect.height as f64 * scale_y).round() as u32).max(1);
+    imageops::crop_imm(image, x, y, width, height).to_image()
+}
+
+/// Whether more than `threshold` (a fraction of all pixels) differ between two crops.
+fn changed(previous: &RgbaImage, current: &RgbaImage, threshold: f64) -> bool {
+    if previous.dimensions() != current.dimensions() {
+        return true;
+    }
+    let differing = previous
+        .pixels()
+        .zip(current.pixels())
+        .filter(|(a, b)| a != b)
+        .count();
+    let total = (current.width() as u64 * current.height() as u64).max(1);
+    differing > 0 && differing as f64 / total as f64 > threshold
+}
+
+/// Finds the monitor fully containing `rect`, in desktop coordinates.
+fn monitor_for(rect: CaptureRect) -> Result<Monitor, CaptureError> {
+    let invalid = |message: String| CaptureError::new(ErrorCode::InvalidArgument, message);
+    if rect.width == 0 || rect.height == 0 {
+        return Err(invalid(format!("Rectangle {:?} is empty", rect)));
+    }
+    let monitor = Monitor::from_point(rect.x, rect.y)
+        .map_err(|e| CaptureError::from(e).context("Error finding the monitor of the region"))?;
+    let inside = rect.x as i64 + rect.width as i64 <= monitor.x() as i64 + monitor.width() as i64
+        && rect.y as i64 + rect.height as i64 <= monitor.y() as i64 + monitor.height() as i64;
+    if !inside {
+        return Err(invalid(format!(
+            "Rectangle {:?} is not inside a single monitor",
+            rect
+        )));
+    }
+    Ok(monitor)
+}
+
+fn start(
+    rect: CaptureRect,
+    interval_ms: c_uint,
+    threshold: f64,
+    on_change: FrameCallback,
+    user_data: UserData,
+    cancel: CaptureHandle,
+) -> Result<CaptureHandle, CaptureError> {
+    backend::require_threads()?;
+    let cancel = cancel::resolve(cancel)?;
+    let frames = FrameSink::new(on_change, user_data);
+    if !(0.0..1.0).contains(&threshold) {
+        return Err(CaptureError::new(
+            ErrorCode::InvalidArgument,
+            format!(
+                "Threshold must be at least 0 and below 1, got {}",
+                threshold
+            ),
+        ));
+    }
+    let monitor = monitor_for(rect)?;
+    let interval = Duration::from_millis(interval_ms.max(MIN_INTERVAL_MS) as u64);
+
+    // Capture the baseline up front so a bad region fails here rather than in the thread.
+    let first = {
+        let _usage = frames.enter();
+        let image = capture_background_frame(&monitor)?;
+        usage::converting(|| crop(&monitor, &image, rect))
+    };
+
+    let watch = RegionWatch {
+        frames: frames.clone(),
+        worker: Worker::new(cancel.as_deref()),
+    };
+    Ok(WATCHES.start(watch, move |handle, stop| {
+        let _usage = frames.enter();
+        let deliver = |image: &RgbaImage| frames.deliver(image.clone());
+        deliver(&first);
+        let mut previous = first;
+
+        while !stop.sleep(interval) {
+            priority::yield_to_interactive(interval);
+            match capture_background_frame(&monitor) {
+                Ok(image) => {
+                    let (current, changed) = usage::converting(|| {
+                        let current = crop(&monitor, &image, rect);
+                        let changed = changed(&previous, &current, threshold);
+                        (current, changed)
+                    });
+                    if changed {
+                        deliver(&current);
+                        previous = current;
+                    }
+                }
+                Err(e) => {
+                    let err_msg = format!("Error capturing watched region: {}", e);
+                    event::emit(EventType::WatchError, handle, Some(&err_msg));
+                }
+            }
+        }
+        frames.close();
+    }))
+}
+
+/// Watches a rectangle of the screen, given in desktop coordinates and lying within one monitor,
+/// and calls `on_change` with an image of just that region whenever it changes: once right away
+/// with the current contents, then each time more than `threshold` of its pixels (a fraction from
+/// 0, meaning any change, up to but excluding 1) differ from the last delivered image.
+/// The region is checked every `interval_ms` milliseconds (at least 10) on a background thread,
+/// which also runs the callback; the callback owns each image and MUST call capture_free_image()
+/// on it. With a NULL `on_change`, images are instead kept for capture_stream_poll_frame().
+/// Capture failures are reported as CAPTURE_EVENT_WATCH_ERROR events.
+/// `cancel` is a token from capture_cancel_token_new() that also stops the watch, or 0.
+/// Returns a watch handle for capture_region_watch_stop(), or 0 on error.
+///
+/// # Safety
+/// `on_change` must stay valid, and safe to call from another thread, until
+/// capture_region_watch_stop() returns.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn capture_region_watch(
+    rect: CaptureRect,
+    interval_ms: c_uint,
+    threshold: f64,
+    on_change: FrameCallback,
+    user_data: *mut c_void,
+    cancel: CaptureHandle,
+) -> CaptureHandle {
+    match start(
+        rect,
+        interval_ms,
+        threshold,
+        on_change,
+        UserData(user_data),
+        cancel,
+    ) {
+        Ok(handle) => handle,
+        Err(err) => {
+            let err = err.context("Failed to watch region");
+            eprintln!("{}", err);
+            set_last_error(err);
+            0
+        }
+    }
+}
+
+/// Where a region watch delivers its frames.
+pub(crate) fn frame_sink(watch: CaptureHandle) -> Result<FrameSink, CaptureError> {
+    Ok(WATCHES.get(watch)?.frames.clone())
+}
+
+/// Stops a region watch, waits for its thread to finish and invalidates the handle.
+/// No callbacks run after this returns.
+/// Returns CAPTURE_OK, CAPTURE_ERROR_INVALID_HANDLE if `watch` isn't a live watch, or
+/// CAPTURE_ERROR_INVALID_ARGUMENT, leaving the watch running, if called from the watch's own
+/// callbacks; cancel the watch's token from there instead.
+#[unsafe(no_mangle)]
+pub extern "C" fn capture_region_watch_stop(watch: CaptureHandle) -> c_int {
+    match WATCHES.stop(watch) {
+        Ok(()) => ErrorCode::Ok as c_int,
+        Err(err) => {
+            let code = err.code as c_int;
+            set_last_error(err);
+            code
+        }
+    }
+}
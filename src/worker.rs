@@ -0,0 +1,90 @@
+// capture-ffi/src/worker.rs
+//! Background threads owned by a handle. Streams, region watches, composite recordings and
+//! heatmaps each run one from the moment their handle is issued until it is stopped.
+
+use crate::{
+    CaptureError, ErrorCode,
+    cancel::CancelToken,
+    handle::{CaptureHandle, Registry},
+};
+use std::{
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+};
+
+/// The thread behind a handle and the token that stops it.
+pub(crate) struct Worker {
+    stop: Arc<CancelToken>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Worker {
+    /// A worker that is also stopped when `parent` (the caller's CancelToken, if any) is
+    /// cancelled. Its thread starts with Registry::start().
+    pub(crate) fn new(parent: Option<&CancelToken>) -> Self {
+        Worker {
+            stop: CancelToken::child_of(parent),
+            thread: Mutex::new(None),
+        }
+    }
+}
+
+/// An object that owns a Worker.
+pub(crate) trait Task: Send + Sync + 'static {
+    fn worker(&self) -> &Worker;
+}
+
+impl<T: Task> Registry<T> {
+    /// Stores `value` and runs `run` on its worker's thread with the new handle and the token
+    /// that stops it, which `run` should check until it is cancelled.
+    pub(crate) fn start(
+        &self,
+        value: T,
+        run: impl FnOnce(CaptureHandle, &CancelToken) + Send + 'static,
+    ) -> CaptureHandle {
+        let stop = value.worker().stop.clone();
+        let handle = self.insert(value);
+        let thread = thread::spawn(move || run(handle, &stop));
+        // The task can't have been stopped yet: the caller doesn't know its handle.
+        if let Ok(value) = self.get(handle) {
+            *value
+                .worker()
+                .thread
+                .lock()
+                .unwrap_or_else(|e| e.into_inner()) = Some(thread);
+        }
+        handle
+    }
+
+    /// Invalidates a handle, stops its worker and waits for the thread to finish. Fails without
+    /// stopping anything when called on that thread, e.g. from one of its callbacks, since it
+    /// can't wait for itself.
+    pub(crate) fn stop(&self, handle: CaptureHandle) -> Result<(), CaptureError> {
+        let on_worker = self
+            .get(handle)?
+            .worker()
+            .thread
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_ref()
+            .is_some_and(|thread| thread.thread().id() == thread::current().id());
+        if on_worker {
+            return Err(CaptureError::new(
+                ErrorCode::InvalidArgument,
+                "Can't stop a task from its own thread or callbacks",
+            ));
+        }
+        let value = self.remove(handle)?;
+        let worker = value.worker();
+        worker.stop.cancel();
+        let thread = worker
+            .thread
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+        if let Some(thread) = thread {
+            let _ = thread.join();
+        }
+        Ok(())
+    }
+}